@@ -0,0 +1,86 @@
+/*
+Timings for the obj parser, run with cargo bench --bench obj_parse.
+Covers the small art/world.obj, every frame of the dance sequences, and a generated grid big enough
+to compare parsing on one thread with parsing on all of them.
+ */
+
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use bvparty::nocmp::obj_parser::{Mesh, NormalSource};
+
+//runs f iterations times and prints the fastest and the mean run
+fn bench(name: &str, iterations: u32, mut f: impl FnMut()) {
+    let mut fastest = Duration::MAX;
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f();
+        let elapsed = start.elapsed();
+        fastest = fastest.min(elapsed);
+        total += elapsed;
+    }
+    println!("{name:<40} fastest {:>9.3} ms   mean {:>9.3} ms", fastest.as_secs_f64() * 1000.0, (total / iterations).as_secs_f64() * 1000.0);
+}
+
+fn obj_files(directory: &str) -> Vec<String> {
+    let mut files: Vec<String> = std::fs::read_dir(directory).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "obj"))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    files.sort();
+    files
+}
+
+//a size by size grid of quads with uvs and normals, in a smoothing group
+fn grid_obj(size: usize) -> String {
+    let mut obj = String::with_capacity(size * size * 100);
+    for y in 0..=size {
+        for x in 0..=size {
+            let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+            writeln!(obj, "v {u} {} {v}", (u * 10.0).sin() * (v * 7.0).cos() * 0.1).unwrap();
+            writeln!(obj, "vt {u} {v}").unwrap();
+            writeln!(obj, "vn 0 1 0").unwrap();
+        }
+    }
+    writeln!(obj, "s 1").unwrap();
+    for y in 0..size {
+        for x in 0..size {
+            let corner = |x: usize, y: usize| y * (size + 1) + x + 1;
+            let (a, b, c, d) = (corner(x, y), corner(x + 1, y), corner(x + 1, y + 1), corner(x, y + 1));
+            writeln!(obj, "f {a}/{a}/{a} {d}/{d}/{d} {c}/{c}/{c} {b}/{b}/{b}").unwrap();
+        }
+    }
+    obj
+}
+
+fn main() {
+    bench("art/world.obj", 200, || {
+        Mesh::parse_from_file("art/world.obj").unwrap();
+    });
+
+    for directory in ["art/dance_frames", "art/2dance_frames"] {
+        let frames = obj_files(directory);
+        bench(&format!("{directory} ({} frames)", frames.len()), 5, || {
+            for frame in &frames {
+                Mesh::parse_from_file(frame).unwrap();
+            }
+        });
+        bench(&format!("{directory} generated normals"), 5, || {
+            for frame in &frames {
+                Mesh::parse_from_file_with_normals(frame, NormalSource::Generated).unwrap();
+            }
+        });
+    }
+
+    let grid = grid_obj(400);
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let label = format!("grid {} MB", grid.len() / (1024 * 1024));
+    bench(&format!("{label}, 1 thread"), 5, || {
+        Mesh::parse_from_str(&grid, Path::new(""), NormalSource::File, 1).unwrap();
+    });
+    bench(&format!("{label}, {threads} threads"), 5, || {
+        Mesh::parse_from_str(&grid, Path::new(""), NormalSource::File, threads).unwrap();
+    });
+}
@@ -25,6 +25,39 @@ use winit::keyboard::{KeyCode, PhysicalKey};
 
 use winit::window::{Fullscreen, Window};
 
+//samples per pixel for the meshes, clamped to what the adapter supports (1, 2, 4 or 8)
+const REQUESTED_MSAA_SAMPLES: u32 = 4;
+
+//Where render_scene draws the 3D part of the frame
+enum SceneTarget {
+    PostChain,
+    TransitionFrom,
+    TransitionTo,
+}
+
+//The feedback from buffer_b on binding 0 and the keyboard on binding 2
+fn buffer_a_channels(device: &wgpu::Device, buffer_b: &nocmp::shadertoy_buffer::ShaderToylikeBuffer, keyboard: &nocmp::keyboard_texture::KeyboardTexture)
+    -> anyhow::Result<(wgpu::BindGroupLayout,wgpu::BindGroup)> {
+    nocmp::texture::setup_texture_stage(device,&[buffer_b.get_target_rtt(),keyboard.texture()],Some("buffer_a channels"))
+}
+
+//3d text from the alphabet glyphs, the layouts are made once from its font
+struct DemoText {
+    text: nocmp::text3d::Text3d,
+    //dropping in over the greets, L steps through the alignments of it
+    greetings: nocmp::text3d::TextLayout,
+    greetings_align: nocmp::text3d::TextAlign,
+    //riding the heart curve over the creds
+    credits: nocmp::text3d::TextLayout,
+    credits_animations: Vec<nocmp::text3d::LetterAnimation>,
+}
+
+//Every letter of the credits heading turns around its own middle, one turn per 4 beats
+fn spin_letter(glyph: &nocmp::text3d::PlacedGlyph, beat: f32) -> cgmath::Matrix4<f32> {
+    let angle = cgmath::Rad((beat / 4.0 + glyph.index as f32 * 0.1) * std::f32::consts::TAU);
+    cgmath::Matrix4::from_translation(glyph.center) * cgmath::Matrix4::from_angle_y(angle) * cgmath::Matrix4::from_translation(-glyph.center)
+}
+
 struct State<'demo_lifetime> {
     surface: wgpu::Surface<'demo_lifetime>,
     device: wgpu::Device,
@@ -35,27 +68,49 @@ struct State<'demo_lifetime> {
     //it gets dropped after it(the surface), because
     //the surface contains unsafe references to the windows resources
     window: &'demo_lifetime Window,
-    dif_tex_1: nocmp::texture::Texture,
     dif_tex_2: nocmp::texture::Texture,
-    rtt_tex: nocmp::texture::Texture,
-    depth_texture : nocmp::texture::Texture,
-    texture_bind_group_layout: wgpu::BindGroupLayout,
-    texture_bind_group: wgpu::BindGroup,
+    //multisampled color and depth for the geometry passes in render_scene
+    scene_msaa : nocmp::msaa::MsaaTargets,
     fs_quad: nocmp::shadertoy_buffer::ShaderToylikeBuffer,
+    fs_quad_array: nocmp::shadertoy_buffer::ShaderToylikeBuffer,
     buffer_a: nocmp::shadertoy_buffer::ShaderToylikeBuffer,
     buffer_b: nocmp::shadertoy_buffer::ShaderToylikeBuffer,
+    //what buffer_a reads, the target of buffer_b and the keyboard, rebuilt when buffer_b's target is
+    buffer_a_channels: wgpu::BindGroup,
     buffer_screen: nocmp::shadertoy_buffer::ShaderToylikeBuffer,
-    obj_mesh_test: nocmp::obj_mesh_test::ObjMeshTest,
-    spline_test: nocmp::spline_test::SplineTest,
+    post_chain: nocmp::post_process::PostChain,
+    transitions: nocmp::transition::TransitionRenderer,
+    //the custom transition the flipbook sections switch with
+    overlay_transition: nocmp::transition::TransitionKind,
+    color_grading: nocmp::color_grading::ColorGrading,
+    beat_clock: nocmp::beat_clock::BeatClock,
+    //every mesh pipeline, the static meshes and the text draw through it, the dancers with their own group 2
+    mesh_renderer: nocmp::mesh_renderer::MeshRenderer,
+    mesh_pipeline: nocmp::mesh_renderer::PipelineKey,
+    world_mesh: nocmp::mesh_renderer::GpuMesh,
+    world_model_matrix: cgmath::Matrix4<f32>,
     toylike_uniforms: nocmp::shadertoy_buffer::ShaderToyUniforms,
+    keyboard: nocmp::keyboard_texture::KeyboardTexture,
     camera: nocmp::camera::Camera,
     camera_controller: nocmp::camera::CameraController,
     camera_uniform : nocmp::camera::CameraUniform,
     camera_uniform_buffer : wgpu::Buffer,
-    dancer : Vec<nocmp::obj_mesh_test::ObjMeshTest>,
-    dancer_frame : usize,
+    lights: nocmp::lights::Lights,
+    //the spot light on the dancer, keyed on the beats of the greets part
+    spot_track: nocmp::lights::LightTrack,
+    dancer : nocmp::animated_mesh::AnimatedMesh,
+    //art/dancer.glb when it is there, drawn instead of the obj frames
+    skinned_dancer: Option<(nocmp::gltf_loader::GltfScene, nocmp::skinned_mesh::SkinnedMesh)>,
+    //None if the alphabet has no glyphs we recognize
+    demo_text: Option<DemoText>,
+    //what frustum culling skipped in the last frame, C prints it
+    cull_stats: nocmp::bounds::CullStats,
     meshes : HashMap<String, HashMap< String, nocmp::obj_parser::Mesh> >,
     textures: HashMap<String, wgpu::BindGroup>,
+    message_frames: nocmp::texture_array::TextureArray,
+    greet_frames: nocmp::texture_array::TextureArray,
+    refreng_frames: nocmp::texture_array::TextureArray,
+    cred_frames: nocmp::texture_array::TextureArray,
 
 }
 
@@ -71,14 +126,7 @@ impl<'demo_lifetime> State<'demo_lifetime> {
         let elapsed = song_time.elapsed();
         let elapsed_millis = elapsed.as_millis();
         let is_beat = elapsed_millis % 469 <= 55;
-        let is_beat_x2 = elapsed_millis % (469/4) <= 60;
 
-        if is_beat_x2{
-            self.dancer_frame += 1;
-            if self.dancer_frame >= self.dancer.len() {
-                self.dancer_frame = 0;
-            }
-        }
         if  is_beat {
 
             self.toylike_uniforms.uniforms.iMouse[0] = self.window.inner_size().width as f32 * 0.5_f32;
@@ -134,9 +182,14 @@ impl<'demo_lifetime> State<'demo_lifetime> {
         ).await.unwrap();
 
         //let us create the device and queue
+        //FLOAT32_FILTERABLE lets Rgba32Float render targets be sampled with linear filtering where supported
+        //TEXTURE_COMPRESSION_BC lets KTX2/DDS textures stay compressed on the gpu, without it they are decoded on the cpu
+        //TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES allows msaa sample counts other than 1 and 4
+        let optional_features = wgpu::Features::FLOAT32_FILTERABLE | wgpu::Features::TEXTURE_COMPRESSION_BC
+            | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
         let(device,queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
+                required_features: adapter.features() & optional_features,
                 required_limits: if cfg!(target_arch = "wasm32"){
                     wgpu::Limits::downlevel_webgl2_defaults()
                 } else {
@@ -148,61 +201,33 @@ impl<'demo_lifetime> State<'demo_lifetime> {
             None,
         ).await.unwrap();
 
-        let dif_tex_1= nocmp::texture::Texture::from_bytes(&device,&queue,include_bytes!("../art/logo.png"),"testing").unwrap();
         let dif_tex_2= nocmp::texture::Texture::from_bytes(&device,&queue,include_bytes!("diffuse.png"),"testing imagetest").unwrap();
 
-        let (texture_bind_group_layout,texture_bind_group) = nocmp::texture::setup_texture_stage(&device, &[&dif_tex_1], Some("Just one texture")).unwrap();
         let mut textures = HashMap::new();
 
         //Loading up all textures
 
 
-        let mut message_frames = 11;
-        while message_frames >= 0{
-
-            let path : String = format!("art/message/frame_{message_frames}.png");
-            let (_,txbg) = nocmp::texture::setup_texture_stage(&device, &[&nocmp::texture::Texture::from_path(&device,&queue,
-                                                                                                               path.as_str(),"fingers crossed").unwrap()
-            ], Some("Just one texture")).unwrap();
-            textures.insert(format!("frame_{message_frames}").parse().unwrap(),txbg);
-            message_frames-=1;
-        }
-
-        let mut greet_frames= 14;
-        while greet_frames >= 0{
-
-            let path : String = format!("art/greets/greets_{greet_frames}.png");
-            let (_,txbg) = nocmp::texture::setup_texture_stage(&device, &[&nocmp::texture::Texture::from_path(&device,&queue,
-                                                                                                              path.as_str(),"fingers crossed").unwrap()
-            ], Some("Just one texture")).unwrap();
-            textures.insert(format!("greet_{greet_frames}").parse().unwrap(),txbg);
-            greet_frames-=1;
-        }
-
-        let mut refreng_frames= 3;
-        while refreng_frames >= 0{
-
-            let path : String = format!("art/refrence/refreng_{refreng_frames}.png");
-            let (_,txbg) = nocmp::texture::setup_texture_stage(&device, &[&nocmp::texture::Texture::from_path(&device,&queue,
-                                                                                                              path.as_str(),"fingers crossed").unwrap()
-            ], Some("Just one texture")).unwrap();
-            textures.insert(format!("refreng_{refreng_frames}").parse().unwrap(),txbg);
-            refreng_frames-=1;
-        }
-
-        let mut cred_frames= 6;
-        while cred_frames >= 0{
-
-            let path : String = format!("art/creds/creds_{cred_frames}.png");
-            let (_,txbg) = nocmp::texture::setup_texture_stage(&device, &[&nocmp::texture::Texture::from_path(&device,&queue,
-                                                                                                              path.as_str(),"fingers crossed").unwrap()
-            ], Some("Just one texture")).unwrap();
-            textures.insert(format!("creds_{cred_frames}").parse().unwrap(),txbg);
-            cred_frames-=1;
-        }
-
-        let (_,txbg) = nocmp::texture::setup_texture_stage(&device, &[&nocmp::texture::Texture::from_bytes(&device,&queue,
-                                                                                                           include_bytes!("../art/logo.png"),"fingers crossed").unwrap()
+        //Each flipbook is one texture array, the frame is picked with set_frame instead of rebinding
+        let frame_array_layout = nocmp::texture_array::TextureArray::create_bind_group_layout(&device);
+        let message_paths : Vec<String> = (0..=11).map(|frame| format!("art/message/frame_{frame}.png")).collect();
+        let message_frames = nocmp::texture_array::TextureArray::from_paths(&device,&queue,&frame_array_layout,&message_paths,"message frames").unwrap();
+        let greet_paths : Vec<String> = (0..=14).map(|frame| format!("art/greets/greets_{frame}.png")).collect();
+        let greet_frames = nocmp::texture_array::TextureArray::from_paths(&device,&queue,&frame_array_layout,&greet_paths,"greet frames").unwrap();
+        let refreng_paths : Vec<String> = (0..=3).map(|frame| format!("art/refrence/refreng_{frame}.png")).collect();
+        let refreng_frames = nocmp::texture_array::TextureArray::from_paths(&device,&queue,&frame_array_layout,&refreng_paths,"refreng frames").unwrap();
+        let cred_paths : Vec<String> = (0..=6).map(|frame| format!("art/creds/creds_{frame}.png")).collect();
+        let cred_frames = nocmp::texture_array::TextureArray::from_paths(&device,&queue,&frame_array_layout,&cred_paths,"cred frames").unwrap();
+
+        //the logo is drawn over the linear hdr scene, so it is loaded as srgb,
+        //it is 1920x1080 and the window opens at half that, so it gets mips and trilinear filtering
+        let logo_config = nocmp::texture::TextureConfig {
+            srgb: true,
+            ..nocmp::texture::TextureConfig::mipmapped(wgpu::AddressMode::ClampToEdge, 16)
+        };
+        //its layout is the single texture layout buffer_a and fs_quad are made with
+        let (texture_bind_group_layout,txbg) = nocmp::texture::setup_texture_stage(&device, &[&nocmp::texture::Texture::from_bytes_with_config(&device,&queue,
+                                                                                                           include_bytes!("../art/logo.png"),"fingers crossed",&logo_config).unwrap()
         ], Some("Just one texture")).unwrap();
         textures.insert("logo".parse().unwrap(),txbg);
 
@@ -213,8 +238,6 @@ impl<'demo_lifetime> State<'demo_lifetime> {
             .find(|f| *f == wgpu::TextureFormat::Rgba8Unorm)
             .unwrap_or(surface_caps.formats[0]);
 
-        let rtt_tex= nocmp::texture::Texture::create_rtt_texture(1024,1024,&device,surface_format,Some("rtt_nocmp_test")).unwrap();
-
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
@@ -227,40 +250,92 @@ impl<'demo_lifetime> State<'demo_lifetime> {
         };
         surface.configure(&device,&config);
 
-        let depth_texture = nocmp::texture::Texture::create_depth_texture(&device,&config,"nocmp depth texture");
+
+        //the scene renders into the post chain's hdr target, so everything that draws the scene is built for that format
+        //the post chain ends in the grading input, and the grading pass is what writes the surface
+        let post_chain = nocmp::post_process::PostChain::new(&device,config.width,config.height,nocmp::color_grading::INPUT_FORMAT).unwrap();
+        let mut color_grading = nocmp::color_grading::ColorGrading::new(&device,&queue,config.width,config.height,config.format).unwrap();
+        //luts exported from the grading tools go in art/grading, they are crossfaded with the camera changes
+        if let Ok(entries) = std::fs::read_dir("art/grading") {
+            let mut lut_paths : Vec<String> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path().to_string_lossy().to_string())
+                .filter(|path| path.ends_with(".cube"))
+                .collect();
+            lut_paths.sort();
+            for path in lut_paths.iter().take(nocmp::color_grading::MAX_BLENDED_LUTS) {
+                if let Err(error) = color_grading.add_lut_from_path(&device,&queue,path) {
+                    println!("skipping lut {path} : {error:?}");
+                }
+            }
+        }
+        //the diffuse test texture doubles as the dissolve mask for the camera transitions
+        let mut transitions = nocmp::transition::TransitionRenderer::new(&device,config.width,config.height,nocmp::post_process::HDR_FORMAT,&dif_tex_2).unwrap();
+        let overlay_transition = transitions.add_custom(&device,include_str!("shadertoys/transition_pixelate.wgsl"),"pixelate transition");
+        let scene_config = wgpu::SurfaceConfiguration {
+            format: nocmp::post_process::HDR_FORMAT,
+            ..config.clone()
+        };
+        let msaa_sample_count = nocmp::msaa::clamp_sample_count(&adapter,&device,
+            &[scene_config.format,nocmp::texture::Texture::DEPTH_FORMAT],REQUESTED_MSAA_SAMPLES);
+        let scene_msaa = nocmp::msaa::MsaaTargets::new(&device,&scene_config,msaa_sample_count);
+        //the overlays never use their own target, so it stays in the (smaller) surface format
+        let overlay_target = nocmp::shadertoy_buffer::RenderTargetConfig {
+            format: Some(config.format),
+            ..Default::default()
+        };
 
         let toylike_uniforms = nocmp::shadertoy_buffer::ShaderToyUniforms::new(&device).unwrap();
+        //bound as a channel of buffer_a to get shadertoy like keyboard input
+        let keyboard = nocmp::keyboard_texture::KeyboardTexture::new(&device).unwrap();
 
-        let buffer_a = nocmp::shadertoy_buffer::ShaderToylikeBuffer::create(
+        //buffer_a and buffer_b feed each other every frame, 8 bit targets band and lose the slow fade
+        let feedback_target = nocmp::shadertoy_buffer::RenderTargetConfig::screen_sized(wgpu::TextureFormat::Rgba16Float,1.0);
+        //buffer_b reads buffer_a's target, which has the same single filterable texture layout as the logo
+        let buffer_b = nocmp::shadertoy_buffer::ShaderToylikeBuffer::create_with_target(
             &device,
             &toylike_uniforms,
             &texture_bind_group_layout,
-            &config,
-            wgpu::include_wgsl!("shadertoys/shader_buffer_a.wgsl")
+            &scene_config,
+            wgpu::include_wgsl!("shadertoys/shader_buffer_b.wgsl"),
+            feedback_target
         ).unwrap();
 
-        let buffer_b = nocmp::shadertoy_buffer::ShaderToylikeBuffer::create(
+        let (buffer_a_channels_layout,buffer_a_channels) = buffer_a_channels(&device,&buffer_b,&keyboard).unwrap();
+        let buffer_a = nocmp::shadertoy_buffer::ShaderToylikeBuffer::create_with_target(
             &device,
             &toylike_uniforms,
-            &texture_bind_group_layout,
-            &config,
-            wgpu::include_wgsl!("shadertoys/shader_buffer_b.wgsl")
+            &buffer_a_channels_layout,
+            &scene_config,
+            wgpu::include_wgsl!("shadertoys/shader_buffer_a.wgsl"),
+            feedback_target
         ).unwrap();
 
-        let buffer_screen = nocmp::shadertoy_buffer::ShaderToylikeBuffer::create(
+        let buffer_screen = nocmp::shadertoy_buffer::ShaderToylikeBuffer::create_with_target(
             &device,
             &toylike_uniforms,
-            &texture_bind_group_layout,
-            &config,
-            wgpu::include_wgsl!("shadertoys/shader_buffer_screen.wgsl")
+            buffer_a.get_target_rtt_bindgroup_layout(),
+            &scene_config,
+            wgpu::include_wgsl!("shadertoys/shader_buffer_screen.wgsl"),
+            overlay_target
         ).unwrap();
 
-        let fs_quad = nocmp::shadertoy_buffer::ShaderToylikeBuffer::create(
+        let fs_quad = nocmp::shadertoy_buffer::ShaderToylikeBuffer::create_with_target(
             &device,
             &toylike_uniforms,
             &texture_bind_group_layout,
-            &config,
-            wgpu::include_wgsl!("shadertoys/fs_quad.wgsl")
+            &scene_config,
+            wgpu::include_wgsl!("shadertoys/fs_quad.wgsl"),
+            overlay_target
+        ).unwrap();
+
+        let fs_quad_array = nocmp::shadertoy_buffer::ShaderToylikeBuffer::create_with_target(
+            &device,
+            &toylike_uniforms,
+            &frame_array_layout,
+            &scene_config,
+            wgpu::include_wgsl!("shadertoys/fs_quad_array.wgsl"),
+            overlay_target
         ).unwrap();
 
 
@@ -281,71 +356,136 @@ impl<'demo_lifetime> State<'demo_lifetime> {
         let mut camera_uniform = nocmp::camera::CameraUniform::new();
         let camera_uniform_buffer = camera_uniform.create_buffer(&device).unwrap();
 
-        let spline_test = nocmp::spline_test::SplineTest::create(
-            &device,
-            &toylike_uniforms,
-            &texture_bind_group_layout,
-            &config,
-            wgpu::include_wgsl!("shadertoys/test.wgsl"),
-            &camera_uniform_buffer,
-        ).unwrap();
+        //a warm key and a cool fill from where the two suns of the old shading were,
+        //a point light circling the dancer and a spot on it, the last two are animated in update()
+        let mut lights = nocmp::lights::Lights::new(&device);
+        lights.ambient = [0.08, 0.08, 0.1];
+        lights.lights.push(nocmp::lights::Light::directional(cgmath::Vector3::new(0.57, 0.57, -0.57)).with_color([1.0, 0.9, 0.75]).with_intensity(1.2));
+        lights.lights.push(nocmp::lights::Light::directional(cgmath::Vector3::new(0.57, -0.57, -0.57)).with_color([0.5, 0.6, 1.0]).with_intensity(0.4));
+        lights.lights.push(nocmp::lights::Light::point(cgmath::Vector3::new(0.0, 0.5, 1.0)).with_range(4.0));
+        lights.lights.push(nocmp::lights::Light::spot(cgmath::Vector3::new(0.0, 0.0, 0.0), cgmath::Vector3::new(0.0, -1.0, 0.0)).with_intensity(0.0));
+        lights.push_lights(&queue);
+        let spot = nocmp::lights::Light::spot(cgmath::Vector3::new(0.0, 3.0, 0.5), cgmath::Vector3::new(0.0, -1.0, -0.15))
+            .with_color([1.0, 0.95, 0.9])
+            .with_range(8.0)
+            .with_cone(0.2, 0.35);
+        let spot_track = nocmp::lights::LightTrack::new()
+            .with_key(60.0, spot.with_intensity(0.0))
+            .with_key(64.0, spot.with_intensity(12.0))
+            .with_key(72.0, spot.with_intensity(12.0).with_cone(0.3, 0.5))
+            .with_key(80.0, spot.with_intensity(0.0));
 
         let mut meshes: HashMap<String, HashMap< String, nocmp::obj_parser::Mesh> > = HashMap::new();
-        meshes.insert("world".parse().unwrap(), nocmp::obj_parser::Mesh::parse_from_file("art/world.obj").unwrap());
-        meshes.insert("alphabet".parse().unwrap(), nocmp::obj_parser::Mesh::parse_from_file("art/alphabet/alphabet_.obj").unwrap());
+        meshes.insert("world".parse().unwrap(), nocmp::mesh_cache::load_obj("art/world.obj").unwrap());
+        meshes.insert("alphabet".parse().unwrap(), nocmp::mesh_cache::load_obj("art/alphabet/alphabet_.obj").unwrap());
 
-        let obj_mesh_test = nocmp::obj_mesh_test::ObjMeshTest::create(
+        let mut mesh_renderer = nocmp::mesh_renderer::MeshRenderer::new(
             &device,
+            &queue,
             &toylike_uniforms,
-            &texture_bind_group_layout,
-            &config,
-            wgpu::include_wgsl!("shadertoys/obj_test.wgsl"),
+            &scene_config,
+            msaa_sample_count,
             &camera_uniform_buffer,
+            lights.uniform_buffer(),
+        ).unwrap();
+        let mesh_pipeline = nocmp::mesh_renderer::PipelineKey::new(
+            mesh_renderer.shader(&device, wgpu::include_wgsl!("shadertoys/obj_test.wgsl")),
+            nocmp::mesh_renderer::VertexLayout::Mesh,
+        );
+        mesh_renderer.prepare(&device, mesh_pipeline);
+        let world_mesh = nocmp::mesh_renderer::GpuMesh::new(
+            &device,
             &queue,
+            &mesh_renderer,
             &(meshes.get_key_value("world").unwrap().1.get_key_value("World").unwrap().1)
         ).unwrap();
 
 
 
-        let mut dancer : Vec<nocmp::obj_mesh_test::ObjMeshTest> = Vec::new();
-
+        //both dance sequences play as one animation
+        let mut dancer_paths : Vec<String> = Vec::new();
         let mut dancer_frames = 334;
         while(dancer_frames < 371){
-            let path : String = format!("art/dance_frames/dance_frames0{dancer_frames}.obj");
-
-            meshes.insert(path.parse().unwrap(), nocmp::obj_parser::Mesh::parse_from_file(path.as_str()).unwrap());
-            let dance_frame_mesh= nocmp::obj_mesh_test::ObjMeshTest::create(
-                &device,
-                &toylike_uniforms,
-                &texture_bind_group_layout,
-                &config,
-                wgpu::include_wgsl!("shadertoys/obj_test.wgsl"),
-                &camera_uniform_buffer,
-                &queue,
-                &(meshes.get_key_value(&path).unwrap().1.get_key_value("Beta_Surface").unwrap().1)
-            ).unwrap();
-            dancer.push(dance_frame_mesh);
+            dancer_paths.push(format!("art/dance_frames/dance_frames0{dancer_frames}.obj"));
             dancer_frames+=1;
         }
 
         let mut dancer_frames = 157;
         while(dancer_frames <= 207){
-            let path : String = format!("art/2dance_frames/2dancer0{dancer_frames}.obj");
+            dancer_paths.push(format!("art/2dance_frames/2dancer0{dancer_frames}.obj"));
+            dancer_frames+=1;
+        }
 
-            meshes.insert(path.parse().unwrap(), nocmp::obj_parser::Mesh::parse_from_file(path.as_str()).unwrap());
-            let dance_frame_mesh= nocmp::obj_mesh_test::ObjMeshTest::create(
+        for path in &dancer_paths {
+            meshes.insert(path.clone(), nocmp::mesh_cache::load_obj(path.as_str()).unwrap());
+        }
+        let dancer_meshes : Vec<&nocmp::obj_parser::Mesh> = dancer_paths.iter()
+            .map(|path| meshes.get(path).unwrap().get("Beta_Surface").unwrap())
+            .collect();
+        let mut dancer = nocmp::animated_mesh::AnimatedMesh::create(
+            &device,
+            &queue,
+            &mut mesh_renderer,
+            wgpu::include_wgsl!("shadertoys/obj_test.wgsl"),
+            dancer_meshes.as_slice(),
+        ).unwrap();
+        //37 frames looping over 8 beats, and 51 frames going back and forth, 8 beats each way
+        let first_clip_frames = 371 - 334;
+        dancer.add_clip(nocmp::animated_mesh::AnimationClip::new("dance_frames", 0..first_clip_frames).with_beats_per_loop(8.0)).unwrap();
+        dancer.add_clip(nocmp::animated_mesh::AnimationClip::new("2dance_frames", first_clip_frames..dancer.frame_count())
+            .with_beats_per_loop(8.0)
+            .with_loop_mode(nocmp::animated_mesh::LoopMode::PingPong)).unwrap();
+        dancer.play("dance_frames", 0.0, 0.0).unwrap();
+
+        let demo_text = nocmp::text3d::Font3d::from_meshes(&meshes["alphabet"]).ok().map(|font| {
+            let greetings_align = nocmp::text3d::TextAlign::Center;
+            let greetings = font.layout("GREETINGS", greetings_align, None);
+            //right aligned the text ends at the head of the curve, and the letters trail behind it over a third of the curve
+            let credits = font.layout("CREDITS", nocmp::text3d::TextAlign::Right, None);
+            //the heart from the old spline test, about six letters high around the middle of the text
+            let font_height = font.ascent - font.descent;
+            let heart_curve = std::fs::read_to_string("art/heart_curve_2.json").ok()
+                .and_then(|json| serde_json::from_str::<Vec<nocmp::spline_curves::CurvePoint>>(&json).ok())
+                .unwrap_or_default();
+            let (min_x, max_x) = heart_curve.iter().fold((f32::MAX, f32::MIN), |(min, max), point| (min.min(point.x), max.max(point.x)));
+            let (min_y, max_y) = heart_curve.iter().fold((f32::MAX, f32::MIN), |(min, max), point| (min.min(point.y), max.max(point.y)));
+            let heart_scale = 6.0 * font_height / (max_x - min_x).max(f32::EPSILON);
+            let heart_curve = heart_curve.iter().map(|point| nocmp::spline_curves::CurvePoint {
+                x: (point.x - (min_x + max_x) * 0.5) * heart_scale,
+                y: (point.y - (min_y + max_y) * 0.5) * heart_scale,
+                z: point.z * heart_scale,
+            }).collect();
+            let credits_animations = vec![
+                nocmp::text3d::LetterAnimation::Custom(spin_letter),
+                nocmp::text3d::LetterAnimation::FollowSpline { points: heart_curve, beats_per_loop: 8.0, spacing: 0.33 / credits.width.max(f32::EPSILON) },
+            ];
+            //a fifth of a unit tall, above the dancer
+            let scale = 0.2 / font_height;
+            let mut text = nocmp::text3d::Text3d::create(
                 &device,
-                &toylike_uniforms,
-                &texture_bind_group_layout,
-                &config,
+                &queue,
+                &mut mesh_renderer,
                 wgpu::include_wgsl!("shadertoys/obj_test.wgsl"),
-                &camera_uniform_buffer,
+                &meshes["alphabet"],
+                font,
+            ).unwrap();
+            text.model_matrix = cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, 0.6, 0.0)) * cgmath::Matrix4::from_scale(scale);
+            DemoText { text, greetings, greetings_align, credits, credits_animations }
+        });
+
+        let skinned_dancer = if std::path::Path::new("art/dancer.glb").exists() {
+            let scene = nocmp::gltf_loader::GltfScene::load("art/dancer.glb").unwrap();
+            let mesh = nocmp::skinned_mesh::SkinnedMesh::create(
+                &device,
                 &queue,
-                &(meshes.get_key_value(&path).unwrap().1.get_key_value("Beta_Surface").unwrap().1)
+                &mut mesh_renderer,
+                wgpu::include_wgsl!("shadertoys/obj_test.wgsl"),
+                &scene,
             ).unwrap();
-            dancer.push(dance_frame_mesh);
-            dancer_frames+=1;
-        }
+            Some((scene, mesh))
+        } else {
+            None
+        };
 
 
 
@@ -356,27 +496,41 @@ impl<'demo_lifetime> State<'demo_lifetime> {
             config,
             size,
             window,
-            texture_bind_group,
-            texture_bind_group_layout,
-            dif_tex_1,
             dif_tex_2,
-            rtt_tex,
-            depth_texture,
+            scene_msaa,
             buffer_a,
             buffer_b,
+            buffer_a_channels,
             buffer_screen,
+            post_chain,
+            transitions,
+            overlay_transition,
+            color_grading,
+            beat_clock: nocmp::beat_clock::BeatClock::default(),
             toylike_uniforms,
-            spline_test,
-            obj_mesh_test,
+            keyboard,
+            mesh_renderer,
+            mesh_pipeline,
+            world_mesh,
+            world_model_matrix: cgmath::Matrix4::from_scale(1.0),
             camera,
             camera_controller,
             camera_uniform,
             camera_uniform_buffer,
+            lights,
+            spot_track,
             dancer,
-            dancer_frame : 0,
+            skinned_dancer,
+            demo_text,
+            cull_stats: nocmp::bounds::CullStats::default(),
             meshes,
             fs_quad,
-            textures
+            fs_quad_array,
+            textures,
+            message_frames,
+            greet_frames,
+            refreng_frames,
+            cred_frames,
         }
     }
 
@@ -384,13 +538,27 @@ impl<'demo_lifetime> State<'demo_lifetime> {
         &self.window
     }
 
+    pub fn cull_stats(&self) -> nocmp::bounds::CullStats {
+        self.cull_stats
+    }
+
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
 
-            self.depth_texture = nocmp::texture::Texture::create_depth_texture(&self.device,&self.config,"depth texture");
+            self.scene_msaa.resize(&self.device,&self.config);
+
+            //screen relative render targets follow the new size
+            for buffer in [&mut self.buffer_a,&mut self.buffer_b,&mut self.buffer_screen,&mut self.fs_quad,&mut self.fs_quad_array] {
+                buffer.resize(&self.device,new_size.width,new_size.height).unwrap();
+            }
+            let (_,buffer_a_channels) = buffer_a_channels(&self.device,&self.buffer_b,&self.keyboard).unwrap();
+            self.buffer_a_channels = buffer_a_channels;
+            self.post_chain.resize(&self.device,new_size.width,new_size.height);
+            self.transitions.resize(&self.device,new_size.width,new_size.height,&self.dif_tex_2).unwrap();
+            self.color_grading.resize(&self.device,new_size.width,new_size.height).unwrap();
 
             self.toylike_uniforms.uniforms.iResolution[0] = new_size.width as f32;
             self.toylike_uniforms.uniforms.iResolution[1] = new_size.height as f32;
@@ -400,66 +568,117 @@ impl<'demo_lifetime> State<'demo_lifetime> {
     }
 
     fn input(&mut self,event: &WindowEvent) ->bool {
-        false
+        self.keyboard.process_events(event)
     }
 
     fn update(&mut self,delta_time: instant::Duration) {
         self.toylike_uniforms.uniforms.iTime += delta_time.as_secs_f32().max(f32::MIN_POSITIVE);
         self.toylike_uniforms.push_buffer_to_gfx_card(&self.queue);
 
+        //T steps through the tonemap operators, E toggles auto exposure (starts on), L steps through the greetings alignments,
+        //C prints what culling skipped in the last frame
+        //asked before the keyboard push, that clears the pressed row
+        let tonemapper = &mut self.post_chain.tonemapper;
+        if self.keyboard.pressed(KeyCode::KeyT) {
+            tonemapper.operator = tonemapper.operator.next();
+            println!("tonemap operator: {:?}", tonemapper.operator);
+        }
+        if self.keyboard.pressed(KeyCode::KeyE) {
+            tonemapper.auto_exposure = match tonemapper.auto_exposure {
+                Some(_) => None,
+                None => Some(nocmp::tonemapping::AutoExposureSettings::default()),
+            };
+            println!("auto exposure: {}", tonemapper.auto_exposure.is_some() && tonemapper.has_auto_exposure());
+        }
+        if let (true, Some(demo_text)) = (self.keyboard.pressed(KeyCode::KeyL), &mut self.demo_text) {
+            demo_text.greetings_align = demo_text.greetings_align.next();
+            demo_text.greetings = demo_text.text.font.layout("GREETINGS", demo_text.greetings_align, None);
+            println!("greetings alignment: {:?}", demo_text.greetings_align);
+        }
+        if self.keyboard.pressed(KeyCode::KeyC) {
+            println!("culling: {}", self.cull_stats());
+        }
+        self.keyboard.push_texture_to_gfx_card(&self.queue);
 
-        //self.camera_controller.update_camera(&mut self.camera);
-        //self.camera_uniform.update_view_proj(&self.camera);
-
-        let x_sin = f32::sin(self.toylike_uniforms.uniforms.iTime*0.1) * 5.0;
-        //self.obj_mesh_test.model_matrix = cgmath::Matrix4::from_translation(cgmath::Vector3 { x: x_sin, y: f32::sin(self.toylike_uniforms.uniforms.iTime*3.0)*0.25, z: 0.0 });
-        self.obj_mesh_test.push_modelview(&self.queue);
-
-
-        self.queue.write_buffer(&self.camera_uniform_buffer,0,bytemuck::cast_slice(&[self.camera_uniform]));
-    }
-
-    fn render(&mut self) -> Result<(),wgpu::SurfaceError> {
-        let output= self.surface.get_current_texture()?;
-        let view_of_surface = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
-        });
-
-        self.buffer_a.render_to_own_buffer(self.buffer_b.get_target_rtt_bindgroup(),&self.toylike_uniforms,&mut encoder);
-        self.buffer_b.render_to_own_buffer(self.buffer_a.get_target_rtt_bindgroup(),&self.toylike_uniforms,&mut encoder);
-        self.buffer_screen.render_to_screen(&view_of_surface,self.buffer_a.get_target_rtt_bindgroup(),&self.toylike_uniforms,&mut encoder);
-
-        
-        //This renders to screen with texture_bind_group , which is our POOC scroller texture which is y = 8k, and thus very squashed without a shader with texture coordinate hacks
-        //self.buffer_screen.render_to_screen(&view_of_surface,&self.texture_bind_group,&self.toylike_uniforms,&mut encoder);
-
-        self.obj_mesh_test.render_to_screen(&view_of_surface, &self.depth_texture.view, &self.texture_bind_group, &self.toylike_uniforms, &mut encoder);
-        self.dancer.get_mut(self.dancer_frame).unwrap().render_to_screen_no_clear(&view_of_surface, &self.depth_texture.view, &self.texture_bind_group, &self.toylike_uniforms, &mut encoder);
-
+        //post effects kick on the beat
+        let time = self.toylike_uniforms.uniforms.iTime;
+        let beat_phase = self.beat_clock.beat_phase(time);
+        let kick = (1.0 - beat_phase).powf(4.0);
+        if let Some(bloom) = self.post_chain.effect_mut(nocmp::post_process::PostEffectKind::Bloom) {
+            bloom.params[1] = 0.4 + 0.6 * kick;
+        }
+        if let Some(chromatic_aberration) = self.post_chain.effect_mut(nocmp::post_process::PostEffectKind::ChromaticAberration) {
+            chromatic_aberration.params[0] = 0.002 + 0.012 * kick;
+        }
 
+        //each camera section gets the next lut, blended in over the same two beats as the camera transition
+        let lut_count = self.color_grading.lut_count();
+        if lut_count > 0 {
+            let beat = self.beat_clock.beat(time).max(0.0);
+            let section = (beat / 32.0).floor() as usize;
+            let fade = if section == 0 { 1.0 } else { ((beat - section as f32 * 32.0) / 2.0).min(1.0) };
+            let current = section % lut_count;
+            let previous = (section + lut_count - 1) % lut_count;
+            for index in 0..lut_count {
+                self.color_grading.set_weight(index,0.0);
+            }
+            self.color_grading.set_weight(previous,1.0 - fade);
+            self.color_grading.set_weight(current,self.color_grading.weight(current) + fade);
+        }
 
-        let time = self.toylike_uniforms.uniforms.iTime;
-        let time_ms = (time * 1000.0) as i32;
 
-        if(time > 0.0 && time < 7.0){
+        //self.camera_controller.update_camera(&mut self.camera);
+        //self.camera_uniform.update_view_proj(&self.camera);
 
+        //the dancer switches moves with every 16 beats, crossfading over a beat
+        let beat = self.beat_clock.beat(time).max(0.0);
+        let phrase = (beat / 16.0).floor();
+        let clip = if phrase as i32 % 2 == 0 { "dance_frames" } else { "2dance_frames" };
+        self.dancer.play(clip, phrase * 16.0, 1.0).unwrap();
+        self.dancer.update(&self.queue, beat);
+        //the first animation of the glb loops, 16 beats per loop
+        if let Some((scene, mesh)) = &self.skinned_dancer {
+            let animation = if scene.animations.is_empty() { None } else { Some(0) };
+            let time = scene.animations.first().map_or(0.0, |animation| (beat / 16.0).fract() * animation.duration);
+            mesh.update_pose(&self.queue, scene, animation, time);
+        }
 
-            let beat_decay = 350;
-            let beat_decay_longer = 650;
-            let is_beat = time_ms % 469 <= beat_decay;
-            let is_beat_half = time_ms % (469*2) <= beat_decay;
-            if(is_beat_half){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("logo").unwrap(),&self.toylike_uniforms,&mut encoder);
+        //same beats as the greets frames, a letter drops in on every beat, and the creds get the heading on the heart curve
+        if let Some(demo_text) = &mut self.demo_text {
+            let greets_start = 16.0 * 4.0;
+            let creds_start = 40.0 * 4.0;
+            if beat >= greets_start && beat < 20.0 * 4.0 {
+                let animations = [
+                    nocmp::text3d::LetterAnimation::DropIn { start_beat: greets_start, beats_per_letter: 1.0, beats_per_drop: 0.5, height: 2.0 },
+                    nocmp::text3d::LetterAnimation::Wave { amplitude: 0.15, beats_per_wave: 2.0, letters_per_wave: 4.0 },
+                ];
+                demo_text.text.update(&self.device, &self.queue, &demo_text.greetings, &animations, [1.0;4], beat);
+            } else if beat >= creds_start {
+                demo_text.text.update(&self.device, &self.queue, &demo_text.credits, &demo_text.credits_animations, [1.0;4], beat - creds_start);
+            } else {
+                demo_text.text.update(&self.device, &self.queue, &nocmp::text3d::TextLayout::default(), &[], [1.0;4], beat);
             }
         }
 
-        let number_beats = time_ms / 469;
-        let number_beats_2x = time_ms / (469*2);
-        let mut camera_change =  (number_beats / 32)%4;
+        //the point light circles the dancer once every 8 beats in the colors of the old palette, flashing on the beat
+        let orbit = beat / 8.0 * std::f32::consts::TAU;
+        let palette = |offset: f32| 0.5 + 0.5 * f32::cos(beat * 0.25 + offset);
+        let point = &mut self.lights.lights[2];
+        point.position = cgmath::Vector3::new(f32::sin(orbit) * 1.2, 0.6, f32::cos(orbit) * 1.2);
+        point.color = [palette(0.0), palette(2.0), palette(4.0)];
+        point.intensity = 0.6 + 1.4 * kick;
+        if let Some(spot) = self.spot_track.sample(beat) {
+            self.lights.lights[3] = spot;
+        }
+        self.lights.push_lights(&self.queue);
 
-        //camera_change = 3;
-        if(camera_change == 0){
+        let x_sin = f32::sin(self.toylike_uniforms.uniforms.iTime*0.1) * 5.0;
+        //self.world_model_matrix = cgmath::Matrix4::from_translation(cgmath::Vector3 { x: x_sin, y: f32::sin(self.toylike_uniforms.uniforms.iTime*3.0)*0.25, z: 0.0 });
+    }
+
+    //The four camera moves of the timeline, written to the camera uniform buffer right away
+    fn apply_camera_setup(&mut self, camera_setup: i32) {
+        if(camera_setup == 0){
 
             self.camera.eye.x =  f32::sin(self.toylike_uniforms.uniforms.iTime*0.1 + 0.7) * 0.05;
             self.camera.eye.y = f32::sin(self.toylike_uniforms.uniforms.iTime*0.1) * 0.2;
@@ -468,7 +687,7 @@ impl<'demo_lifetime> State<'demo_lifetime> {
             self.camera_uniform.update_view_proj(&self.camera);
 
         }
-        if(camera_change == 1){
+        if(camera_setup == 1){
 
             self.camera.eye.x = f32::sin(self.toylike_uniforms.uniforms.iTime*1.1) * 0.3;
             self.camera.eye.y =  f32::cos(self.toylike_uniforms.uniforms.iTime*2.1) * 0.2 + 0.3;
@@ -476,7 +695,7 @@ impl<'demo_lifetime> State<'demo_lifetime> {
             self.camera_uniform.update_view_proj(&self.camera);
 
         }
-        if(camera_change == 2){
+        if(camera_setup == 2){
 
             self.camera.eye.x = f32::sin(self.toylike_uniforms.uniforms.iTime*5.1) * 0.3;
             self.camera.eye.y =  f32::cos(self.toylike_uniforms.uniforms.iTime*2.1) * 0.2 + 0.3;
@@ -484,7 +703,7 @@ impl<'demo_lifetime> State<'demo_lifetime> {
             self.camera_uniform.update_view_proj(&self.camera);
 
         }
-        if(camera_change == 3){
+        if(camera_setup == 3){
 
             self.camera.eye.x = f32::sin(self.toylike_uniforms.uniforms.iTime*1.1) * 10.3;
             self.camera.eye.y =  f32::cos(self.toylike_uniforms.uniforms.iTime*2.1) * 0.2 + 0.3;
@@ -493,105 +712,187 @@ impl<'demo_lifetime> State<'demo_lifetime> {
 
         }
 
+        self.queue.write_buffer(&self.camera_uniform_buffer,0,bytemuck::cast_slice(&[self.camera_uniform]));
+    }
+
+    //The 3D part of the frame, drawn into the post chain directly or into one side of a transition
+    fn render_scene(&mut self, target: SceneTarget, encoder: &mut wgpu::CommandEncoder) {
+        let view = match target {
+            SceneTarget::PostChain => self.post_chain.scene_view(),
+            SceneTarget::TransitionFrom => self.transitions.from_view(),
+            SceneTarget::TransitionTo => self.transitions.to_view(),
+        };
+        self.buffer_screen.render_to_screen(view,self.buffer_a.get_target_rtt_bindgroup(),&self.toylike_uniforms,encoder);
+
+        //with the camera of this target, the skinned dancer moves too much with its pose to cull
+        let frustum = self.camera.frustum();
+        self.dancer.cull(&frustum, &mut self.cull_stats);
+        if let Some(demo_text) = &mut self.demo_text {
+            demo_text.text.cull(&self.queue, &frustum, &mut self.cull_stats);
+        }
+        let mut draws = vec![nocmp::mesh_renderer::MeshDraw::new(&self.world_mesh, self.mesh_pipeline, self.world_model_matrix)];
+        draws.retain(|draw| self.cull_stats.record(draw.visible(&frustum)));
+        if let Some(demo_text) = &self.demo_text {
+            draws.extend(demo_text.text.draws());
+        }
+
+        //the meshes draw multisampled and every pass resolves into view, the first one clears
+        let (msaa_view, resolve_target) = self.scene_msaa.color_attachment(view);
+        let depth_view = self.scene_msaa.depth_view();
+        let black = wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 };
+        self.mesh_renderer.render(&self.device, &self.queue, msaa_view, resolve_target, depth_view, Some(black), encoder, &draws);
+        match &self.skinned_dancer {
+            Some((_, mesh)) => mesh.render_to_screen_no_clear(&self.mesh_renderer, msaa_view, resolve_target, depth_view, encoder),
+            None => self.dancer.render_to_screen_no_clear(&self.mesh_renderer, msaa_view, resolve_target, depth_view, encoder),
+        }
+    }
+
+    //The logo and the flipbooks over the 3D part, number_beats picks the flipbook frames
+    fn render_overlays(&mut self, target: SceneTarget, number_beats: i32, time: f32, encoder: &mut wgpu::CommandEncoder) {
+        let scene_view = match target {
+            SceneTarget::PostChain => self.post_chain.scene_view(),
+            SceneTarget::TransitionFrom => self.transitions.from_view(),
+            SceneTarget::TransitionTo => self.transitions.to_view(),
+        };
+        let time_ms = (time * 1000.0) as i32;
+        let number_beats_2x = number_beats / 2;
+
+
+        if(time > 0.0 && time < 7.0){
+
+
+            let beat_decay = 350;
+            let beat_decay_longer = 650;
+            let is_beat = time_ms % 469 <= beat_decay;
+            let is_beat_half = time_ms % (469*2) <= beat_decay;
+            if(is_beat_half){
+                self.fs_quad.render_to_screen_without_clear(scene_view,&self.textures.get("logo").unwrap(),&self.toylike_uniforms,encoder);
+            }
+        }
+
 
         if(number_beats >= 16*4 && number_beats < 20*4){
 
             let offset = 16*4;
             let greet = (number_beats-offset) ;
             if(greet < 15){
-                let greet_frame = format!("greet_{greet}");
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get(&greet_frame).unwrap(),&self.toylike_uniforms,&mut encoder);
+                self.greet_frames.set_frame(&self.queue,greet as u32);
+                self.fs_quad_array.render_to_screen_without_clear(scene_view,self.greet_frames.bind_group(),&self.toylike_uniforms,encoder);
             }
         }
 
         if(number_beats >= 30 *4 && number_beats < 38*4){
 
             let refreng= number_beats_2x % 4;
-            let refrenge_frame= format!("refreng_{refreng}");
-            self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get(&refrenge_frame).unwrap(),&self.toylike_uniforms,&mut encoder);
+            self.refreng_frames.set_frame(&self.queue,refreng as u32);
+            self.fs_quad_array.render_to_screen_without_clear(scene_view,self.refreng_frames.bind_group(),&self.toylike_uniforms,encoder);
         }
 
-        if(number_beats >= 40 *4 && number_beats < 41*4){
+        if(number_beats >= 40 *4 ){
 
-            let creds_frame= format!("creds_0");
-            self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get(&creds_frame).unwrap(),&self.toylike_uniforms,&mut encoder);
+            //one cred per bar of 4 beats from bar 40, the last one stays up
+            let cred = (number_beats / 4 - 40).min(6);
+            self.cred_frames.set_frame(&self.queue,cred as u32);
+            self.fs_quad_array.render_to_screen_without_clear(scene_view,self.cred_frames.bind_group(),&self.toylike_uniforms,encoder);
         }
-        if(number_beats >= 41 *4 && number_beats < 42*4){
 
-            let creds_frame= format!("creds_1");
-            self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get(&creds_frame).unwrap(),&self.toylike_uniforms,&mut encoder);
-        }
-        if(number_beats >= 42 *4 && number_beats < 43*4){
-
-            let creds_frame= format!("creds_2");
-            self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get(&creds_frame).unwrap(),&self.toylike_uniforms,&mut encoder);
-        }
-        if(number_beats >= 43 *4 && number_beats < 44*4){
 
-            let creds_frame= format!("creds_3");
-            self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get(&creds_frame).unwrap(),&self.toylike_uniforms,&mut encoder);
-        }
-        if(number_beats >= 44 *4 && number_beats < 45*4){
+        if(time > 7.43 && time < 15.0){
 
-            let creds_frame= format!("creds_4");
-            self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get(&creds_frame).unwrap(),&self.toylike_uniforms,&mut encoder);
+            //start times of the message frames, synced by hand to the music
+            let message_frame_times = [7.582, 7.869, 8.777, 9.339, 10.293, 10.670, 12.161, 12.763, 13.349, 13.634, 13.832, 14.150];
+            if let Some(message_frame) = message_frame_times.iter().rposition(|start| time > *start) {
+                self.message_frames.set_frame(&self.queue,message_frame as u32);
+                self.fs_quad_array.render_to_screen_without_clear(scene_view,self.message_frames.bind_group(),&self.toylike_uniforms,encoder);
+            }
         }
-        if(number_beats >= 45 *4 && number_beats < 46*4){
+    }
 
-            let creds_frame= format!("creds_5");
-            self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get(&creds_frame).unwrap(),&self.toylike_uniforms,&mut encoder);
-        }
-        if(number_beats >= 46 *4 ){
+    fn render(&mut self) -> Result<(),wgpu::SurfaceError> {
+        let output= self.surface.get_current_texture()?;
+        let view_of_surface = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+        let time = self.toylike_uniforms.uniforms.iTime;
+        let beat = self.beat_clock.beat(time);
+        //summed over both sides of a transition
+        self.cull_stats = nocmp::bounds::CullStats::default();
+        self.mesh_renderer.begin_frame();
 
-            let creds_frame= format!("creds_6");
-            self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get(&creds_frame).unwrap(),&self.toylike_uniforms,&mut encoder);
-        }
+        let number_beats = self.beat_clock.whole_beats(time);
+        let camera_section = number_beats / 32;
+        let mut camera_change =  camera_section % 4;
 
+        //camera_change = 3;
 
-        if(time > 7.43 && time < 15.0){
+        //the feedback buffers step once per frame, even when the scene is drawn twice for a transition
+        self.buffer_a.render_to_own_buffer(&self.buffer_a_channels,&self.toylike_uniforms,&mut encoder);
+        self.buffer_b.render_to_own_buffer(self.buffer_a.get_target_rtt_bindgroup(),&self.toylike_uniforms,&mut encoder);
 
-            if(time > 7.582 && time < 7.869){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_0").unwrap(),&self.toylike_uniforms,&mut encoder);
-            }
-            if(time > 7.869 && time < 8.777){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_1").unwrap(),&self.toylike_uniforms,&mut encoder);
-            }
-            if(time > 8.777 && time < 9.339){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_2").unwrap(),&self.toylike_uniforms,&mut encoder);
-            }
-            if(time > 9.339 && time < 10.293){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_3").unwrap(),&self.toylike_uniforms,&mut encoder);
+        //every camera change blends in over a couple of beats instead of cutting
+        let transition_kinds = [
+            nocmp::transition::TransitionKind::Crossfade,
+            nocmp::transition::TransitionKind::Wipe,
+            nocmp::transition::TransitionKind::MaskDissolve,
+        ];
+        let mut camera_transition = nocmp::transition::Transition::new(
+            transition_kinds[(camera_section.max(0) % 3) as usize],
+            (camera_section * 32) as f32,
+            2.0
+        );
+        camera_transition.angle = 0.3;
+        //the refreng comes in and goes out with the custom transition. The greets and creds start on a camera change,
+        //the camera transition already draws the scene twice there, so they cut in on top of it
+        let overlay_transition = [30*4, 38*4].iter()
+            .map(|start_beat| nocmp::transition::Transition::new(self.overlay_transition, *start_beat as f32, 1.0))
+            .find_map(|transition| transition.progress(beat).map(|progress| (transition, progress)));
+        let mut overlays_drawn = false;
+        match camera_transition.progress(beat) {
+            Some(progress) if camera_section > 0 => {
+                //the camera is a single uniform buffer, so the outgoing scene gets its own submit
+                //with the old camera written before it
+                self.apply_camera_setup((camera_section - 1) % 4);
+                self.render_scene(SceneTarget::TransitionFrom,&mut encoder);
+                self.queue.submit(std::iter::once(encoder.finish()));
+                encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
+
+                self.apply_camera_setup(camera_change);
+                self.render_scene(SceneTarget::TransitionTo,&mut encoder);
+                self.transitions.render(&self.queue,&mut encoder,&camera_transition,progress,time,self.post_chain.scene_view());
             }
-            if(time > 10.293 && time < 10.670){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_4").unwrap(),&self.toylike_uniforms,&mut encoder);
-            }
-            if(time > 10.670 && time < 12.161){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_5").unwrap(),&self.toylike_uniforms,&mut encoder);
-            }
-            if(time > 12.161 && time < 12.763){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_6").unwrap(),&self.toylike_uniforms,&mut encoder);
-            }
-            if(time > 12.763 && time < 13.349){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_7").unwrap(),&self.toylike_uniforms,&mut encoder);
-            }
-            if(time > 13.349 && time < 13.634){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_8").unwrap(),&self.toylike_uniforms,&mut encoder);
-            }
-            if(time > 13.634 && time < 13.832){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_9").unwrap(),&self.toylike_uniforms,&mut encoder);
-            }
-            if(time > 13.832 && time < 14.150){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_10").unwrap(),&self.toylike_uniforms,&mut encoder);
-            }
-            if(time > 14.150 && time < 114.150){
-                self.fs_quad.render_to_screen_without_clear(&view_of_surface,&self.textures.get("frame_11").unwrap(),&self.toylike_uniforms,&mut encoder);
+            _ => {
+                self.apply_camera_setup(camera_change);
+                match overlay_transition {
+                    Some((transition, progress)) => {
+                        //the flipbook frame is a uniform as well, so the outgoing side gets its own submit too
+                        self.render_scene(SceneTarget::TransitionFrom,&mut encoder);
+                        self.render_overlays(SceneTarget::TransitionFrom,transition.start_beat as i32 - 1,time,&mut encoder);
+                        self.queue.submit(std::iter::once(encoder.finish()));
+                        encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Render Encoder"),
+                        });
+
+                        self.render_scene(SceneTarget::TransitionTo,&mut encoder);
+                        self.render_overlays(SceneTarget::TransitionTo,number_beats,time,&mut encoder);
+                        self.transitions.render(&self.queue,&mut encoder,&transition,progress,time,self.post_chain.scene_view());
+                        overlays_drawn = true;
+                    }
+                    None => self.render_scene(SceneTarget::PostChain,&mut encoder),
+                }
             }
         }
 
+        //everything draws into the post chain's hdr target, the post chain writes the surface at the end
+        if !overlays_drawn {
+            self.render_overlays(SceneTarget::PostChain,number_beats,time,&mut encoder);
+        }
 
 
-
-        //self.spline_test.render_to_screen(&view_of_surface,self.buffer_a.get_target_rtt_bindgroup(),&self.toylike_uniforms,&mut encoder,&self.queue);
+        self.post_chain.render(&self.queue,&mut encoder,self.color_grading.input_view(),time);
+        self.color_grading.render(&self.queue,&mut encoder,&view_of_surface);
 
         //submit will accept anythingthatimplements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
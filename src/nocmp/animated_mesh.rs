@@ -0,0 +1,463 @@
+/*
+Vertex animation for obj frame sequences (like the dancer), drawn with one pipeline instead of one mesh per frame.
+The pipeline comes from the MeshRenderer (VertexLayout::Animated), the mesh binds its own group 2.
+
+Every vertex of every frame goes into a vertex animation texture (Rgba32Float, read with textureLoad so it works on webgl2 too),
+three texels per vertex :
+  position.xyz, uv.x
+  normal.xyz, uv.y
+  tangent
+All frames share one index buffer, with indices local to their frame. vs_animated in obj_test.wgsl reads frame a and frame b
+for the vertex and blends them, so any fractional frame time can be shown.
+Blending needs the two frames to have the same topology (same vertex count and indices), frames that differ
+just switch over at the next whole frame.
+
+Clips are named frame ranges played in beats, so the animation follows the BeatClock instead of the frame rate.
+Playing a new clip crossfades from the old one, blending frames c/d of the old clip with a/b of the new one
+(again only when the topology matches, otherwise it switches half way through the crossfade).
+ */
+
+use anyhow::*;
+use std::ops::Range;
+use wgpu::util::DeviceExt;
+use wgpu::StoreOp;
+use crate::nocmp::material::{GpuMaterial, Material};
+use crate::nocmp::mesh_renderer::{MeshRenderer, ModelUniform, PipelineKey, VertexLayout};
+use crate::nocmp::bounds::{Aabb, CullStats, Frustum};
+use crate::nocmp::obj_parser::{Mesh, ObjLoaderRealtimeVertex};
+
+//webgl2 only guarantees 2048 wide textures
+const ANIMATION_TEXTURE_WIDTH: u32 = 2048;
+const TEXELS_PER_VERTEX: u32 = 3;
+
+//has to match AnimationUniforms in obj_test.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct AnimationUniforms {
+    //first vertex of each frame in the animation texture
+    frame_a: u32,
+    frame_b: u32,
+    blend: f32,
+    texture_width: u32,
+    //the clip being faded out
+    frame_c: u32,
+    frame_d: u32,
+    blend_cd: f32,
+    //0 is all a/b, 1 is all c/d
+    mix_cd: f32,
+}
+
+struct AnimationFrame {
+    first_vertex: u32,
+    //per material, ranges into the shared index buffer
+    submeshes: Vec<(Range<u32>, usize)>,
+    //frames with the same topology id have the same vertex count and indices, and can be blended
+    topology: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LoopMode {
+    Loop,
+    //forwards, then backwards, without repeating the end frames
+    PingPong,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    //frames of the animated mesh, in the order they were given to create
+    pub frames: Range<usize>,
+    pub loop_mode: LoopMode,
+    pub frames_per_beat: f32,
+}
+
+impl AnimationClip {
+    //4 frames per beat is what the dancer used to step at
+    pub fn new(name: &str, frames: Range<usize>) -> Self {
+        AnimationClip {
+            name: name.to_string(),
+            frames,
+            loop_mode: LoopMode::Loop,
+            frames_per_beat: 4.0,
+        }
+    }
+
+    //sets the speed so the whole clip takes beats, a loop then always lines up with the bars
+    pub fn with_beats_per_loop(self: Self, beats: f32) -> Self {
+        let frames_per_beat = self.frames.len() as f32 / beats.max(f32::MIN_POSITIVE);
+        AnimationClip {
+            frames_per_beat,
+            ..self
+        }
+    }
+
+    pub fn with_loop_mode(self: Self, loop_mode: LoopMode) -> Self {
+        AnimationClip {
+            loop_mode,
+            ..self
+        }
+    }
+
+    //The two frames to blend and how far between them, beats into the clip
+    pub fn sample(self: &Self, beats: f32) -> (usize, usize, f32) {
+        let count = self.frames.len().max(1);
+        let last = count - 1;
+        let position = (beats * self.frames_per_beat).max(0.0);
+        let (a, b, t) = match self.loop_mode {
+            LoopMode::Loop => {
+                let position = position.rem_euclid(count as f32);
+                let a = (position.floor() as usize).min(last);
+                (a, (a + 1) % count, position.fract())
+            }
+            LoopMode::PingPong if count > 1 => {
+                let period = (2 * last) as f32;
+                let position = position.rem_euclid(period);
+                let step = (position.floor() as usize).min(2 * last - 1);
+                //step n goes from frame n to n+1 on the way out and mirrored on the way back
+                let frame_of = |step: usize| if step <= last { step } else { 2 * last - step };
+                (frame_of(step), frame_of(step + 1), position.fract())
+            }
+            LoopMode::PingPong => (0, 0, 0.0),
+        };
+        (self.frames.start + a, self.frames.start + b, t)
+    }
+}
+
+//a clip playing since start_beat
+#[derive(Copy, Clone, Debug)]
+struct ClipPlayback {
+    clip: usize,
+    start_beat: f32,
+}
+
+pub struct AnimatedMesh {
+    pipeline: PipelineKey,
+    index_buffer: wgpu::Buffer,
+    frames: Vec<AnimationFrame>,
+    //the frame whose indices get drawn, set by update
+    drawn_frame: usize,
+    clips: Vec<AnimationClip>,
+    playing: Option<ClipPlayback>,
+    //the clip being crossfaded away from, and the length of the crossfade in beats
+    fading_out: Option<(ClipPlayback, f32)>,
+    //one per material used by any frame
+    pub materials: Vec<GpuMaterial>,
+    model_uniform_buffer: wgpu::Buffer,
+    animation_uniform_buffer: wgpu::Buffer,
+    bind_group_2: wgpu::BindGroup,
+    pub model_matrix: cgmath::Matrix4<f32>,
+    //around every frame, before model_matrix
+    pub bounds: Aabb,
+    //false after cull found the mesh outside the frustum
+    visible: bool,
+}
+
+impl AnimatedMesh {
+
+    //frames are drawn in order, all of them with the material of the same name from their own mtllib
+    pub fn create(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut MeshRenderer,
+        shader_descriptor: wgpu::ShaderModuleDescriptor,
+        meshes: &[&Mesh],
+    ) -> Result<Self> {
+        if meshes.is_empty() {
+            bail!("an animated mesh needs at least one frame");
+        }
+
+        //all frames end up in one texture and one index buffer
+        let mut texels: Vec<[f32;4]> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        let mut frames: Vec<AnimationFrame> = Vec::new();
+        let mut material_names: Vec<Option<String>> = Vec::new();
+        for mesh in meshes {
+            let first_vertex = (texels.len() as u32) / TEXELS_PER_VERTEX;
+            for vertex in bytemuck::cast_slice::<ObjLoaderRealtimeVertex, [f32;12]>(mesh.real_verts.as_slice()) {
+                //position, normal, uv and tangent, see ObjLoaderRealtimeVertex::ATTRIBUTES
+                texels.push([vertex[0], vertex[1], vertex[2], vertex[6]]);
+                texels.push([vertex[3], vertex[4], vertex[5], vertex[7]]);
+                texels.push([vertex[8], vertex[9], vertex[10], vertex[11]]);
+            }
+
+            let first_index = indices.len() as u32;
+            indices.extend_from_slice(mesh.faces.as_slice());
+            let mut submeshes = Vec::new();
+            for submesh in &mesh.submeshes {
+                let name = submesh.material.clone().filter(|name| mesh.materials.contains_key(name));
+                let material_index = match material_names.iter().position(|known| *known == name) {
+                    Some(index) => index,
+                    None => {
+                        material_names.push(name);
+                        material_names.len() - 1
+                    }
+                };
+                submeshes.push((first_index + submesh.index_range.start..first_index + submesh.index_range.end, material_index));
+            }
+            //the first earlier frame with the same vertex count and indices gives the topology id
+            let topology = meshes.iter()
+                .zip(frames.iter())
+                .find(|(earlier, _)| earlier.real_verts.len() == mesh.real_verts.len() && earlier.faces == mesh.faces)
+                .map(|(_, frame)| frame.topology)
+                .unwrap_or(frames.len());
+            frames.push(AnimationFrame {
+                first_vertex,
+                submeshes,
+                topology,
+            });
+        }
+
+        let texel_count = texels.len() as u32;
+        let height = ((texel_count + ANIMATION_TEXTURE_WIDTH - 1) / ANIMATION_TEXTURE_WIDTH).max(1);
+        if height > device.limits().max_texture_dimension_2d {
+            bail!("{} vertices do not fit in a {}x{} animation texture",texel_count / TEXELS_PER_VERTEX,ANIMATION_TEXTURE_WIDTH,device.limits().max_texture_dimension_2d);
+        }
+        texels.resize((ANIMATION_TEXTURE_WIDTH * height) as usize, [0.0;4]);
+        let animation_texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("vertex animation texture"),
+                size: wgpu::Extent3d {
+                    width: ANIMATION_TEXTURE_WIDTH,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(texels.as_slice()),
+        );
+        let animation_view = animation_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("animated mesh index buffer"),
+                contents: bytemuck::cast_slice(indices.as_slice()),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+        let model_matrix: cgmath::Matrix4<f32> = cgmath::Matrix4::from_scale(1.0);
+        let model_uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("animated mesh model uniform buffer"),
+                contents: bytemuck::cast_slice(&[ModelUniform { model_matrix: model_matrix.into() }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let animation_uniforms = AnimationUniforms {
+            frame_a: 0,
+            frame_b: 0,
+            blend: 0.0,
+            texture_width: ANIMATION_TEXTURE_WIDTH,
+            frame_c: 0,
+            frame_d: 0,
+            blend_cd: 0.0,
+            mix_cd: 0.0,
+        };
+        let animation_uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("animation uniform buffer"),
+                contents: bytemuck::cast_slice(&[animation_uniforms]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group_2 = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("animated mesh bind group 2"),
+            layout: renderer.bind_group_layout_2(VertexLayout::Animated),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: model_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: animation_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&animation_view),
+                },
+            ],
+        });
+
+        //materials by name, the first frame that has a material decides what it looks like
+        let mut materials = Vec::new();
+        for name in &material_names {
+            let default_material = Material::default();
+            let material = name.as_ref()
+                .and_then(|name| meshes.iter().find_map(|mesh| mesh.materials.get(name)))
+                .unwrap_or(&default_material);
+            materials.push(GpuMaterial::new(device, queue, material, renderer.material_layout(), &[])?);
+        }
+
+        let pipeline = PipelineKey::new(renderer.shader(device, shader_descriptor), VertexLayout::Animated);
+        renderer.prepare_for_materials(device, pipeline, &materials);
+
+        Ok(Self {
+            pipeline,
+            index_buffer,
+            frames,
+            drawn_frame: 0,
+            clips: Vec::new(),
+            playing: None,
+            fading_out: None,
+            materials,
+            model_uniform_buffer,
+            animation_uniform_buffer,
+            bind_group_2,
+            model_matrix,
+            bounds: meshes.iter().fold(Aabb::EMPTY, |bounds, mesh| bounds.union(&mesh.bounds)),
+            visible: true,
+        })
+    }
+
+    //decides if the next renders draw the mesh, with the current model_matrix
+    pub fn cull(self: &mut Self, frustum: &Frustum, stats: &mut CullStats) -> bool {
+        self.visible = stats.record(frustum.intersects_aabb(&self.bounds.transformed(&self.model_matrix)));
+        self.visible
+    }
+
+    pub fn frame_count(self: &Self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn add_clip(self: &mut Self, clip: AnimationClip) -> Result<()> {
+        if clip.frames.is_empty() || clip.frames.end > self.frames.len() {
+            bail!("clip {} has frames {:?}, the mesh has {} frames", clip.name, clip.frames, self.frames.len());
+        }
+        match self.clips.iter().position(|known| known.name == clip.name) {
+            Some(index) => self.clips[index] = clip,
+            None => self.clips.push(clip),
+        }
+        Ok(())
+    }
+
+    //Starts the clip at start_beat, crossfading from the clip that was playing over crossfade_beats.
+    //Playing the clip that is already playing keeps it going.
+    pub fn play(self: &mut Self, name: &str, start_beat: f32, crossfade_beats: f32) -> Result<()> {
+        let clip = self.clips.iter().position(|clip| clip.name == name)
+            .ok_or_else(|| anyhow!("no clip named {name}"))?;
+        if self.playing.map(|playback| playback.clip) == Some(clip) {
+            return Ok(());
+        }
+        self.fading_out = match self.playing {
+            Some(playback) if crossfade_beats > 0.0 => Some((playback, crossfade_beats)),
+            _ => None,
+        };
+        self.playing = Some(ClipPlayback { clip, start_beat });
+        Ok(())
+    }
+
+    //Moves the clips to beat (from the BeatClock) and pushes the frames and model_matrix to the gpu
+    pub fn update(self: &mut Self, queue: &wgpu::Queue, beat: f32) {
+        let model_uniforms = ModelUniform { model_matrix: self.model_matrix.into() };
+        queue.write_buffer(&self.model_uniform_buffer, 0, bytemuck::cast_slice(&[model_uniforms]));
+        let playback = match self.playing {
+            Some(playback) => playback,
+            None => return,
+        };
+        let current = self.clips[playback.clip].sample(beat - playback.start_beat);
+        let fading_out = match self.fading_out {
+            Some((old, crossfade_beats)) => {
+                let fade = (beat - playback.start_beat) / crossfade_beats;
+                if fade >= 1.0 {
+                    self.fading_out = None;
+                    None
+                } else {
+                    Some((self.clips[old.clip].sample(beat - old.start_beat), 1.0 - fade.max(0.0)))
+                }
+            }
+            None => None,
+        };
+        self.push_frames(queue, current, fading_out);
+    }
+
+    //frames a/b blended by t, mixed with frames c/d of a clip fading out. Anything that can not be blended
+    //(different topology) snaps to one side instead
+    fn push_frames(self: &mut Self, queue: &wgpu::Queue, (a, b, t): (usize, usize, f32), fading_out: Option<((usize, usize, f32), f32)>) {
+        let blendable = |from: usize, to: usize, t: f32| if self.frames[from].topology == self.frames[to].topology { t } else { 0.0 };
+        let mut frames = (a, b, blendable(a, b, t));
+        let mut faded = (a, a, 0.0, 0.0);
+        if let Some(((c, d, t_cd), mix_cd)) = fading_out {
+            if self.frames[c].topology == self.frames[a].topology {
+                faded = (c, d, blendable(c, d, t_cd), mix_cd);
+            } else if mix_cd > 0.5 {
+                //the old clip is still the bigger part, keep showing it
+                frames = (c, d, blendable(c, d, t_cd));
+            }
+        }
+        self.drawn_frame = frames.0;
+        let animation_uniforms = AnimationUniforms {
+            frame_a: self.frames[frames.0].first_vertex,
+            frame_b: self.frames[frames.1].first_vertex,
+            blend: frames.2,
+            texture_width: ANIMATION_TEXTURE_WIDTH,
+            frame_c: self.frames[faded.0].first_vertex,
+            frame_d: self.frames[faded.1].first_vertex,
+            blend_cd: faded.2,
+            mix_cd: faded.3,
+        };
+        queue.write_buffer(&self.animation_uniform_buffer, 0, bytemuck::cast_slice(&[animation_uniforms]));
+    }
+
+    //draws the frame picked by the last update on top of what is there, translucent materials after the opaque ones.
+    //renderer has to be the one the mesh was created with
+    pub fn render_to_screen_no_clear(
+        self: &Self,
+        renderer: &MeshRenderer,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        depth_view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        if !self.visible {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("animated mesh pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_bind_group(0, renderer.bind_group_0(), &[]);
+        render_pass.set_bind_group(2, &self.bind_group_2, &[]);
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        let frame = &self.frames[self.drawn_frame];
+        for translucent in [false, true] {
+            let in_pass = |material_index: usize| self.materials[material_index].translucent() == translucent;
+            if !frame.submeshes.iter().any(|(_, material_index)| in_pass(*material_index)) {
+                continue;
+            }
+            render_pass.set_pipeline(renderer.pipeline(if translucent { self.pipeline.translucent() } else { self.pipeline }));
+            for (index_range, material_index) in &frame.submeshes {
+                if in_pass(*material_index) {
+                    render_pass.set_bind_group(1, &self.materials[*material_index].bind_group, &[]);
+                    render_pass.draw_indexed(index_range.clone(), 0, 0..1);
+                }
+            }
+        }
+    }
+}
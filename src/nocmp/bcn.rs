@@ -0,0 +1,152 @@
+/*
+CPU decoders for the BC1-BC5 block compressed formats (a.k.a DXT1/DXT3/DXT5/ATI1/ATI2).
+Used as a fallback when the adapter does not have TEXTURE_COMPRESSION_BC, so KTX2 and DDS files
+still load, just as plain Rgba8 textures.
+Every block is 4x4 texels, BC1 and BC4 blocks are 8 bytes, BC2, BC3 and BC5 blocks are 16 bytes.
+BC6H and BC7 are a lot more involved and are not decoded here.
+ */
+use anyhow::*;
+
+//Decompresses one mip level into tightly packed rgba8, width*height*4 bytes.
+//Single channel formats end up in red, two channel formats in red and green.
+pub fn decompress_to_rgba8(format: wgpu::TextureFormat, width: u32, height: u32, data: &[u8]) -> Result<Vec<u8>> {
+    let block_bytes = match format {
+        wgpu::TextureFormat::Bc1RgbaUnorm | wgpu::TextureFormat::Bc1RgbaUnormSrgb
+        | wgpu::TextureFormat::Bc4RUnorm | wgpu::TextureFormat::Bc4RSnorm => 8,
+        wgpu::TextureFormat::Bc2RgbaUnorm | wgpu::TextureFormat::Bc2RgbaUnormSrgb
+        | wgpu::TextureFormat::Bc3RgbaUnorm | wgpu::TextureFormat::Bc3RgbaUnormSrgb
+        | wgpu::TextureFormat::Bc5RgUnorm | wgpu::TextureFormat::Bc5RgSnorm => 16,
+        _ => return Err(anyhow!("no cpu decoder for {:?}, the adapter needs to support it",format)),
+    };
+    let blocks_wide = ((width + 3) / 4) as usize;
+    let blocks_high = ((height + 3) / 4) as usize;
+    if data.len() < blocks_wide * blocks_high * block_bytes {
+        return Err(anyhow!("{:?} level of {}x{} needs {} bytes, got {}",format,width,height,blocks_wide * blocks_high * block_bytes,data.len()));
+    }
+
+    let mut rgba = vec![0u8; (width * height * 4) as usize];
+    let mut texels = [[0u8;4];16];
+    for block_y in 0..blocks_high {
+        for block_x in 0..blocks_wide {
+            let offset = (block_y * blocks_wide + block_x) * block_bytes;
+            let block = &data[offset..offset + block_bytes];
+            match format {
+                wgpu::TextureFormat::Bc1RgbaUnorm | wgpu::TextureFormat::Bc1RgbaUnormSrgb => {
+                    decode_color_block(block, &mut texels, true);
+                }
+                wgpu::TextureFormat::Bc2RgbaUnorm | wgpu::TextureFormat::Bc2RgbaUnormSrgb => {
+                    decode_color_block(&block[8..16], &mut texels, false);
+                    //explicit 4 bit alpha, two texels per byte, low nibble first
+                    for i in 0..16 {
+                        let nibble = (block[i / 2] >> ((i % 2) * 4)) & 0xF;
+                        texels[i][3] = nibble * 17;
+                    }
+                }
+                wgpu::TextureFormat::Bc3RgbaUnorm | wgpu::TextureFormat::Bc3RgbaUnormSrgb => {
+                    decode_color_block(&block[8..16], &mut texels, false);
+                    let alpha = decode_channel_block(&block[0..8], false);
+                    for i in 0..16 {
+                        texels[i][3] = alpha[i];
+                    }
+                }
+                wgpu::TextureFormat::Bc4RUnorm | wgpu::TextureFormat::Bc4RSnorm => {
+                    let red = decode_channel_block(block, format == wgpu::TextureFormat::Bc4RSnorm);
+                    for i in 0..16 {
+                        texels[i] = [red[i], 0, 0, 255];
+                    }
+                }
+                _ => {
+                    let snorm = format == wgpu::TextureFormat::Bc5RgSnorm;
+                    let red = decode_channel_block(&block[0..8], snorm);
+                    let green = decode_channel_block(&block[8..16], snorm);
+                    for i in 0..16 {
+                        texels[i] = [red[i], green[i], 0, 255];
+                    }
+                }
+            }
+
+            //blocks on the right and bottom edge can hang outside the image
+            for i in 0..16 {
+                let x = block_x * 4 + i % 4;
+                let y = block_y * 4 + i / 4;
+                if x < width as usize && y < height as usize {
+                    let index = (y * width as usize + x) * 4;
+                    rgba[index..index + 4].copy_from_slice(&texels[i]);
+                }
+            }
+        }
+    }
+    Ok(rgba)
+}
+
+fn rgb565_to_rgb8(color: u16) -> [u8;3] {
+    let r = ((color >> 11) & 0x1F) as u32;
+    let g = ((color >> 5) & 0x3F) as u32;
+    let b = (color & 0x1F) as u32;
+    [((r * 255 + 15) / 31) as u8, ((g * 255 + 31) / 63) as u8, ((b * 255 + 15) / 31) as u8]
+}
+
+//The 8 byte color block shared by BC1, BC2 and BC3.
+//Only BC1 has the three color mode with transparent black, BC2 and BC3 always use four colors.
+fn decode_color_block(block: &[u8], texels: &mut [[u8;4];16], allow_transparent: bool) {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let c0 = rgb565_to_rgb8(color0);
+    let c1 = rgb565_to_rgb8(color1);
+    let mix = |a: u8, b: u8, wa: u32, wb: u32| ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8;
+
+    let mut palette = [[0u8;4];4];
+    palette[0] = [c0[0], c0[1], c0[2], 255];
+    palette[1] = [c1[0], c1[1], c1[2], 255];
+    if color0 > color1 || !allow_transparent {
+        palette[2] = [mix(c0[0],c1[0],2,1), mix(c0[1],c1[1],2,1), mix(c0[2],c1[2],2,1), 255];
+        palette[3] = [mix(c0[0],c1[0],1,2), mix(c0[1],c1[1],1,2), mix(c0[2],c1[2],1,2), 255];
+    } else {
+        palette[2] = [mix(c0[0],c1[0],1,1), mix(c0[1],c1[1],1,1), mix(c0[2],c1[2],1,1), 255];
+        palette[3] = [0, 0, 0, 0];
+    }
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    for i in 0..16 {
+        texels[i] = palette[((indices >> (i * 2)) & 0x3) as usize];
+    }
+}
+
+//The 8 byte single channel block used by BC3 alpha, BC4 and both halves of BC5.
+//Snorm values are remapped to 0-255 so they fit in an unorm fallback texture.
+fn decode_channel_block(block: &[u8], snorm: bool) -> [u8;16] {
+    let (e0, e1) = if snorm {
+        ((block[0] as i8).max(-127) as f32 / 127.0, (block[1] as i8).max(-127) as f32 / 127.0)
+    } else {
+        (block[0] as f32 / 255.0, block[1] as f32 / 255.0)
+    };
+
+    let mut palette = [0.0f32;8];
+    palette[0] = e0;
+    palette[1] = e1;
+    let endpoints_descending = if snorm { (block[0] as i8) > (block[1] as i8) } else { block[0] > block[1] };
+    if endpoints_descending {
+        for i in 1..7 {
+            palette[i + 1] = (e0 * (7 - i) as f32 + e1 * i as f32) / 7.0;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = (e0 * (5 - i) as f32 + e1 * i as f32) / 5.0;
+        }
+        palette[6] = if snorm { -1.0 } else { 0.0 };
+        palette[7] = 1.0;
+    }
+
+    //16 indices of 3 bits packed in the remaining 6 bytes
+    let mut bits = 0u64;
+    for i in 0..6 {
+        bits |= (block[2 + i] as u64) << (i * 8);
+    }
+    let mut values = [0u8;16];
+    for i in 0..16 {
+        let value = palette[((bits >> (i * 3)) & 0x7) as usize];
+        let unorm = if snorm { value * 0.5 + 0.5 } else { value };
+        values[i] = (unorm.clamp(0.0,1.0) * 255.0).round() as u8;
+    }
+    values
+}
@@ -0,0 +1,52 @@
+/*
+Musical time for the timeline.
+The track runs at 469 ms per beat (about 128 bpm), the clock turns demo seconds into beats,
+so effects, transitions and animation can be placed on beats instead of hand tuned seconds.
+ */
+
+#[derive(Copy,Clone,Debug)]
+pub struct BeatClock {
+    pub beat_length_ms: f32,
+    //seconds before the first beat
+    pub offset_seconds: f32,
+}
+
+impl Default for BeatClock {
+    fn default() -> Self {
+        BeatClock {
+            beat_length_ms: 469.0,
+            offset_seconds: 0.0,
+        }
+    }
+}
+
+impl BeatClock {
+    pub fn from_bpm(bpm: f32) -> Self {
+        BeatClock {
+            beat_length_ms: 60_000.0 / bpm,
+            ..Default::default()
+        }
+    }
+
+    pub fn bpm(self: &Self) -> f32 {
+        60_000.0 / self.beat_length_ms
+    }
+
+    //Fractional beats since the first beat
+    pub fn beat(self: &Self, time_seconds: f32) -> f32 {
+        (time_seconds - self.offset_seconds) * 1000.0 / self.beat_length_ms
+    }
+
+    pub fn whole_beats(self: &Self, time_seconds: f32) -> i32 {
+        self.beat(time_seconds).floor() as i32
+    }
+
+    //0 on the beat, going towards 1 right before the next one
+    pub fn beat_phase(self: &Self, time_seconds: f32) -> f32 {
+        self.beat(time_seconds).rem_euclid(1.0)
+    }
+
+    pub fn seconds(self: &Self, beats: f32) -> f32 {
+        beats * self.beat_length_ms / 1000.0
+    }
+}
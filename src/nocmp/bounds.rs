@@ -0,0 +1,185 @@
+/*
+Bounding volumes and view frustum culling.
+
+Meshes get an axis aligned box and a sphere around their vertices when they are loaded (Mesh::compute_bounds),
+both can be moved into world space with a model matrix. Frustum::from_view_projection pulls the six clip planes
+out of a view projection matrix (Camera::frustum), with wgpu's 0..1 depth range.
+The renderers have a cull method that tests their bounds against a frustum before drawing and counts
+what it did in CullStats, so a debug overlay can show how much is skipped.
+ */
+
+use cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    //contains nothing, not even the origin. Growing it by a point gives a box around just that point
+    pub const EMPTY: Aabb = Aabb {
+        min: Vector3::new(f32::MAX, f32::MAX, f32::MAX),
+        max: Vector3::new(f32::MIN, f32::MIN, f32::MIN),
+    };
+
+    pub fn from_points(points: impl IntoIterator<Item = [f32;3]>) -> Self {
+        let mut aabb = Self::EMPTY;
+        for point in points {
+            aabb.grow(Vector3::from(point));
+        }
+        aabb
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn grow(self: &mut Self, point: Vector3<f32>) {
+        self.min = Vector3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z));
+        self.max = Vector3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z));
+    }
+
+    pub fn union(self: &Self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Vector3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+
+    pub fn center(self: &Self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    //half the size
+    pub fn extents(self: &Self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    //The box around this box moved by matrix (Arvo's method), a bit bigger than the box itself when rotated
+    pub fn transformed(self: &Self, matrix: &Matrix4<f32>) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        let center = (matrix * self.center().extend(1.0)).truncate();
+        let extents = self.extents();
+        let axis = |row: usize| -> f32 {
+            matrix[0][row].abs() * extents.x + matrix[1][row].abs() * extents.y + matrix[2][row].abs() * extents.z
+        };
+        let extents = Vector3::new(axis(0), axis(1), axis(2));
+        Aabb {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    //negative for a sphere around nothing
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub const EMPTY: BoundingSphere = BoundingSphere {
+        center: Vector3::new(0.0, 0.0, 0.0),
+        radius: -1.0,
+    };
+
+    //Centered on the box around the points, just big enough to hold the point furthest from there
+    pub fn from_points(points: impl IntoIterator<Item = [f32;3]> + Clone) -> Self {
+        let aabb = Aabb::from_points(points.clone());
+        if aabb.is_empty() {
+            return Self::EMPTY;
+        }
+        let center = aabb.center();
+        let radius = points.into_iter()
+            .map(|point| (Vector3::from(point) - center).magnitude2())
+            .fold(0.0, f32::max)
+            .sqrt();
+        BoundingSphere { center, radius }
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.radius < 0.0
+    }
+
+    //Scaled by the largest scale in matrix, so it still holds everything after non uniform scaling
+    pub fn transformed(self: &Self, matrix: &Matrix4<f32>) -> BoundingSphere {
+        if self.is_empty() {
+            return *self;
+        }
+        let scale = matrix.x.truncate().magnitude()
+            .max(matrix.y.truncate().magnitude())
+            .max(matrix.z.truncate().magnitude());
+        BoundingSphere {
+            center: (matrix * self.center.extend(1.0)).truncate(),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+//Planes point into the frustum, a point p is inside a plane when dot(plane.xyz, p) + plane.w >= 0
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    //Gribb and Hartmann, for clip space with x and y in -w..w and z in 0..w
+    pub fn from_view_projection(view_projection: &Matrix4<f32>) -> Self {
+        let row = |i: usize| Vector4::new(view_projection[0][i], view_projection[1][i], view_projection[2][i], view_projection[3][i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.truncate().magnitude();
+            if length > 0.0 { plane / length } else { plane }
+        });
+        Frustum { planes }
+    }
+
+    pub fn intersects_sphere(self: &Self, sphere: &BoundingSphere) -> bool {
+        !sphere.is_empty() && self.planes.iter().all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+
+    //Can let a box near a corner of the frustum through, never culls a visible one
+    pub fn intersects_aabb(self: &Self, aabb: &Aabb) -> bool {
+        !aabb.is_empty() && self.planes.iter().all(|plane| {
+            //the corner furthest along the plane normal
+            let corner = Vector3::new(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+//What culling did, summed over everything tested since the last reset
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub tested: u32,
+    pub culled: u32,
+}
+
+impl CullStats {
+    //counts one test, returns visible so it can be used inline
+    pub fn record(self: &mut Self, visible: bool) -> bool {
+        self.tested += 1;
+        if !visible {
+            self.culled += 1;
+        }
+        visible
+    }
+
+    pub fn drawn(self: &Self) -> u32 {
+        self.tested - self.culled
+    }
+}
+
+impl std::fmt::Display for CullStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "drawn {}/{} ({} culled)", self.drawn(), self.tested, self.culled)
+    }
+}
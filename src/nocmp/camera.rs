@@ -40,6 +40,11 @@ impl Camera {
         // 3.
         return OPENGL_TO_WGPU_MATRIX * proj * view;
     }
+
+    //what build_view_projection_matrix can see, for culling
+    pub fn frustum(&self) -> crate::nocmp::bounds::Frustum {
+        crate::nocmp::bounds::Frustum::from_view_projection(&self.build_view_projection_matrix())
+    }
 }
 
 
@@ -51,6 +56,8 @@ pub struct CameraUniform {
     // We can't use cgmath with bytemuck directly so we'll have
     // to convert the Matrix4 into a 4x4 f32 array
     pub view_proj: [[f32; 4]; 4],
+    //the eye, w is unused. For specular lighting
+    pub view_position: [f32; 4],
 }
 
 impl CameraUniform {
@@ -58,11 +65,13 @@ impl CameraUniform {
         use cgmath::SquareMatrix;
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
+            view_position: [0.0, 0.0, 0.0, 1.0],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = camera.build_view_projection_matrix().into();
+        self.view_position = camera.eye.to_homogeneous().into();
     }
 
 
@@ -0,0 +1,385 @@
+/*
+Color grading with 3D LUTs.
+Loads Adobe/Resolve .cube files into 3D textures and applies them as the last pass before the surface,
+so stills graded in external tools look the same in the demo.
+
+Up to MAX_BLENDED_LUTS luts are applied at once, each with its own weight. Whatever is left of the
+weights up to 1.0 keeps the ungraded color, so a single lut can be faded in by animating its weight,
+and two luts can be crossfaded by moving weight from one to the other.
+The post chain writes linear, tonemapped color into input_view(), render() encodes it to srgb,
+grades that (luts from grading tools expect display encoded input) and writes the output.
+When the output format is an srgb format the hardware does the encoding, so the result is decoded back to linear first.
+ */
+use std::fs;
+use anyhow::*;
+use bytemuck::Zeroable;
+use wgpu::util::DeviceExt;
+use crate::nocmp::texture;
+use crate::nocmp::texture::Texture;
+
+pub const MAX_BLENDED_LUTS: usize = 4;
+//the grading input has to hold the post chain output without banding before the lut lookup
+pub const INPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+//A parsed .cube file, only 3D luts are supported
+#[derive(Clone,Debug)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    pub size: u32,
+    pub domain_min: [f32;3],
+    pub domain_max: [f32;3],
+    //size^3 entries, red changes fastest, then green, then blue
+    pub data: Vec<[f32;3]>,
+}
+
+impl CubeLut {
+    pub fn from_path(path: &str) -> Result<Self> {
+        println!("Opening LUT : {}",path);
+        let contents = fs::read_to_string(path).with_context(|| format!("could not read {}",path))?;
+        Self::parse(&contents).with_context(|| format!("could not parse {}",path))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.0;3];
+        let mut domain_max = [1.0;3];
+        let mut data = Vec::new();
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let keyword = parts.next().unwrap();
+            match keyword {
+                "TITLE" => {
+                    title = Some(line["TITLE".len()..].trim().trim_matches('"').to_string());
+                }
+                "LUT_3D_SIZE" => {
+                    let value: u32 = parts.next()
+                        .ok_or_else(|| anyhow!("line {} : LUT_3D_SIZE without a size",line_number + 1))?
+                        .parse()?;
+                    if value < 2 || value > 256 {
+                        bail!("line {} : LUT_3D_SIZE {} is out of range",line_number + 1,value);
+                    }
+                    size = Some(value);
+                }
+                "LUT_1D_SIZE" => {
+                    bail!("line {} : 1D luts are not supported",line_number + 1);
+                }
+                "DOMAIN_MIN" => domain_min = parse_triplet(line,line_number,parts)?,
+                "DOMAIN_MAX" => domain_max = parse_triplet(line,line_number,parts)?,
+                //other keywords (LUT_3D_INPUT_RANGE and vendor specific ones) don't change the lookup
+                keyword if keyword.chars().next().unwrap().is_ascii_alphabetic() => {}
+                _ => {
+                    data.push(parse_triplet(line,line_number,line.split_whitespace())?);
+                }
+            }
+        }
+
+        let size = size.ok_or_else(|| anyhow!("missing LUT_3D_SIZE"))?;
+        let expected = (size * size * size) as usize;
+        if data.len() != expected {
+            bail!("expected {} entries for a {}^3 lut, found {}",expected,size,data.len());
+        }
+        for axis in 0..3 {
+            if domain_max[axis] <= domain_min[axis] {
+                bail!("DOMAIN_MAX has to be larger than DOMAIN_MIN");
+            }
+        }
+        Ok(Self { title, size, domain_min, domain_max, data })
+    }
+
+    //A lut that leaves the colors as they are
+    pub fn identity(size: u32) -> Self {
+        let step = 1.0 / (size - 1) as f32;
+        let mut data = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.push([r as f32 * step, g as f32 * step, b as f32 * step]);
+                }
+            }
+        }
+        Self { title: Some("identity".to_string()), size, domain_min: [0.0;3], domain_max: [1.0;3], data }
+    }
+
+    //Rgba16Float is filterable everywhere, so the lookup gets trilinear interpolation between the lut entries
+    fn create_texture(self: &Self, device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Result<Texture> {
+        let texels: Vec<half::f16> = self.data.iter()
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 1.0])
+            .map(half::f16::from_f32)
+            .collect();
+        let mut lut = Texture::create_volume(device, queue, self.size, self.size, self.size,
+            wgpu::TextureFormat::Rgba16Float, bytemuck::cast_slice(&texels), Some(label))?;
+        //volumes repeat by default, a lut has to clamp or the edges bleed into the opposite side
+        lut.sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        Ok(lut)
+    }
+}
+
+fn parse_triplet<'a>(line: &str, line_number: usize, parts: impl Iterator<Item = &'a str>) -> Result<[f32;3]> {
+    let values = parts.map(|part| part.parse::<f32>()).collect::<std::result::Result<Vec<f32>,_>>()
+        .map_err(|_| anyhow!("line {} : could not parse '{}'",line_number + 1,line))?;
+    if values.len() != 3 {
+        bail!("line {} : expected 3 values, found '{}'",line_number + 1,line);
+    }
+    Ok([values[0], values[1], values[2]])
+}
+
+#[repr(C)]
+#[derive(Debug,Copy,Clone,bytemuck::Pod,bytemuck::Zeroable)]
+struct GradingUniforms {
+    weights: [f32;4],
+    domain_min: [[f32;4];MAX_BLENDED_LUTS],
+    domain_max: [[f32;4];MAX_BLENDED_LUTS],
+    //x is 1.0 when the output format is srgb
+    output_srgb: [f32;4],
+}
+
+struct GradingLut {
+    texture: Texture,
+    domain_min: [f32;3],
+    domain_max: [f32;3],
+    weight: f32,
+}
+
+pub struct ColorGrading {
+    input: Texture,
+    //bound to the slots that have no lut, its weight is always 0
+    identity: Texture,
+    luts: Vec<GradingLut>,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    texture_bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    output_srgb: bool,
+}
+
+impl ColorGrading {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32, output_format: wgpu::TextureFormat) -> Result<Self> {
+        let input = Self::create_input(device, width, height)?;
+        let identity = CubeLut::identity(2).create_texture(device, queue, "identity lut")?;
+
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("color grading uniforms"),
+                contents: bytemuck::cast_slice(&[GradingUniforms::zeroed()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("color grading uniform bind group layout"),
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("color grading uniform bind group"),
+        });
+
+        //binding 0/1 is the input, then a texture and sampler pair per lut slot
+        let mut layout_entries = Vec::new();
+        layout_entries.extend(texture::texture_layout_entries(device, &input, 0, wgpu::ShaderStages::FRAGMENT));
+        for slot in 0..MAX_BLENDED_LUTS as u32 {
+            layout_entries.extend(texture::texture_layout_entries(device, &identity, 2 + slot * 2, wgpu::ShaderStages::FRAGMENT));
+        }
+        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: layout_entries.as_slice(),
+            label: Some("color grading texture bind group layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("color grading pipeline layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("color grading shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shadertoys/color_grading.wgsl").into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("color grading pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let texture_bind_group = Self::create_texture_bind_group(device, &texture_bind_group_layout, &input, &identity, &[]);
+        Ok(Self {
+            input,
+            identity,
+            luts: Vec::new(),
+            uniform_buffer,
+            uniform_bind_group,
+            texture_bind_group_layout,
+            texture_bind_group,
+            pipeline,
+            output_srgb: output_format.is_srgb(),
+        })
+    }
+
+    fn create_input(device: &wgpu::Device, width: u32, height: u32) -> Result<Texture> {
+        Texture::create_rtt_texture_with_sampler(width, height, device, INPUT_FORMAT,
+            wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest, wgpu::AddressMode::ClampToEdge, Some("color grading input"))
+    }
+
+    fn create_texture_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        input: &Texture,
+        identity: &Texture,
+        luts: &[GradingLut],
+    ) -> wgpu::BindGroup {
+        let mut entries = Vec::new();
+        entries.extend(texture::texture_bind_group_entries(input, 0));
+        for slot in 0..MAX_BLENDED_LUTS {
+            let lut = luts.get(slot).map(|lut| &lut.texture).unwrap_or(identity);
+            entries.extend(texture::texture_bind_group_entries(lut, 2 + slot as u32 * 2));
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: entries.as_slice(),
+            label: Some("color grading texture bind group"),
+        })
+    }
+
+    fn rebuild_bind_group(self: &mut Self, device: &wgpu::Device) {
+        self.texture_bind_group = Self::create_texture_bind_group(device, &self.texture_bind_group_layout, &self.input, &self.identity, &self.luts);
+    }
+
+    //Adds a lut with weight 0 and returns its index for set_weight
+    pub fn add_lut(self: &mut Self, device: &wgpu::Device, queue: &wgpu::Queue, lut: &CubeLut, label: &str) -> Result<usize> {
+        if self.luts.len() >= MAX_BLENDED_LUTS {
+            bail!("{} : only {} luts can be blended",label,MAX_BLENDED_LUTS);
+        }
+        let texture = lut.create_texture(device, queue, label)?;
+        self.luts.push(GradingLut {
+            texture,
+            domain_min: lut.domain_min,
+            domain_max: lut.domain_max,
+            weight: 0.0,
+        });
+        self.rebuild_bind_group(device);
+        Ok(self.luts.len() - 1)
+    }
+
+    pub fn add_lut_from_path(self: &mut Self, device: &wgpu::Device, queue: &wgpu::Queue, path: &str) -> Result<usize> {
+        let lut = CubeLut::from_path(path)?;
+        //the TITLE of the file goes in the label, so the lut is easy to tell apart in graphics debuggers
+        let label = match &lut.title {
+            Some(title) => format!("{path} ({title})"),
+            None => path.to_string(),
+        };
+        self.add_lut(device, queue, &lut, &label)
+    }
+
+    pub fn lut_count(self: &Self) -> usize {
+        self.luts.len()
+    }
+
+    //The weights are used as they are, keep the sum at or below 1.0 to blend towards the ungraded image
+    pub fn set_weight(self: &mut Self, index: usize, weight: f32) {
+        if let Some(lut) = self.luts.get_mut(index) {
+            lut.weight = weight;
+        }
+    }
+
+    pub fn weight(self: &Self, index: usize) -> f32 {
+        self.luts.get(index).map(|lut| lut.weight).unwrap_or(0.0)
+    }
+
+    pub fn resize(self: &mut Self, device: &wgpu::Device, width: u32, height: u32) -> Result<()> {
+        self.input = Self::create_input(device, width, height)?;
+        self.rebuild_bind_group(device);
+        Ok(())
+    }
+
+    pub fn input_view(self: &Self) -> &wgpu::TextureView {
+        &self.input.view
+    }
+
+    pub fn render(self: &Self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, output_view: &wgpu::TextureView) {
+        let mut uniforms = GradingUniforms::zeroed();
+        for (slot, lut) in self.luts.iter().enumerate() {
+            uniforms.weights[slot] = lut.weight;
+            uniforms.domain_min[slot] = [lut.domain_min[0], lut.domain_min[1], lut.domain_min[2], 0.0];
+            uniforms.domain_max[slot] = [lut.domain_max[0], lut.domain_max[1], lut.domain_max[2], 0.0];
+        }
+        //empty slots still need a valid domain
+        for slot in self.luts.len()..MAX_BLENDED_LUTS {
+            uniforms.domain_max[slot] = [1.0;4];
+        }
+        uniforms.output_srgb[0] = if self.output_srgb { 1.0 } else { 0.0 };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("color grading pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.texture_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,484 @@
+/*
+glTF 2.0 (.gltf with .bin files, or .glb) loading, the cpu side of skinned_mesh.rs.
+
+Everything in the file is read up front : meshes become SkinnedVertex triangle lists, pbr materials are turned into
+the Material we use for obj files (with the images embedded in the file), and nodes, skins and animation channels
+are kept so a pose can be sampled at any time.
+Meshes without a skin get joint 0 with full weight, and the renderer puts the world matrix of their node there,
+so everything in a file goes through the same skinning vertex shader.
+
+Not supported : data: uris, morph targets, sparse accessors and primitives that are not triangle lists.
+ */
+use anyhow::*;
+use cgmath::{InnerSpace, Matrix4, Quaternion, SquareMatrix, Vector3};
+use std::path::Path;
+use crate::nocmp::material::Material;
+use crate::nocmp::tangents;
+
+//has to match SkinnedVertexInput in obj_test.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinnedVertex {
+    pub position: [f32;3],
+    pub normal: [f32;3],
+    pub uv: [f32;2],
+    pub tangent: [f32;4],
+    //indices into the joints of the skin
+    pub joints: [u32;4],
+    pub weights: [f32;4],
+}
+
+impl SkinnedVertex {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x3,
+            2 => Float32x2,
+            3 => Float32x4,
+            4 => Uint32x4,
+            5 => Float32x4,
+        ];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SkinnedVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+impl tangents::TangentVertex for SkinnedVertex {
+    fn position(self: &Self) -> [f32;3] {
+        self.position
+    }
+
+    fn normal(self: &Self) -> [f32;3] {
+        self.normal
+    }
+
+    fn uv(self: &Self) -> [f32;2] {
+        self.uv
+    }
+
+    fn set_tangent(self: &mut Self, tangent: [f32;4]) {
+        self.tangent = tangent;
+    }
+}
+
+pub struct GltfPrimitive {
+    pub vertices: Vec<SkinnedVertex>,
+    pub indices: Vec<u32>,
+    //index into GltfScene::materials
+    pub material: Option<usize>,
+}
+
+pub struct GltfMesh {
+    pub name: String,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+pub struct GltfMaterial {
+    pub material: Material,
+    //indices into GltfScene::images
+    pub base_color_image: Option<usize>,
+    pub normal_image: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GltfNode {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    //rest pose, animations replace parts of it
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    pub mesh: Option<usize>,
+    pub skin: Option<usize>,
+}
+
+pub struct Skin {
+    pub name: String,
+    //node indices, SkinnedVertex::joints index into this
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelProperty {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    //values are in tangent, value, out tangent for every keyframe
+    CubicSpline,
+}
+
+pub struct AnimationChannel {
+    pub node: usize,
+    pub property: ChannelProperty,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    //xyz for translation and scale, xyzw quaternions for rotation
+    pub values: Vec<[f32;4]>,
+}
+
+pub struct GltfAnimation {
+    pub name: String,
+    pub channels: Vec<AnimationChannel>,
+    //seconds, the last keyframe of any channel
+    pub duration: f32,
+}
+
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<GltfMaterial>,
+    pub images: Vec<Option<image::DynamicImage>>,
+    pub nodes: Vec<GltfNode>,
+    pub skins: Vec<Skin>,
+    pub animations: Vec<GltfAnimation>,
+}
+
+fn read_file(directory: &Path, uri: &str) -> Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        bail!("data: uris are not supported, export as glb or with separate files");
+    }
+    let path = directory.join(uri);
+    std::fs::read(&path).with_context(|| format!("could not read {}", path.display()))
+}
+
+//area weighted vertex normals, for primitives that come without any
+fn generate_normals(vertices: &mut [SkinnedVertex], indices: &[u32]) {
+    let mut normals = vec![Vector3::new(0.0f32, 0.0, 0.0); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| Vector3::from(vertices[index as usize].position));
+        //the cross product is as long as twice the area
+        let normal = (b - a).cross(c - a);
+        for index in triangle {
+            normals[*index as usize] += normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { Vector3::unit_y() };
+        vertex.normal = normal.into();
+    }
+}
+
+impl GltfScene {
+    pub fn load(path: &str) -> Result<Self> {
+        let gltf = gltf::Gltf::open(path).with_context(|| format!("could not open {path}"))?;
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        let mut buffers: Vec<Vec<u8>> = Vec::new();
+        for buffer in gltf.document.buffers() {
+            let data = match buffer.source() {
+                gltf::buffer::Source::Bin => gltf.blob.clone().ok_or_else(|| anyhow!("{path} has no binary chunk"))?,
+                gltf::buffer::Source::Uri(uri) => read_file(directory, uri)?,
+            };
+            if data.len() < buffer.length() {
+                bail!("buffer {} of {path} is {} bytes, expected {}", buffer.index(), data.len(), buffer.length());
+            }
+            buffers.push(data);
+        }
+        let buffer_data = |buffer: gltf::Buffer| Some(buffers[buffer.index()].as_slice());
+
+        //an image that does not load only costs its texture, the material falls back to plain values
+        let mut images = Vec::new();
+        for image in gltf.document.images() {
+            let bytes = match image.source() {
+                gltf::image::Source::View { view, .. } => {
+                    Ok(buffers[view.buffer().index()][view.offset()..view.offset() + view.length()].to_vec())
+                }
+                gltf::image::Source::Uri { uri, .. } => read_file(directory, uri),
+            };
+            let decoded = bytes.and_then(|bytes| image::load_from_memory(&bytes).map_err(Error::from));
+            match decoded {
+                Result::Ok(decoded) => images.push(Some(decoded)),
+                Err(error) => {
+                    println!("{path} : could not load image {} ({error})", image.index());
+                    images.push(None);
+                }
+            }
+        }
+
+        let mut materials = Vec::new();
+        for material in gltf.document.materials() {
+            let pbr = material.pbr_metallic_roughness();
+            let base_color = pbr.base_color_factor();
+            let metallic = pbr.metallic_factor();
+            let roughness = pbr.roughness_factor().max(0.01);
+            //dielectrics reflect about 4%, metals reflect their base color
+            let specular = [0, 1, 2].map(|i| 0.04 + (base_color[i] - 0.04) * metallic);
+            materials.push(GltfMaterial {
+                material: Material {
+                    name: material.name().unwrap_or("gltf material").to_string(),
+                    diffuse: [base_color[0], base_color[1], base_color[2]],
+                    dissolve: base_color[3],
+                    specular,
+                    //a blinn-phong exponent that gives about the same highlight size
+                    shininess: (2.0 / roughness.powi(4) - 2.0).clamp(1.0, 1000.0),
+                    emissive: material.emissive_factor(),
+                    optical_density: 1.5,
+                    ..Default::default()
+                },
+                base_color_image: pbr.base_color_texture().map(|info| info.texture().source().index()),
+                normal_image: material.normal_texture().map(|normal| normal.texture().source().index()),
+            });
+        }
+
+        let mut meshes = Vec::new();
+        for mesh in gltf.document.meshes() {
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    println!("{path} : skipping a {:?} primitive of mesh {}, only triangles are supported", primitive.mode(), mesh.index());
+                    continue;
+                }
+                let reader = primitive.reader(buffer_data);
+                let positions: Vec<[f32;3]> = match reader.read_positions() {
+                    Some(positions) => positions.collect(),
+                    None => continue,
+                };
+                let mut vertices: Vec<SkinnedVertex> = positions.iter().map(|position| SkinnedVertex {
+                    position: *position,
+                    normal: [0.0, 1.0, 0.0],
+                    uv: [0.0, 0.0],
+                    tangent: [1.0, 0.0, 0.0, 1.0],
+                    joints: [0; 4],
+                    weights: [1.0, 0.0, 0.0, 0.0],
+                }).collect();
+                if let Some(uvs) = reader.read_tex_coords(0) {
+                    vertices.iter_mut().zip(uvs.into_f32()).for_each(|(vertex, uv)| vertex.uv = uv);
+                }
+                if let (Some(joints), Some(weights)) = (reader.read_joints(0), reader.read_weights(0)) {
+                    for ((vertex, joints), weights) in vertices.iter_mut().zip(joints.into_u16()).zip(weights.into_f32()) {
+                        vertex.joints = joints.map(|joint| joint as u32);
+                        //exporters are not always exact, the weights have to add up to 1
+                        let sum: f32 = weights.iter().sum();
+                        vertex.weights = if sum > 0.0 { weights.map(|weight| weight / sum) } else { [1.0, 0.0, 0.0, 0.0] };
+                    }
+                }
+                let mut indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..vertices.len() as u32).collect(),
+                };
+                indices.truncate(indices.len() / 3 * 3);
+
+                match reader.read_normals() {
+                    Some(normals) => vertices.iter_mut().zip(normals).for_each(|(vertex, normal)| vertex.normal = normal),
+                    None => generate_normals(&mut vertices, &indices),
+                }
+                match reader.read_tangents() {
+                    Some(tangents) => vertices.iter_mut().zip(tangents).for_each(|(vertex, tangent)| vertex.tangent = tangent),
+                    None => {
+                        if !tangents::generate_tangents(&mut vertices, &mut indices) {
+                            println!("{path} : could not generate tangents for mesh {}, normal maps will not line up", mesh.index());
+                        }
+                    }
+                }
+
+                primitives.push(GltfPrimitive {
+                    vertices,
+                    indices,
+                    material: primitive.material().index(),
+                });
+            }
+            meshes.push(GltfMesh {
+                name: mesh.name().unwrap_or("gltf mesh").to_string(),
+                primitives,
+            });
+        }
+
+        let mut nodes: Vec<GltfNode> = Vec::new();
+        for node in gltf.document.nodes() {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            nodes.push(GltfNode {
+                name: node.name().unwrap_or("gltf node").to_string(),
+                parent: None,
+                children: node.children().map(|child| child.index()).collect(),
+                translation: translation.into(),
+                rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+                scale: scale.into(),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                skin: node.skin().map(|skin| skin.index()),
+            });
+        }
+        for index in 0..nodes.len() {
+            for child in nodes[index].children.clone() {
+                nodes[child].parent = Some(index);
+            }
+        }
+
+        let mut skins = Vec::new();
+        for skin in gltf.document.skins() {
+            let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+            let inverse_bind_matrices = match skin.reader(buffer_data).read_inverse_bind_matrices() {
+                Some(matrices) => matrices.map(Matrix4::from).collect(),
+                None => vec![Matrix4::identity(); joints.len()],
+            };
+            skins.push(Skin {
+                name: skin.name().unwrap_or("gltf skin").to_string(),
+                joints,
+                inverse_bind_matrices,
+            });
+        }
+
+        let mut animations = Vec::new();
+        for animation in gltf.document.animations() {
+            let mut channels = Vec::new();
+            for channel in animation.channels() {
+                let reader = channel.reader(buffer_data);
+                let times: Vec<f32> = match reader.read_inputs() {
+                    Some(inputs) => inputs.collect(),
+                    None => continue,
+                };
+                let (property, values): (ChannelProperty, Vec<[f32;4]>) = match reader.read_outputs() {
+                    Some(gltf::animation::util::ReadOutputs::Translations(values)) =>
+                        (ChannelProperty::Translation, values.map(|[x, y, z]| [x, y, z, 0.0]).collect()),
+                    Some(gltf::animation::util::ReadOutputs::Rotations(values)) =>
+                        (ChannelProperty::Rotation, values.into_f32().collect()),
+                    Some(gltf::animation::util::ReadOutputs::Scales(values)) =>
+                        (ChannelProperty::Scale, values.map(|[x, y, z]| [x, y, z, 0.0]).collect()),
+                    //morph target weights
+                    _ => continue,
+                };
+                let interpolation = match channel.sampler().interpolation() {
+                    gltf::animation::Interpolation::Step => Interpolation::Step,
+                    gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                    gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+                };
+                let expected = if interpolation == Interpolation::CubicSpline { times.len() * 3 } else { times.len() };
+                if times.is_empty() || values.len() < expected {
+                    println!("{path} : skipping a broken channel in animation {}", animation.index());
+                    continue;
+                }
+                channels.push(AnimationChannel {
+                    node: channel.target().node().index(),
+                    property,
+                    interpolation,
+                    times,
+                    values,
+                });
+            }
+            let duration = channels.iter().filter_map(|channel| channel.times.last().copied()).fold(0.0, f32::max);
+            animations.push(GltfAnimation {
+                name: animation.name().unwrap_or("gltf animation").to_string(),
+                channels,
+                duration,
+            });
+        }
+
+        Ok(GltfScene {
+            meshes,
+            materials,
+            images,
+            nodes,
+            skins,
+            animations,
+        })
+    }
+
+    pub fn animation_index(self: &Self, name: &str) -> Option<usize> {
+        self.animations.iter().position(|animation| animation.name == name)
+    }
+
+    //World matrices of every node, in the rest pose or with an animation at time (seconds, clamped to the animation)
+    pub fn pose(self: &Self, animation: Option<usize>, time: f32) -> Vec<Matrix4<f32>> {
+        let mut locals: Vec<(Vector3<f32>, Quaternion<f32>, Vector3<f32>)> = self.nodes.iter()
+            .map(|node| (node.translation, node.rotation, node.scale))
+            .collect();
+        if let Some(animation) = animation.and_then(|index| self.animations.get(index)) {
+            for channel in &animation.channels {
+                let value = channel.sample(time);
+                let local = &mut locals[channel.node];
+                match channel.property {
+                    ChannelProperty::Translation => local.0 = Vector3::new(value[0], value[1], value[2]),
+                    ChannelProperty::Rotation => local.1 = Quaternion::new(value[3], value[0], value[1], value[2]).normalize(),
+                    ChannelProperty::Scale => local.2 = Vector3::new(value[0], value[1], value[2]),
+                }
+            }
+        }
+
+        let mut world = vec![Matrix4::identity(); self.nodes.len()];
+        //parents before children, starting from the roots
+        let mut stack: Vec<usize> = (0..self.nodes.len()).filter(|index| self.nodes[*index].parent.is_none()).collect();
+        while let Some(index) = stack.pop() {
+            let (translation, rotation, scale) = locals[index];
+            let local = Matrix4::from_translation(translation) * Matrix4::from(rotation) * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z);
+            world[index] = match self.nodes[index].parent {
+                Some(parent) => world[parent] * local,
+                None => local,
+            };
+            stack.extend(self.nodes[index].children.iter().copied());
+        }
+        world
+    }
+
+    //The matrices the vertex shader skins with, for the mesh on node
+    pub fn joint_matrices(self: &Self, node: usize, world: &[Matrix4<f32>]) -> Vec<Matrix4<f32>> {
+        match self.nodes[node].skin.map(|skin| &self.skins[skin]) {
+            Some(skin) => skin.joints.iter()
+                .zip(skin.inverse_bind_matrices.iter())
+                .map(|(joint, inverse_bind)| world[*joint] * inverse_bind)
+                .collect(),
+            //not skinned, joint 0 places the whole mesh
+            None => vec![world[node]],
+        }
+    }
+}
+
+impl AnimationChannel {
+    fn value(self: &Self, keyframe: usize) -> [f32;4] {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values[keyframe * 3 + 1],
+            _ => self.values[keyframe],
+        }
+    }
+
+    pub fn sample(self: &Self, time: f32) -> [f32;4] {
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return self.value(0);
+        }
+        if time >= self.times[last] {
+            return self.value(last);
+        }
+        let next = self.times.partition_point(|keyframe_time| *keyframe_time <= time).min(last);
+        let previous = next - 1;
+        let delta = (self.times[next] - self.times[previous]).max(f32::MIN_POSITIVE);
+        let t = (time - self.times[previous]) / delta;
+        let rotation = self.property == ChannelProperty::Rotation;
+        let (from, to) = (self.value(previous), self.value(next));
+        match self.interpolation {
+            Interpolation::Step => from,
+            Interpolation::Linear if rotation => {
+                let from = Quaternion::new(from[3], from[0], from[1], from[2]);
+                let to = Quaternion::new(to[3], to[0], to[1], to[2]);
+                let blended = from.slerp(to, t);
+                [blended.v.x, blended.v.y, blended.v.z, blended.s]
+            }
+            Interpolation::Linear => [0, 1, 2, 3].map(|i| from[i] + (to[i] - from[i]) * t),
+            Interpolation::CubicSpline => {
+                //hermite spline, tangents are scaled by the time between the keyframes
+                let out_tangent = self.values[previous * 3 + 2];
+                let in_tangent = self.values[next * 3];
+                let (t2, t3) = (t * t, t * t * t);
+                let mut value = [0, 1, 2, 3].map(|i| {
+                    (2.0 * t3 - 3.0 * t2 + 1.0) * from[i]
+                        + (t3 - 2.0 * t2 + t) * delta * out_tangent[i]
+                        + (-2.0 * t3 + 3.0 * t2) * to[i]
+                        + (t3 - t2) * delta * in_tangent[i]
+                });
+                if rotation {
+                    let length = value.iter().map(|component| component * component).sum::<f32>().sqrt().max(f32::MIN_POSITIVE);
+                    value = value.map(|component| component / length);
+                }
+                value
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+/*
+One obj mesh drawn many times in a single draw call (letters, particles, a crowd of dancers),
+instead of one GpuMesh draw per copy.
+
+Every copy is an InstanceData in a per instance vertex buffer, filled with set_instances each frame.
+It is drawn by a MeshRenderer with a VertexLayout::Instanced pipeline (see draw).
+vs_instanced in obj_test.wgsl reads the model matrix from the instance and puts the per draw model matrix
+(group 2, like a plain MeshDraw) in front of it, so model_matrix moves all the instances at once.
+The instance color multiplies the shaded color in fs_main, params is passed on to the fragment shader untouched.
+cull drops the instances whose bounding sphere is outside the view before they are drawn.
+ */
+
+use anyhow::*;
+use crate::nocmp::mesh_renderer::{GpuMesh, MeshDraw, MeshRenderer, PipelineKey};
+use crate::nocmp::obj_parser::Mesh;
+use crate::nocmp::bounds::{CullStats, Frustum};
+
+//has to match InstanceInput in obj_test.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceData {
+    pub model_matrix: [[f32;4];4],
+    pub color: [f32;4],
+    //whatever a custom shader wants per instance (phase, beat offset, ..)
+    pub params: [f32;4],
+}
+
+impl InstanceData {
+    pub fn new(model_matrix: cgmath::Matrix4<f32>) -> Self {
+        Self {
+            model_matrix: model_matrix.into(),
+            color: [1.0;4],
+            params: [0.0;4],
+        }
+    }
+
+    pub fn with_color(self: Self, color: [f32;4]) -> Self {
+        Self { color, ..self }
+    }
+
+    pub fn with_params(self: Self, params: [f32;4]) -> Self {
+        Self { params, ..self }
+    }
+
+    const ATTRIBS: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        6 => Float32x4,
+        7 => Float32x4,
+        8 => Float32x4,
+        9 => Float32x4,
+        10 => Float32x4,
+        11 => Float32x4,
+    ];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+pub struct InstancedMesh {
+    pub mesh: GpuMesh,
+    instance_buffer: wgpu::Buffer,
+    //how many instances instance_buffer has room for, and how many are drawn
+    instance_capacity: usize,
+    instance_count: u32,
+    //everything set_instances got, cull uploads the visible ones
+    instances: Vec<InstanceData>,
+    pub model_matrix: cgmath::Matrix4<f32>,
+}
+
+impl InstancedMesh {
+
+    //capacity is how many instances to make room for up front, set_instances grows the buffer when it needs to
+    pub fn create(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &MeshRenderer,
+        mesh: &Mesh,
+        capacity: usize,
+    ) -> Result<Self> {
+        let instance_capacity = capacity.max(1);
+        Ok(Self {
+            mesh: GpuMesh::new(device, queue, renderer, mesh)?,
+            instance_buffer: Self::create_instance_buffer(device, instance_capacity),
+            instance_capacity,
+            instance_count: 0,
+            instances: Vec::new(),
+            model_matrix: cgmath::Matrix4::from_scale(1.0),
+        })
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("instance buffer"),
+            size: (capacity * std::mem::size_of::<InstanceData>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    //Replaces the instances drawn, call it every frame the instances move.
+    //The buffer is recreated (twice as big) when instances doesn't fit
+    pub fn set_instances(self: &mut Self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[InstanceData]) {
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(instances));
+        self.instance_count = instances.len() as u32;
+        self.instances.clear();
+        self.instances.extend_from_slice(instances);
+    }
+
+    //Draws only the instances from the last set_instances that can be seen, with the current model_matrix.
+    //Every instance counts as a test in stats, returns how many are left
+    pub fn cull(self: &mut Self, queue: &wgpu::Queue, frustum: &Frustum, stats: &mut CullStats) -> u32 {
+        let visible: Vec<InstanceData> = self.instances.iter()
+            .filter(|instance| {
+                let matrix = self.model_matrix * cgmath::Matrix4::from(instance.model_matrix);
+                stats.record(frustum.intersects_sphere(&self.mesh.bounding_sphere.transformed(&matrix)))
+            })
+            .copied()
+            .collect();
+        if !visible.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(visible.as_slice()));
+        }
+        self.instance_count = visible.len() as u32;
+        self.instance_count
+    }
+
+    //None when there is nothing to draw, pipeline needs VertexLayout::Instanced
+    pub fn draw(self: &Self, pipeline: PipelineKey) -> Option<MeshDraw<'_>> {
+        if self.instance_count == 0 {
+            return None;
+        }
+        Some(MeshDraw::new(&self.mesh, pipeline, self.model_matrix).with_instances(&self.instance_buffer, self.instance_count))
+    }
+}
@@ -0,0 +1,241 @@
+/*
+Keyboard state as a texture, like the keyboard input on shadertoy.
+The texture is 256x3 , x is the javascript keycode (65 is A, 37-40 are the arrows and so on)
+row 0 : 1.0 while the key is held down
+row 1 : 1.0 only in the frame the key was pressed
+row 2 : toggles between 0.0 and 1.0 every time the key is pressed
+
+Bind it as any other channel with setup_texture_stage, and read it in wgsl with
+textureLoad(t_keyboard, vec2<i32>(65, 0), 0).r
+so ported effects keep their controls while we develop.
+Key presses can be asked for on the cpu with pressed, for demo controls outside the shaders.
+ */
+use anyhow::*;
+use winit::event::{ElementState, KeyEvent, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use crate::nocmp::texture::Texture;
+
+const KEY_COUNT: usize = 256;
+const ROW_DOWN: usize = 0;
+const ROW_PRESSED: usize = 1;
+const ROW_TOGGLED: usize = 2;
+
+pub struct KeyboardTexture {
+    //three rows of 256 bytes, laid out exactly like the texture
+    state: [u8; KEY_COUNT * 3],
+    texture: Texture,
+}
+
+impl KeyboardTexture {
+    pub fn new(device: &wgpu::Device) -> Result<Self> {
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some("keyboard texture"),
+                size: wgpu::Extent3d {
+                    width: KEY_COUNT as u32,
+                    height: 3,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            }
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        //keys are discrete, filtering between neighbouring keycodes makes no sense
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            state: [0; KEY_COUNT * 3],
+            texture: Texture { texture, view, sampler, view_dimension: wgpu::TextureViewDimension::D2, sampler_binding_type: wgpu::SamplerBindingType::Filtering },
+        })
+    }
+
+    pub fn texture(self: &Self) -> &Texture {
+        &self.texture
+    }
+
+    //Only true in the frame the key was pressed, ask before push_texture_to_gfx_card clears it
+    pub fn pressed(self: &Self, key_code: KeyCode) -> bool {
+        self.row_state(ROW_PRESSED, key_code)
+    }
+
+    fn row_state(self: &Self, row: usize, key_code: KeyCode) -> bool {
+        match javascript_key_code(key_code) {
+            Some(code) => self.state[row * KEY_COUNT + code as usize] != 0,
+            None => false,
+        }
+    }
+
+    pub fn process_events(self: &mut Self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state,
+                    physical_key: PhysicalKey::Code(key_code),
+                    repeat,
+                    ..
+                },
+                ..
+            } => {
+                let code = match javascript_key_code(*key_code) {
+                    Some(code) => code as usize,
+                    None => return false,
+                };
+                match state {
+                    ElementState::Pressed => {
+                        self.state[ROW_DOWN * KEY_COUNT + code] = 255;
+                        //holding a key down sends repeats, those are not new presses
+                        if !*repeat {
+                            self.state[ROW_PRESSED * KEY_COUNT + code] = 255;
+                            self.state[ROW_TOGGLED * KEY_COUNT + code] ^= 255;
+                        }
+                    }
+                    ElementState::Released => {
+                        self.state[ROW_DOWN * KEY_COUNT + code] = 0;
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    //Uploads the state, call once per frame before rendering.
+    //The pressed row is cleared afterwards, so it is only set for one frame.
+    pub fn push_texture_to_gfx_card(self: &mut Self, queue: &wgpu::Queue) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.state,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(KEY_COUNT as u32),
+                rows_per_image: Some(3),
+            },
+            wgpu::Extent3d {
+                width: KEY_COUNT as u32,
+                height: 3,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.state[ROW_PRESSED * KEY_COUNT..(ROW_PRESSED + 1) * KEY_COUNT].fill(0);
+    }
+}
+
+//Shadertoy indexes the keyboard texture with javascript keycodes, so we translate winit keys to those.
+pub fn javascript_key_code(key_code: KeyCode) -> Option<u8> {
+    let code = match key_code {
+        KeyCode::Backspace => 8,
+        KeyCode::Tab => 9,
+        KeyCode::Enter | KeyCode::NumpadEnter => 13,
+        KeyCode::ShiftLeft | KeyCode::ShiftRight => 16,
+        KeyCode::ControlLeft | KeyCode::ControlRight => 17,
+        KeyCode::AltLeft | KeyCode::AltRight => 18,
+        KeyCode::Pause => 19,
+        KeyCode::CapsLock => 20,
+        KeyCode::Escape => 27,
+        KeyCode::Space => 32,
+        KeyCode::PageUp => 33,
+        KeyCode::PageDown => 34,
+        KeyCode::End => 35,
+        KeyCode::Home => 36,
+        KeyCode::ArrowLeft => 37,
+        KeyCode::ArrowUp => 38,
+        KeyCode::ArrowRight => 39,
+        KeyCode::ArrowDown => 40,
+        KeyCode::Insert => 45,
+        KeyCode::Delete => 46,
+        KeyCode::Digit0 => 48,
+        KeyCode::Digit1 => 49,
+        KeyCode::Digit2 => 50,
+        KeyCode::Digit3 => 51,
+        KeyCode::Digit4 => 52,
+        KeyCode::Digit5 => 53,
+        KeyCode::Digit6 => 54,
+        KeyCode::Digit7 => 55,
+        KeyCode::Digit8 => 56,
+        KeyCode::Digit9 => 57,
+        KeyCode::KeyA => 65,
+        KeyCode::KeyB => 66,
+        KeyCode::KeyC => 67,
+        KeyCode::KeyD => 68,
+        KeyCode::KeyE => 69,
+        KeyCode::KeyF => 70,
+        KeyCode::KeyG => 71,
+        KeyCode::KeyH => 72,
+        KeyCode::KeyI => 73,
+        KeyCode::KeyJ => 74,
+        KeyCode::KeyK => 75,
+        KeyCode::KeyL => 76,
+        KeyCode::KeyM => 77,
+        KeyCode::KeyN => 78,
+        KeyCode::KeyO => 79,
+        KeyCode::KeyP => 80,
+        KeyCode::KeyQ => 81,
+        KeyCode::KeyR => 82,
+        KeyCode::KeyS => 83,
+        KeyCode::KeyT => 84,
+        KeyCode::KeyU => 85,
+        KeyCode::KeyV => 86,
+        KeyCode::KeyW => 87,
+        KeyCode::KeyX => 88,
+        KeyCode::KeyY => 89,
+        KeyCode::KeyZ => 90,
+        KeyCode::Numpad0 => 96,
+        KeyCode::Numpad1 => 97,
+        KeyCode::Numpad2 => 98,
+        KeyCode::Numpad3 => 99,
+        KeyCode::Numpad4 => 100,
+        KeyCode::Numpad5 => 101,
+        KeyCode::Numpad6 => 102,
+        KeyCode::Numpad7 => 103,
+        KeyCode::Numpad8 => 104,
+        KeyCode::Numpad9 => 105,
+        KeyCode::NumpadMultiply => 106,
+        KeyCode::NumpadAdd => 107,
+        KeyCode::NumpadSubtract => 109,
+        KeyCode::NumpadDecimal => 110,
+        KeyCode::NumpadDivide => 111,
+        KeyCode::F1 => 112,
+        KeyCode::F2 => 113,
+        KeyCode::F3 => 114,
+        KeyCode::F4 => 115,
+        KeyCode::F5 => 116,
+        KeyCode::F6 => 117,
+        KeyCode::F7 => 118,
+        KeyCode::F8 => 119,
+        KeyCode::F9 => 120,
+        KeyCode::F10 => 121,
+        KeyCode::F11 => 122,
+        KeyCode::F12 => 123,
+        KeyCode::Semicolon => 186,
+        KeyCode::Equal => 187,
+        KeyCode::Comma => 188,
+        KeyCode::Minus => 189,
+        KeyCode::Period => 190,
+        KeyCode::Slash => 191,
+        KeyCode::Backquote => 192,
+        KeyCode::BracketLeft => 219,
+        KeyCode::Backslash => 220,
+        KeyCode::BracketRight => 221,
+        KeyCode::Quote => 222,
+        _ => return None,
+    };
+    Some(code)
+}
@@ -0,0 +1,229 @@
+/*
+Real-time lights for the mesh shaders.
+
+Lights is the list of lights in the scene and the uniform buffer obj_test.wgsl reads it from (group 0, binding 2).
+Up to MAX_LIGHTS directional, point and spot lights are shaded with Blinn-Phong in fs_main, using the Kd, Ks, Ns and Ka
+of the material, plus an ambient color that is multiplied by Ka.
+It is a fixed size uniform array rather than a storage buffer so it works with webgl2 limits.
+
+Everything on Light is public, so the timeline can change lights in update() like the post effect params,
+call push_lights afterwards. LightTrack keyframes a light over beats for moves that are easier to write as keys.
+ */
+
+use bytemuck::Zeroable;
+use cgmath::{InnerSpace, Vector3, VectorSpace};
+
+//has to match MAX_LIGHTS in obj_test.wgsl
+pub const MAX_LIGHTS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightKind {
+    //from infinitely far away along direction, position is ignored
+    Directional,
+    //in all directions from position, direction is ignored
+    Point,
+    //from position along direction, inside a cone
+    Spot,
+}
+
+impl LightKind {
+    fn shader_index(self: Self) -> f32 {
+        match self {
+            LightKind::Directional => 0.0,
+            LightKind::Point => 1.0,
+            LightKind::Spot => 2.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub position: Vector3<f32>,
+    //the way the light travels, not the way to the light
+    pub direction: Vector3<f32>,
+    pub color: [f32;3],
+    //0 turns the light off without removing it
+    pub intensity: f32,
+    //point and spot lights fade out smoothly and reach nothing at this distance
+    pub range: f32,
+    //half angles of the spot cone in radians, full light inside inner, none outside outer
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+impl Light {
+    fn new(kind: LightKind, position: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            kind,
+            position,
+            direction,
+            color: [1.0;3],
+            intensity: 1.0,
+            range: 10.0,
+            inner_angle: 0.3,
+            outer_angle: 0.4,
+        }
+    }
+
+    pub fn directional(direction: Vector3<f32>) -> Self {
+        Self::new(LightKind::Directional, Vector3::new(0.0, 0.0, 0.0), direction)
+    }
+
+    pub fn point(position: Vector3<f32>) -> Self {
+        Self::new(LightKind::Point, position, Vector3::new(0.0, -1.0, 0.0))
+    }
+
+    pub fn spot(position: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self::new(LightKind::Spot, position, direction)
+    }
+
+    pub fn with_color(self: Self, color: [f32;3]) -> Self {
+        Self { color, ..self }
+    }
+
+    pub fn with_intensity(self: Self, intensity: f32) -> Self {
+        Self { intensity, ..self }
+    }
+
+    pub fn with_range(self: Self, range: f32) -> Self {
+        Self { range, ..self }
+    }
+
+    pub fn with_cone(self: Self, inner_angle: f32, outer_angle: f32) -> Self {
+        Self { inner_angle, outer_angle, ..self }
+    }
+
+    //Everything blended linearly except the kind, which stays the kind of self
+    pub fn lerp(self: &Self, other: &Light, t: f32) -> Light {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        let direction = self.direction.lerp(other.direction, t);
+        Light {
+            kind: self.kind,
+            position: self.position.lerp(other.position, t),
+            //halfway between opposite directions there is none, keep the first
+            direction: if direction.magnitude2() > 0.0 { direction } else { self.direction },
+            color: [mix(self.color[0], other.color[0]), mix(self.color[1], other.color[1]), mix(self.color[2], other.color[2])],
+            intensity: mix(self.intensity, other.intensity),
+            range: mix(self.range, other.range),
+            inner_angle: mix(self.inner_angle, other.inner_angle),
+            outer_angle: mix(self.outer_angle, other.outer_angle),
+        }
+    }
+
+    pub fn uniform(self: &Self) -> LightUniform {
+        let direction = if self.direction.magnitude2() > 0.0 { self.direction.normalize() } else { Vector3::new(0.0, -1.0, 0.0) };
+        //a cone with the inner angle at (or past) the outer one gets a hard edge instead of a division by zero
+        let cos_outer = self.outer_angle.cos();
+        let cos_inner = self.inner_angle.cos().max(cos_outer + 0.0001);
+        LightUniform {
+            position: [self.position.x, self.position.y, self.position.z, self.kind.shader_index()],
+            direction: [direction.x, direction.y, direction.z, self.range.max(0.0001)],
+            color: [self.color[0], self.color[1], self.color[2], self.intensity],
+            cone: [cos_inner, cos_outer, 0.0, 0.0],
+        }
+    }
+}
+
+//has to match Light in obj_test.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    //xyz and the kind in w (0 directional, 1 point, 2 spot)
+    pub position: [f32;4],
+    //normalized xyz and the range in w
+    pub direction: [f32;4],
+    //rgb and the intensity in w
+    pub color: [f32;4],
+    //cosines of the inner and outer spot angles
+    pub cone: [f32;4],
+}
+
+//has to match Lights in obj_test.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightsUniform {
+    pub lights: [LightUniform; MAX_LIGHTS],
+    //rgb, w is unused
+    pub ambient: [f32;4],
+    //x is how many of lights are used
+    pub count: [u32;4],
+}
+
+pub struct Lights {
+    pub lights: Vec<Light>,
+    pub ambient: [f32;3],
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl Lights {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            lights: Vec::new(),
+            ambient: [0.05;3],
+            uniform_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("lights uniform buffer"),
+                size: std::mem::size_of::<LightsUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    //group 0 binding 2 of the mesh shaders
+    pub fn uniform_buffer(self: &Self) -> &wgpu::Buffer {
+        &self.uniform_buffer
+    }
+
+    //lights past MAX_LIGHTS are left out
+    pub fn uniforms(self: &Self) -> LightsUniform {
+        let mut uniforms = LightsUniform {
+            lights: [LightUniform::zeroed(); MAX_LIGHTS],
+            ambient: [self.ambient[0], self.ambient[1], self.ambient[2], 0.0],
+            count: [self.lights.len().min(MAX_LIGHTS) as u32, 0, 0, 0],
+        };
+        for (uniform, light) in uniforms.lights.iter_mut().zip(&self.lights) {
+            *uniform = light.uniform();
+        }
+        uniforms
+    }
+
+    //after changing lights, call it every frame the lights move
+    pub fn push_lights(self: &Self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniforms()]));
+    }
+}
+
+//A light keyframed over beats. Between two keys the light is blended linearly,
+//before the first key and after the last it holds
+#[derive(Clone, Debug, Default)]
+pub struct LightTrack {
+    //in beat order
+    keys: Vec<(f32, Light)>,
+}
+
+impl LightTrack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //keys can be added in any order
+    pub fn with_key(mut self: Self, beat: f32, light: Light) -> Self {
+        let index = self.keys.partition_point(|(key_beat, _)| *key_beat <= beat);
+        self.keys.insert(index, (beat, light));
+        self
+    }
+
+    //None without keys
+    pub fn sample(self: &Self, beat: f32) -> Option<Light> {
+        let next = self.keys.partition_point(|(key_beat, _)| *key_beat <= beat);
+        if next == 0 {
+            return self.keys.first().map(|(_, light)| *light);
+        }
+        let (from_beat, from) = &self.keys[next - 1];
+        match self.keys.get(next) {
+            Some((to_beat, to)) => Some(from.lerp(to, (beat - from_beat) / (to_beat - from_beat))),
+            None => Some(*from),
+        }
+    }
+}
@@ -0,0 +1,332 @@
+/*
+Materials from Wavefront .mtl files, referenced from obj files with mtllib and picked per face with usemtl.
+
+Material is what the file says, GpuMaterial is the uniform buffer and bind group made from it.
+The bind group (group 1 in obj_test.wgsl) is laid out as
+  0 : MaterialUniforms
+  1/2 : map_Kd (diffuse, srgb)
+  3/4 : map_Bump (tangent space normal map)
+  5/6 : map_Ks (specular)
+  7/8 : map_d (alpha)
+  9 + n*2 / 10 + n*2 : extra channel n (cubemaps, noise volumes..)
+Maps that are missing or fail to load are replaced by a 1x1 texture that leaves the material values as they are.
+ */
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use wgpu::util::DeviceExt;
+use crate::nocmp::texture;
+use crate::nocmp::texture::{Texture, TextureConfig};
+
+pub const FIRST_CHANNEL_BINDING: u32 = 9;
+
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    //Ka
+    pub ambient: [f32;3],
+    //Kd
+    pub diffuse: [f32;3],
+    //Ks
+    pub specular: [f32;3],
+    //Ke
+    pub emissive: [f32;3],
+    //Ns, the specular exponent
+    pub shininess: f32,
+    //Ni, index of refraction
+    pub optical_density: f32,
+    //d, 1.0 is opaque (Tr is read as 1.0 - d)
+    pub dissolve: f32,
+    //illum, the illumination model number
+    pub illumination: u32,
+    //map paths are relative to the working directory, like every other asset path
+    pub diffuse_map: Option<String>,
+    pub bump_map: Option<String>,
+    pub specular_map: Option<String>,
+    pub alpha_map: Option<String>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: String::from("default"),
+            ambient: [1.0, 1.0, 1.0],
+            diffuse: [0.8, 0.8, 0.8],
+            specular: [0.5, 0.5, 0.5],
+            emissive: [0.0, 0.0, 0.0],
+            shininess: 250.0,
+            optical_density: 1.45,
+            dissolve: 1.0,
+            illumination: 2,
+            diffuse_map: None,
+            bump_map: None,
+            specular_map: None,
+            alpha_map: None,
+        }
+    }
+}
+
+fn parse_color(parts: &[&str]) -> Result<[f32;3], Box<dyn std::error::Error>> {
+    let r: f32 = parts.get(1).ok_or("missing color value")?.parse()?;
+    //a single value is a grey
+    let g: f32 = match parts.get(2) { Some(value) => value.parse()?, None => r };
+    let b: f32 = match parts.get(3) { Some(value) => value.parse()?, None => r };
+    Ok([r, g, b])
+}
+
+fn parse_value<T: std::str::FromStr>(parts: &[&str]) -> Result<T, Box<dyn std::error::Error>>
+where T::Err: std::error::Error + 'static {
+    Ok(parts.get(1).ok_or("missing value")?.parse()?)
+}
+
+//Map statements can have options in front of the file name (map_Bump -bm 0.5 normal.png),
+//everything after the options is the file name (it can contain spaces), and is relative to the mtl file
+fn parse_map(parts: &[&str], directory: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut rest = parts.get(1..).unwrap_or(&[]);
+    while let Some(option) = rest.first().filter(|part| part.starts_with('-')) {
+        //the number of values after each option, -o -s and -t take 1 to 3 numbers
+        let values = match *option {
+            "-o" | "-s" | "-t" => rest[1..].iter().take(3).take_while(|value| value.parse::<f32>().is_ok()).count(),
+            "-mm" => 2,
+            "-blendu" | "-blendv" | "-boost" | "-texres" | "-clamp" | "-bm" | "-imfchan" | "-type" | "-cc" => 1,
+            //not an option we know, so it has to be the start of the file name
+            _ => break,
+        };
+        rest = rest.get(1 + values..).unwrap_or(&[]);
+    }
+    if rest.is_empty() {
+        return Err("map without a file name".into());
+    }
+    Ok(directory.join(rest.join(" ")).to_string_lossy().to_string())
+}
+
+impl Material {
+    pub fn parse_mtl_file(path: &str) -> Result<HashMap<String, Material>, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        let mut materials: HashMap<String, Material> = HashMap::new();
+        let mut current: Option<Material> = None;
+
+        for line in reader.lines() {
+            let line = line?;
+            let parts: Vec<&str> = line.split_whitespace().collect();
+
+            if parts.is_empty() || parts[0].starts_with('#') {
+                continue;
+            }
+
+            if parts[0] == "newmtl" {
+                if let Some(material) = current.take() {
+                    materials.insert(material.name.clone(), material);
+                }
+                current = Some(Material {
+                    name: parts[1..].join(" "),
+                    ..Default::default()
+                });
+                continue;
+            }
+
+            let material = match current.as_mut() {
+                Some(material) => material,
+                None => return Err(format!("{path} : '{}' before newmtl", parts[0]).into()),
+            };
+            match parts[0] {
+                "Ka" => material.ambient = parse_color(&parts)?,
+                "Kd" => material.diffuse = parse_color(&parts)?,
+                "Ks" => material.specular = parse_color(&parts)?,
+                "Ke" => material.emissive = parse_color(&parts)?,
+                "Ns" => material.shininess = parse_value(&parts)?,
+                "Ni" => material.optical_density = parse_value(&parts)?,
+                "d" => material.dissolve = parse_value(&parts)?,
+                "Tr" => material.dissolve = 1.0 - parse_value::<f32>(&parts)?,
+                "illum" => material.illumination = parse_value(&parts)?,
+                "map_Kd" => material.diffuse_map = Some(parse_map(&parts, directory)?),
+                "map_Bump" | "map_bump" | "bump" | "norm" => material.bump_map = Some(parse_map(&parts, directory)?),
+                "map_Ks" => material.specular_map = Some(parse_map(&parts, directory)?),
+                "map_d" => material.alpha_map = Some(parse_map(&parts, directory)?),
+                _ => {}
+            }
+        }
+        if let Some(material) = current.take() {
+            materials.insert(material.name.clone(), material);
+        }
+        Ok(materials)
+    }
+
+    pub fn uniforms(self: &Self) -> MaterialUniforms {
+        MaterialUniforms {
+            color: [self.diffuse[0], self.diffuse[1], self.diffuse[2], self.dissolve],
+            specular: [self.specular[0], self.specular[1], self.specular[2], self.shininess],
+            ambient: [self.ambient[0], self.ambient[1], self.ambient[2], self.optical_density],
+            emissive: [self.emissive[0], self.emissive[1], self.emissive[2], self.illumination as f32],
+        }
+    }
+}
+
+//has to match MaterialUniforms in obj_test.wgsl
+#[repr(C)]
+#[derive(Copy,Clone, Debug,bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniforms {
+    //Kd and d
+    pub color: [f32;4],
+    //Ks and Ns
+    pub specular: [f32;4],
+    //Ka and Ni
+    pub ambient: [f32;4],
+    //Ke and illum
+    pub emissive: [f32;4],
+}
+
+fn solid_texture(device: &wgpu::Device, queue: &wgpu::Queue, rgba: [u8;4], label: &str) -> anyhow::Result<Texture> {
+    let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(rgba)));
+    Texture::from_image(device, queue, &image, Some(label))
+}
+
+//The texture, and false when it is the plain fallback color because there was no map or it did not load
+fn load_map(device: &wgpu::Device, queue: &wgpu::Queue, path: &Option<String>, image: Option<&image::DynamicImage>, srgb: bool, fallback: [u8;4], label: &str) -> anyhow::Result<(Texture, bool)> {
+    let config = TextureConfig {
+        srgb,
+        ..TextureConfig::mipmapped(wgpu::AddressMode::Repeat, 16)
+    };
+    if let Some(image) = image {
+        return Ok((Texture::from_image_with_config(device, queue, image, Some(label), &config)?, true));
+    }
+    if let Some(path) = path {
+        match Texture::from_path_with_config(device, queue, path, label, &config) {
+            Ok(texture) => return Ok((texture, true)),
+            Err(error) => println!("{label} : could not load {path}, using a plain texture ({error})"),
+        }
+    }
+    Ok((solid_texture(device, queue, fallback, label)?, false))
+}
+
+//Already decoded maps (like the ones embedded in gltf files), used instead of the map paths of the material
+#[derive(Clone, Copy, Default)]
+pub struct MaterialImages<'a> {
+    pub diffuse: Option<&'a image::DynamicImage>,
+    pub bump: Option<&'a image::DynamicImage>,
+    pub specular: Option<&'a image::DynamicImage>,
+    pub alpha: Option<&'a image::DynamicImage>,
+}
+
+pub struct GpuMaterial {
+    pub uniforms: MaterialUniforms,
+    has_alpha_map: bool,
+    uniform_buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl GpuMaterial {
+    //All materials share this layout, only the channels change it
+    pub fn create_bind_group_layout(device: &wgpu::Device, queue: &wgpu::Queue, channels: &[&Texture]) -> anyhow::Result<wgpu::BindGroupLayout> {
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        //every map is a filterable 2D texture, so any of them describes the layout
+        let representative = solid_texture(device, queue, [255, 255, 255, 255], "material layout texture")?;
+        for map in 0..4 {
+            entries.extend(texture::texture_layout_entries(device, &representative, 1 + map * 2, wgpu::ShaderStages::FRAGMENT));
+        }
+        for (i, channel) in channels.iter().enumerate() {
+            entries.extend(texture::texture_layout_entries(device, channel, FIRST_CHANNEL_BINDING + i as u32 * 2, wgpu::ShaderStages::FRAGMENT));
+        }
+        Ok(device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material bind group layout"),
+            entries: entries.as_slice(),
+        }))
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material: &Material,
+        layout: &wgpu::BindGroupLayout,
+        channels: &[&Texture],
+    ) -> anyhow::Result<Self> {
+        Self::with_images(device, queue, material, MaterialImages::default(), layout, channels)
+    }
+
+    pub fn with_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material: &Material,
+        images: MaterialImages,
+        layout: &wgpu::BindGroupLayout,
+        channels: &[&Texture],
+    ) -> anyhow::Result<Self> {
+        let label = format!("material {}", material.name);
+        let (diffuse, _) = load_map(device, queue, &material.diffuse_map, images.diffuse, true, [255, 255, 255, 255], &label)?;
+        //a flat tangent space normal
+        let (bump, _) = load_map(device, queue, &material.bump_map, images.bump, false, [128, 128, 255, 255], &label)?;
+        let (specular, _) = load_map(device, queue, &material.specular_map, images.specular, false, [255, 255, 255, 255], &label)?;
+        let (alpha, has_alpha_map) = load_map(device, queue, &material.alpha_map, images.alpha, false, [255, 255, 255, 255], &label)?;
+
+        let uniforms = material.uniforms();
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("material uniform buffer"),
+                contents: bytemuck::cast_slice(&[uniforms]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+        ];
+        entries.extend(texture::texture_bind_group_entries(&diffuse, 1));
+        entries.extend(texture::texture_bind_group_entries(&bump, 3));
+        entries.extend(texture::texture_bind_group_entries(&specular, 5));
+        entries.extend(texture::texture_bind_group_entries(&alpha, 7));
+        for (i, channel) in channels.iter().enumerate() {
+            entries.extend(texture::texture_bind_group_entries(channel, FIRST_CHANNEL_BINDING + i as u32 * 2));
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&label),
+            layout,
+            entries: entries.as_slice(),
+        });
+
+        Ok(Self {
+            uniforms,
+            has_alpha_map,
+            uniform_buffer,
+            bind_group,
+        })
+    }
+
+    //d below 1 or a map_d, these have to be blended and drawn after the opaque ones
+    pub fn translucent(self: &Self) -> bool {
+        self.uniforms.color[3] < 1.0 || self.has_alpha_map
+    }
+
+    //after changing uniforms, for animating material values
+    pub fn push_uniforms(self: &Self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniforms]));
+    }
+}
+
+//Loads the mtllib of an obj file, a missing or broken library is reported and gives no materials
+pub fn load_material_library(path: &str) -> HashMap<String, Material> {
+    match Material::parse_mtl_file(path) {
+        Ok(materials) => materials,
+        Err(error) => {
+            println!("failed to load material library {path} : {error}");
+            HashMap::new()
+        }
+    }
+}
@@ -0,0 +1,431 @@
+/*
+Binary cache for parsed obj files, so startup does not have to parse the dance frames as text every time.
+load_obj reads art/foo.bvmesh next to art/foo.obj when it is up to date, and otherwise parses the obj
+and writes the cache. A cache is up to date when every source file (the obj and its mtllibs) has the
+recorded length and mtime, or, when only the mtime changed, the recorded hash of their contents.
+
+Everything in the file is 4 byte aligned and in the byte order of the machine that wrote it, so vertex and index
+data are read straight into the vecs the Mesh keeps, through bytemuck, without converting or copying them again.
+The cache is machine local, one written with the other byte order is rejected by the byte order word.
+  header : magic, version, byte order word, parser version, vertex stride, attribute count, source count, material count,
+           mesh count, content hash (2 words)
+  attributes : component count and byte offset per attribute, has to match ObjLoaderRealtimeVertex::ATTRIBUTES
+  sources : path, length (2 words), mtime seconds (2 words), mtime nanoseconds
+  materials : name, Ka, Kd, Ks, Ke, Ns, Ni, d, illum, then the diffuse, bump, specular and alpha map paths
+  meshes : name, vertex count, index width (2 or 4), index count, submesh count,
+           submeshes (material, start, end), vertices, indices
+Strings are a byte length and utf8 padded to 4 bytes, optional strings have a 0/1 word in front.
+The parser version is obj_parser::OUTPUT_VERSION, so a change to what the parser makes of an obj
+(dedup, triangulation, normals, tangents) invalidates caches of sources that did not change.
+Mesh::vertices and Mesh::feces are left empty, the renderers only use real_verts and faces.
+ */
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use crate::nocmp::bounds::{Aabb, BoundingSphere};
+use crate::nocmp::material::Material;
+use crate::nocmp::obj_parser::{self, Mesh, ObjLoaderRealtimeVertex, ObjParseError, SubMesh};
+
+const MAGIC: [u8;8] = *b"BVMESH\0\0";
+//bump when the layout of the file changes
+const VERSION: u32 = 2;
+//reads back as 0x04030201 when the cache was written with the other byte order
+const BYTE_ORDER: u32 = 0x01020304;
+
+pub fn cache_path(obj_path: &str) -> PathBuf {
+    Path::new(obj_path).with_extension("bvmesh")
+}
+
+//Drop in for Mesh::parse_from_file that goes through the cache. A cache that can not be read or
+//written is reported and the obj is parsed as usual.
+pub fn load_obj(obj_path: &str) -> Result<HashMap<String, Mesh>, ObjParseError> {
+    let cache = cache_path(obj_path);
+    if cache.exists() {
+        match read_cache(&cache) {
+            Ok((meshes, true)) => return Ok(meshes),
+            Ok((meshes, false)) => {
+                //same contents, only touched, store the new mtimes so the next start skips hashing
+                if let Err(error) = write_cache(&cache, obj_path, &meshes) {
+                    println!("could not update mesh cache {} : {error}", cache.display());
+                }
+                return Ok(meshes);
+            }
+            Err(error) => println!("parsing {obj_path}, mesh cache {} not used : {error}", cache.display()),
+        }
+    }
+    let meshes = Mesh::parse_from_file(obj_path)?;
+    if let Err(error) = write_cache(&cache, obj_path, &meshes) {
+        println!("could not write mesh cache {} : {error}", cache.display());
+    }
+    Ok(meshes)
+}
+
+struct Source {
+    path: String,
+    length: u64,
+    seconds: u64,
+    nanoseconds: u32,
+}
+
+impl Source {
+    fn from_path(path: &str) -> Result<Self> {
+        let metadata = fs::metadata(path).with_context(|| format!("{path} is missing"))?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok(Source {
+            path: path.to_string(),
+            length: metadata.len(),
+            seconds: modified.as_secs(),
+            nanoseconds: modified.subsec_nanos(),
+        })
+    }
+
+    fn matches(self: &Self, other: &Source) -> bool {
+        self.length == other.length && self.seconds == other.seconds && self.nanoseconds == other.nanoseconds
+    }
+}
+
+//The obj and the material libraries it pulls in, resolved the same way the parser does
+fn source_paths(obj_path: &str) -> Result<Vec<String>> {
+    let text = fs::read_to_string(obj_path)?;
+    let directory = Path::new(obj_path).parent().unwrap_or(Path::new(""));
+    let mut paths = vec![obj_path.to_string()];
+    for line in text.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.first() == Some(&"mtllib") {
+            for library in &parts[1..] {
+                paths.push(directory.join(library).to_string_lossy().to_string());
+            }
+        }
+    }
+    Ok(paths)
+}
+
+//FNV-1a over the contents of all sources, a missing mtl counts as empty like it does for the parser
+fn content_hash(paths: &[String]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for path in paths {
+        for byte in fs::read(path).unwrap_or_default() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        //separator, so moving bytes between files changes the hash
+        hash ^= 0xff;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+struct Writer {
+    words: Vec<u32>,
+}
+
+impl Writer {
+    fn u32(self: &mut Self, value: u32) {
+        self.words.push(value);
+    }
+
+    fn u64(self: &mut Self, value: u64) {
+        self.u32(value as u32);
+        self.u32((value >> 32) as u32);
+    }
+
+    fn f32s(self: &mut Self, values: &[f32]) {
+        self.words.extend(values.iter().map(|value| value.to_bits()));
+    }
+
+    fn bytes(self: &mut Self, bytes: &[u8]) {
+        let mut padded = bytes.to_vec();
+        padded.resize((bytes.len() + 3) / 4 * 4, 0);
+        self.words.extend(padded.chunks_exact(4).map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]])));
+    }
+
+    fn string(self: &mut Self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes(value.as_bytes());
+    }
+
+    fn optional_string(self: &mut Self, value: &Option<String>) {
+        match value {
+            Some(value) => {
+                self.u32(1);
+                self.string(value);
+            }
+            None => self.u32(0),
+        }
+    }
+}
+
+struct Reader<R: Read> {
+    source: R,
+    //bytes left in the file, so a broken count fails before anything is allocated for it
+    remaining: u64,
+    position: u64,
+}
+
+impl<R: Read> Reader<R> {
+    fn read_into(self: &mut Self, bytes: &mut [u8]) -> Result<()> {
+        if bytes.len() as u64 > self.remaining {
+            bail!("file ends early, wanted {} bytes at offset {}", bytes.len(), self.position);
+        }
+        self.source.read_exact(bytes)?;
+        self.remaining -= bytes.len() as u64;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    //skips to the next 4 byte boundary after length bytes
+    fn padding(self: &mut Self, length: usize) -> Result<()> {
+        let mut padding = [0u8;3];
+        self.read_into(&mut padding[..(4 - length % 4) % 4])
+    }
+
+    fn u32(self: &mut Self) -> Result<u32> {
+        let mut word = [0u8;4];
+        self.read_into(&mut word)?;
+        Ok(u32::from_ne_bytes(word))
+    }
+
+    fn u64(self: &mut Self) -> Result<u64> {
+        Ok(self.u32()? as u64 | (self.u32()? as u64) << 32)
+    }
+
+    fn f32s<const N: usize>(self: &mut Self) -> Result<[f32;N]> {
+        let mut values = [0.0;N];
+        for value in values.iter_mut() {
+            *value = f32::from_bits(self.u32()?);
+        }
+        Ok(values)
+    }
+
+    fn bytes(self: &mut Self, length: usize) -> Result<Vec<u8>> {
+        Ok(self.vec::<u8>(length)?)
+    }
+
+    fn string(self: &mut Self) -> Result<String> {
+        let length = self.u32()? as usize;
+        Ok(String::from_utf8(self.bytes(length)?)?)
+    }
+
+    fn optional_string(self: &mut Self) -> Result<Option<String>> {
+        match self.u32()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.string()?)),
+        }
+    }
+
+    //Read from the file straight into the returned vec, which has the alignment of T
+    fn vec<T: bytemuck::Pod>(self: &mut Self, count: usize) -> Result<Vec<T>> {
+        let length = count.checked_mul(std::mem::size_of::<T>()).filter(|length| *length as u64 <= self.remaining);
+        let Some(length) = length else {
+            bail!("file ends early, wanted {count} values at offset {}", self.position);
+        };
+        let mut values = vec![T::zeroed(); count];
+        self.read_into(bytemuck::cast_slice_mut(values.as_mut_slice()))?;
+        self.padding(length)?;
+        Ok(values)
+    }
+}
+
+pub fn write_cache(cache: &Path, obj_path: &str, meshes: &HashMap<String, Mesh>) -> Result<()> {
+    let sources = source_paths(obj_path)?;
+    //every mesh of a file shares the materials of the file
+    let materials = meshes.values().next().map(|mesh| mesh.materials.clone()).unwrap_or_default();
+
+    let mut writer = Writer { words: Vec::new() };
+    writer.bytes(&MAGIC);
+    writer.u32(VERSION);
+    writer.u32(BYTE_ORDER);
+    writer.u32(obj_parser::OUTPUT_VERSION);
+    writer.u32(std::mem::size_of::<ObjLoaderRealtimeVertex>() as u32);
+    writer.u32(ObjLoaderRealtimeVertex::ATTRIBUTES.len() as u32);
+    writer.u32(sources.len() as u32);
+    writer.u32(materials.len() as u32);
+    writer.u32(meshes.len() as u32);
+    writer.u64(content_hash(&sources));
+
+    for [components, offset] in ObjLoaderRealtimeVertex::ATTRIBUTES {
+        writer.u32(components);
+        writer.u32(offset);
+    }
+
+    for path in &sources {
+        //an mtllib that does not exist is recorded as empty, so adding it later invalidates the cache
+        let source = Source::from_path(path).unwrap_or(Source { path: path.clone(), length: 0, seconds: 0, nanoseconds: 0 });
+        writer.string(&source.path);
+        writer.u64(source.length);
+        writer.u64(source.seconds);
+        writer.u32(source.nanoseconds);
+    }
+
+    for material in materials.values() {
+        writer.string(&material.name);
+        writer.f32s(&material.ambient);
+        writer.f32s(&material.diffuse);
+        writer.f32s(&material.specular);
+        writer.f32s(&material.emissive);
+        writer.f32s(&[material.shininess, material.optical_density, material.dissolve]);
+        writer.u32(material.illumination);
+        writer.optional_string(&material.diffuse_map);
+        writer.optional_string(&material.bump_map);
+        writer.optional_string(&material.specular_map);
+        writer.optional_string(&material.alpha_map);
+    }
+
+    for (name, mesh) in meshes {
+        let vertex_count = mesh.real_verts.len() as u32;
+        let index_width: u32 = if vertex_count <= u16::MAX as u32 + 1 { 2 } else { 4 };
+        writer.string(name);
+        writer.u32(vertex_count);
+        writer.u32(index_width);
+        writer.u32(mesh.faces.len() as u32);
+        writer.u32(mesh.submeshes.len() as u32);
+        for submesh in &mesh.submeshes {
+            writer.optional_string(&submesh.material);
+            writer.u32(submesh.index_range.start);
+            writer.u32(submesh.index_range.end);
+        }
+        writer.bytes(bytemuck::cast_slice(mesh.real_verts.as_slice()));
+        if index_width == 2 {
+            let indices: Vec<u16> = mesh.faces.iter().map(|index| *index as u16).collect();
+            writer.bytes(bytemuck::cast_slice(indices.as_slice()));
+        } else {
+            writer.bytes(bytemuck::cast_slice(mesh.faces.as_slice()));
+        }
+    }
+
+    //write next to it and rename, so a crash never leaves half a cache behind
+    let temporary = cache.with_extension("bvmesh.tmp");
+    fs::write(&temporary, bytemuck::cast_slice::<u32, u8>(writer.words.as_slice()))?;
+    fs::rename(&temporary, cache)?;
+    Ok(())
+}
+
+//The meshes of a cache file, and whether the recorded mtimes still match.
+//Fails when the cache is stale, broken or from another version.
+pub fn read_cache(cache: &Path) -> Result<(HashMap<String, Mesh>, bool)> {
+    let file = File::open(cache)?;
+    let length = file.metadata()?.len();
+    if length % 4 != 0 {
+        bail!("size is not a multiple of 4");
+    }
+    let mut reader = Reader { source: BufReader::new(file), remaining: length, position: 0 };
+
+    if reader.bytes(MAGIC.len())?.as_slice() != MAGIC {
+        bail!("not a bvmesh file");
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        bail!("version {version}, expected {VERSION}");
+    }
+    if reader.u32()? != BYTE_ORDER {
+        bail!("written with another byte order");
+    }
+    let parser_version = reader.u32()?;
+    if parser_version != obj_parser::OUTPUT_VERSION {
+        bail!("made by parser version {parser_version}, expected {}", obj_parser::OUTPUT_VERSION);
+    }
+    let stride = reader.u32()?;
+    let attribute_count = reader.u32()? as usize;
+    let source_count = reader.u32()? as usize;
+    let material_count = reader.u32()? as usize;
+    let mesh_count = reader.u32()? as usize;
+    let hash = reader.u64()?;
+
+    let attributes = reader.vec::<[u32;2]>(attribute_count)?;
+    if stride as usize != std::mem::size_of::<ObjLoaderRealtimeVertex>() || attributes.as_slice() != ObjLoaderRealtimeVertex::ATTRIBUTES {
+        bail!("written with another vertex layout");
+    }
+
+    let mut source_paths = Vec::with_capacity(source_count);
+    let mut mtimes_match = true;
+    for _ in 0..source_count {
+        let recorded = Source {
+            path: reader.string()?,
+            length: reader.u64()?,
+            seconds: reader.u64()?,
+            nanoseconds: reader.u32()?,
+        };
+        mtimes_match &= match Source::from_path(&recorded.path) {
+            Ok(current) => current.matches(&recorded),
+            //still missing is fine, the obj parser ignores missing material libraries too
+            Err(_) => recorded.length == 0 && recorded.seconds == 0,
+        };
+        source_paths.push(recorded.path);
+    }
+    if !mtimes_match && content_hash(&source_paths) != hash {
+        bail!("sources changed");
+    }
+
+    let mut materials = HashMap::with_capacity(material_count);
+    for _ in 0..material_count {
+        let name = reader.string()?;
+        let ambient = reader.f32s::<3>()?;
+        let diffuse = reader.f32s::<3>()?;
+        let specular = reader.f32s::<3>()?;
+        let emissive = reader.f32s::<3>()?;
+        let [shininess, optical_density, dissolve] = reader.f32s::<3>()?;
+        let material = Material {
+            name: name.clone(),
+            ambient,
+            diffuse,
+            specular,
+            emissive,
+            shininess,
+            optical_density,
+            dissolve,
+            illumination: reader.u32()?,
+            diffuse_map: reader.optional_string()?,
+            bump_map: reader.optional_string()?,
+            specular_map: reader.optional_string()?,
+            alpha_map: reader.optional_string()?,
+        };
+        materials.insert(name, material);
+    }
+
+    let mut meshes = HashMap::with_capacity(mesh_count);
+    for _ in 0..mesh_count {
+        let name = reader.string()?;
+        let vertex_count = reader.u32()? as usize;
+        let index_width = reader.u32()?;
+        let index_count = reader.u32()? as usize;
+        let submesh_count = reader.u32()? as usize;
+        let mut submeshes = Vec::with_capacity(submesh_count);
+        for _ in 0..submesh_count {
+            let material = reader.optional_string()?;
+            let start = reader.u32()?;
+            let end = reader.u32()?;
+            //the ranges are drawn as they are, a range past the indices would only fail later on the gpu
+            if start > end || end as usize > index_count {
+                bail!("mesh {name} has a submesh range {start}..{end} outside its {index_count} indices");
+            }
+            submeshes.push(SubMesh { material, index_range: start..end });
+        }
+        let real_verts = reader.vec::<ObjLoaderRealtimeVertex>(vertex_count)?;
+        let faces: Vec<u32> = match index_width {
+            2 => reader.vec::<u16>(index_count)?.iter().map(|index| *index as u32).collect(),
+            4 => reader.vec::<u32>(index_count)?,
+            width => bail!("mesh {name} has {width} byte indices"),
+        };
+        if index_count % 3 != 0 || faces.iter().any(|index| *index as usize >= vertex_count) {
+            bail!("mesh {name} has broken indices");
+        }
+
+        let mut mesh = Mesh {
+            vertices: Vec::new(),
+            real_verts,
+            feces: Vec::new(),
+            faces,
+            submeshes,
+            materials: materials.clone(),
+            bounds: Aabb::EMPTY,
+            bounding_sphere: BoundingSphere::EMPTY,
+        };
+        mesh.compute_bounds();
+        meshes.insert(name, mesh);
+    }
+    Ok((meshes, mtimes_match))
+}
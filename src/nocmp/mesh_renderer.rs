@@ -0,0 +1,608 @@
+/*
+Meshes as plain gpu buffers, drawn through pipelines that are shared between them.
+
+A GpuMesh is the vertex and index buffers of a Mesh with its materials, nothing else.
+MeshRenderer owns the bind group layouts, compiles each shader once (keyed by its label, which include_wgsl sets
+to the file name) and makes a pipeline the first time a PipelineKey (shader, vertex layout, render state) is drawn.
+render takes a list of MeshDraw, binds each pipeline once and issues one draw per mesh, the model matrices of
+all the draws go in one uniform buffer that group 2 reads at a dynamic offset.
+Group 2 depends on the vertex layout, the model matrix is always at binding 0. AnimatedMesh and SkinnedMesh bind their
+own group 2 (animation texture, joint matrices) and draw with pipelines from here, but not through render.
+Submeshes with a translucent material (d below 1 or a map_d) are drawn after all the opaque ones, in draw order,
+with the translucent() variant of the draw's key: alpha blended and without depth writes.
+Call begin_frame once per frame before the first render, so the draws of the frame get their own model matrices.
+ */
+
+use anyhow::*;
+use std::collections::HashMap;
+use std::ops::Range;
+use wgpu::util::DeviceExt;
+use wgpu::StoreOp;
+use crate::nocmp;
+use crate::nocmp::material::{GpuMaterial, Material};
+use crate::nocmp::instanced_mesh::InstanceData;
+use crate::nocmp::gltf_loader::SkinnedVertex;
+use crate::nocmp::obj_parser::{Mesh, ObjLoaderRealtimeVertex};
+use crate::nocmp::bounds::{Aabb, BoundingSphere, Frustum};
+
+//has to match ModelUniforms in obj_test.wgsl, group 2 binding 0 of every vertex layout
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ModelUniform {
+    pub model_matrix: [[f32; 4]; 4],
+}
+
+//the real_verts of a Mesh, position, normal, uv and tangent at locations 0 to 3
+fn mesh_vertex_desc() -> wgpu::VertexBufferLayout<'static> {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Float32x4];
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<ObjLoaderRealtimeVertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &ATTRIBUTES,
+    }
+}
+
+//vertex buffers the pipeline reads, and the vertex entry point in the shader that goes with them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VertexLayout {
+    //the mesh vertices, vs_main
+    Mesh,
+    //the mesh vertices and InstanceData, vs_instanced
+    Instanced,
+    //no vertex buffers, vs_animated reads the vertices from the animation texture in group 2
+    Animated,
+    //SkinnedVertex, vs_skinned, with the joint matrices in group 2
+    Skinned,
+}
+
+impl VertexLayout {
+    fn entry_point(self: Self) -> &'static str {
+        match self {
+            VertexLayout::Mesh => "vs_main",
+            VertexLayout::Instanced => "vs_instanced",
+            VertexLayout::Animated => "vs_animated",
+            VertexLayout::Skinned => "vs_skinned",
+        }
+    }
+
+    fn buffers(self: Self) -> Vec<wgpu::VertexBufferLayout<'static>> {
+        match self {
+            VertexLayout::Mesh => vec![mesh_vertex_desc()],
+            VertexLayout::Instanced => vec![mesh_vertex_desc(), InstanceData::desc()],
+            VertexLayout::Animated => vec![],
+            VertexLayout::Skinned => vec![SkinnedVertex::desc()],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Replace,
+    Alpha,
+}
+
+impl BlendMode {
+    fn blend_state(self: Self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Replace => wgpu::BlendState::REPLACE,
+            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RenderState {
+    pub blend: BlendMode,
+    pub cull_mode: Option<wgpu::Face>,
+    pub depth_write: bool,
+    pub depth_compare: wgpu::CompareFunction,
+}
+
+//opaque and back face culled
+impl Default for RenderState {
+    fn default() -> Self {
+        Self {
+            blend: BlendMode::Replace,
+            cull_mode: Some(wgpu::Face::Back),
+            depth_write: true,
+            depth_compare: wgpu::CompareFunction::Less,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub shader: ShaderId,
+    pub vertex_layout: VertexLayout,
+    pub state: RenderState,
+}
+
+impl PipelineKey {
+    pub fn new(shader: ShaderId, vertex_layout: VertexLayout) -> Self {
+        Self { shader, vertex_layout, state: RenderState::default() }
+    }
+
+    pub fn with_state(self: Self, state: RenderState) -> Self {
+        Self { state, ..self }
+    }
+
+    //what the translucent materials of a draw with this key are drawn with,
+    //alpha blended unless the key blends already, and no depth writes so they do not hide each other
+    pub fn translucent(self: Self) -> Self {
+        let blend = if self.state.blend == BlendMode::Replace { BlendMode::Alpha } else { self.state.blend };
+        self.with_state(RenderState { blend, depth_write: false, ..self.state })
+    }
+}
+
+pub struct GpuMesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    //one per material used by the mesh, the submeshes index into it
+    pub materials: Vec<GpuMaterial>,
+    submeshes: Vec<(Range<u32>, usize)>,
+    //of the mesh, before any model matrix
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
+}
+
+impl GpuMesh {
+    //materials are made with the material layout of renderer, so the mesh can only be drawn by that renderer
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, renderer: &MeshRenderer, mesh: &Mesh) -> Result<Self> {
+        //a material per usemtl name, faces without one (or with a name missing from the mtllib) get the default material
+        let mut material_names: Vec<Option<String>> = Vec::new();
+        let mut submeshes = Vec::new();
+        for submesh in &mesh.submeshes {
+            let name = submesh.material.clone().filter(|name| mesh.materials.contains_key(name));
+            let material_index = match material_names.iter().position(|known| *known == name) {
+                Some(index) => index,
+                None => {
+                    material_names.push(name);
+                    material_names.len() - 1
+                }
+            };
+            submeshes.push((submesh.index_range.clone(), material_index));
+        }
+        let mut materials = Vec::new();
+        for name in &material_names {
+            let default_material = Material::default();
+            let material = name.as_ref().map(|name| &mesh.materials[name]).unwrap_or(&default_material);
+            materials.push(GpuMaterial::new(device, queue, material, &renderer.bind_group_layout_1, &[])?);
+        }
+
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("gpu mesh vertex buffer"),
+                contents: bytemuck::cast_slice(mesh.real_verts.as_slice()),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("gpu mesh index buffer"),
+                contents: bytemuck::cast_slice(mesh.faces.as_slice()),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            materials,
+            submeshes,
+            bounds: mesh.bounds,
+            bounding_sphere: mesh.bounding_sphere,
+        })
+    }
+}
+
+//One mesh drawn once (or once per instance) with a pipeline
+pub struct MeshDraw<'a> {
+    pub mesh: &'a GpuMesh,
+    pub pipeline: PipelineKey,
+    pub model_matrix: cgmath::Matrix4<f32>,
+    //the per instance buffer and how many of its instances to draw, for VertexLayout::Instanced
+    pub instances: Option<(&'a wgpu::Buffer, u32)>,
+}
+
+impl<'a> MeshDraw<'a> {
+    pub fn new(mesh: &'a GpuMesh, pipeline: PipelineKey, model_matrix: cgmath::Matrix4<f32>) -> Self {
+        Self { mesh, pipeline, model_matrix, instances: None }
+    }
+
+    pub fn with_instances(self: Self, instance_buffer: &'a wgpu::Buffer, instance_count: u32) -> Self {
+        Self { instances: Some((instance_buffer, instance_count)), ..self }
+    }
+
+    //the mesh bounds moved by model_matrix against frustum, instanced draws are culled per instance before (InstancedMesh::cull)
+    pub fn visible(self: &Self, frustum: &Frustum) -> bool {
+        self.instances.is_some() || frustum.intersects_aabb(&self.mesh.bounds.transformed(&self.model_matrix))
+    }
+}
+
+pub struct MeshRenderer {
+    surface_format: wgpu::TextureFormat,
+    sample_count: u32,
+    bind_group_layout_1: wgpu::BindGroupLayout,
+    bind_group_layout_2: wgpu::BindGroupLayout,
+    animated_bind_group_layout_2: wgpu::BindGroupLayout,
+    skinned_bind_group_layout_2: wgpu::BindGroupLayout,
+    pipeline_layouts: HashMap<VertexLayout, wgpu::PipelineLayout>,
+    bind_group_0: wgpu::BindGroup,
+    shaders: Vec<wgpu::ShaderModule>,
+    shader_ids: HashMap<String, ShaderId>,
+    pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+    //room for model_capacity model matrices, model_stride apart so every one is at a valid dynamic offset
+    model_uniform_buffer: wgpu::Buffer,
+    model_stride: u64,
+    model_capacity: usize,
+    //model matrices handed out since begin_frame
+    models_used: usize,
+    bind_group_2: wgpu::BindGroup,
+}
+
+impl MeshRenderer {
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        toylike_uniforms: &nocmp::shadertoy_buffer::ShaderToyUniforms,
+        surface_config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        camera_uniform_buffer: &wgpu::Buffer,
+        lights_uniform_buffer: &wgpu::Buffer,
+    ) -> Result<Self> {
+        let uniform_entry = |binding: u32, visibility: wgpu::ShaderStages, has_dynamic_offset: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        //toy uniforms, camera and lights, then materials, then the model matrix
+        let bind_group_layout_0 = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh renderer bind group layout 0"),
+            entries: &[
+                uniform_entry(0, wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT, false),
+                uniform_entry(1, wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT, false),
+                uniform_entry(2, wgpu::ShaderStages::FRAGMENT, false),
+            ],
+        });
+        let bind_group_layout_1 = GpuMaterial::create_bind_group_layout(device, queue, &[])?;
+        let bind_group_layout_2 = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh renderer bind group layout 2"),
+            entries: &[
+                uniform_entry(0, wgpu::ShaderStages::VERTEX, true),
+            ],
+        });
+        //model matrix, animation uniforms and the animation texture
+        let animated_bind_group_layout_2 = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh renderer animated bind group layout 2"),
+            entries: &[
+                uniform_entry(0, wgpu::ShaderStages::VERTEX, false),
+                uniform_entry(1, wgpu::ShaderStages::VERTEX, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        //model matrix and the joint matrices
+        let skinned_bind_group_layout_2 = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh renderer skinned bind group layout 2"),
+            entries: &[
+                uniform_entry(0, wgpu::ShaderStages::VERTEX, false),
+                uniform_entry(3, wgpu::ShaderStages::VERTEX, false),
+            ],
+        });
+
+        let mut pipeline_layouts = HashMap::new();
+        for (vertex_layout, layout_2) in [
+            (VertexLayout::Mesh, &bind_group_layout_2),
+            (VertexLayout::Instanced, &bind_group_layout_2),
+            (VertexLayout::Animated, &animated_bind_group_layout_2),
+            (VertexLayout::Skinned, &skinned_bind_group_layout_2),
+        ] {
+            pipeline_layouts.insert(vertex_layout, device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("mesh renderer pipeline layout"),
+                bind_group_layouts: &[
+                    &bind_group_layout_0,
+                    &bind_group_layout_1,
+                    layout_2,
+                ],
+                push_constant_ranges: &[],
+            }));
+        }
+
+        let bind_group_0 = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("mesh renderer bind group 0"),
+            layout: &bind_group_layout_0,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: toylike_uniforms.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: camera_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: lights_uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let model_size = std::mem::size_of::<ModelUniform>() as u64;
+        let model_stride = model_size.div_ceil(alignment) * alignment;
+        let model_capacity = 16;
+        let (model_uniform_buffer, bind_group_2) = Self::create_model_uniforms(device, &bind_group_layout_2, model_stride, model_capacity);
+
+        Ok(Self {
+            surface_format: surface_config.format,
+            sample_count,
+            bind_group_layout_1,
+            bind_group_layout_2,
+            animated_bind_group_layout_2,
+            skinned_bind_group_layout_2,
+            pipeline_layouts,
+            bind_group_0,
+            shaders: Vec::new(),
+            shader_ids: HashMap::new(),
+            pipelines: HashMap::new(),
+            model_uniform_buffer,
+            model_stride,
+            model_capacity,
+            models_used: 0,
+            bind_group_2,
+        })
+    }
+
+    fn create_model_uniforms(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        stride: u64,
+        capacity: usize,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("mesh renderer model uniform buffer"),
+            size: stride * capacity as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("mesh renderer bind group 2"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<ModelUniform>() as u64),
+                    }),
+                },
+            ],
+        });
+        (buffer, bind_group)
+    }
+
+    //Compiles the shader the first time its label is seen, later calls with the same label get the same id back.
+    //A shader without a label is compiled every time
+    pub fn shader(self: &mut Self, device: &wgpu::Device, shader_descriptor: wgpu::ShaderModuleDescriptor) -> ShaderId {
+        if let Some(id) = shader_descriptor.label.and_then(|label| self.shader_ids.get(label)) {
+            return *id;
+        }
+        let id = ShaderId(self.shaders.len());
+        if let Some(label) = shader_descriptor.label {
+            self.shader_ids.insert(label.to_string(), id);
+        }
+        self.shaders.push(device.create_shader_module(shader_descriptor));
+        id
+    }
+
+    //for the materials of meshes that draw themselves with pipelines from here
+    pub fn material_layout(self: &Self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout_1
+    }
+
+    //the model matrix at binding 0 and whatever else the vertex shader of the layout reads
+    pub fn bind_group_layout_2(self: &Self, vertex_layout: VertexLayout) -> &wgpu::BindGroupLayout {
+        match vertex_layout {
+            VertexLayout::Mesh | VertexLayout::Instanced => &self.bind_group_layout_2,
+            VertexLayout::Animated => &self.animated_bind_group_layout_2,
+            VertexLayout::Skinned => &self.skinned_bind_group_layout_2,
+        }
+    }
+
+    //toy uniforms, camera and lights
+    pub fn bind_group_0(self: &Self) -> &wgpu::BindGroup {
+        &self.bind_group_0
+    }
+
+    //the key has to be prepared first
+    pub fn pipeline(self: &Self, key: PipelineKey) -> &wgpu::RenderPipeline {
+        &self.pipelines[&key]
+    }
+
+    //makes the pipeline now instead of at its first draw
+    pub fn prepare(self: &mut Self, device: &wgpu::Device, key: PipelineKey) {
+        if self.pipelines.contains_key(&key) {
+            return;
+        }
+        let shader = &self.shaders[key.shader.0];
+        let buffers = key.vertex_layout.buffers();
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("mesh renderer pipeline"),
+            layout: Some(&self.pipeline_layouts[&key.vertex_layout]),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: key.vertex_layout.entry_point(),
+                compilation_options: Default::default(),
+                buffers: buffers.as_slice(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.surface_format,
+                    blend: Some(key.state.blend.blend_state()),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: key.state.cull_mode,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: nocmp::texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: key.state.depth_write,
+                depth_compare: key.state.depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: self.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+        self.pipelines.insert(key, pipeline);
+    }
+
+    //key, and the translucent variant of it when any of materials is translucent
+    pub fn prepare_for_materials(self: &mut Self, device: &wgpu::Device, key: PipelineKey, materials: &[GpuMaterial]) {
+        self.prepare(device, key);
+        if materials.iter().any(|material| material.translucent()) {
+            self.prepare(device, key.translucent());
+        }
+    }
+
+    //the model matrices of the last frame can be overwritten
+    pub fn begin_frame(self: &mut Self) {
+        self.models_used = 0;
+    }
+
+    //Draws in one pass, grouped by pipeline so each one is bound once. With clear the color and depth are cleared
+    //first (even without draws), without it the draws go on top of what is there.
+    //With a multisampled renderer, view and depth_view are the msaa targets and resolve_target is where the color ends up.
+    //Only for Mesh and Instanced keys, the other layouts need the group 2 of their mesh
+    pub fn render(
+        self: &mut Self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        depth_view: &wgpu::TextureView,
+        clear: Option<wgpu::Color>,
+        encoder: &mut wgpu::CommandEncoder,
+        draws: &[MeshDraw],
+    ) {
+        for draw in draws {
+            self.prepare_for_materials(device, draw.pipeline, &draw.mesh.materials);
+        }
+
+        //earlier passes of the frame still read the old buffer, the matrices after them go in a bigger one
+        if self.models_used + draws.len() > self.model_capacity {
+            self.model_capacity = (self.models_used + draws.len()).next_power_of_two();
+            (self.model_uniform_buffer, self.bind_group_2) =
+                Self::create_model_uniforms(device, &self.bind_group_layout_2, self.model_stride, self.model_capacity);
+        }
+        let first_model = self.models_used;
+        self.models_used += draws.len();
+        if !draws.is_empty() {
+            let stride = self.model_stride as usize;
+            let mut models = vec![0u8; stride * draws.len()];
+            for (i, draw) in draws.iter().enumerate() {
+                let model_uniforms = ModelUniform { model_matrix: draw.model_matrix.into() };
+                models[i * stride..i * stride + std::mem::size_of::<ModelUniform>()].copy_from_slice(bytemuck::bytes_of(&model_uniforms));
+            }
+            queue.write_buffer(&self.model_uniform_buffer, first_model as u64 * self.model_stride, models.as_slice());
+        }
+
+        //in order of the first draw with each pipeline, draws keep their order within a pipeline
+        let mut keys: Vec<PipelineKey> = Vec::new();
+        for draw in draws {
+            if !keys.contains(&draw.pipeline) {
+                keys.push(draw.pipeline);
+            }
+        }
+        let mut order: Vec<usize> = (0..draws.len()).collect();
+        order.sort_by_key(|i| keys.iter().position(|key| *key == draws[*i].pipeline));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("mesh renderer pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: if clear.is_some() { wgpu::LoadOp::Clear(1.0) } else { wgpu::LoadOp::Load },
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_bind_group(0, &self.bind_group_0, &[]);
+        let mut bound: Option<PipelineKey> = None;
+        //the opaque submeshes of every draw first, then the translucent ones on top, not sorted by distance
+        for translucent in [false, true] {
+            for &i in &order {
+                let draw = &draws[i];
+                let in_pass = |material_index: usize| draw.mesh.materials[material_index].translucent() == translucent;
+                let instance_count = draw.instances.map_or(1, |(_, instance_count)| instance_count);
+                if instance_count == 0 || !draw.mesh.submeshes.iter().any(|(_, material_index)| in_pass(*material_index)) {
+                    continue;
+                }
+                let key = if translucent { draw.pipeline.translucent() } else { draw.pipeline };
+                if bound != Some(key) {
+                    render_pass.set_pipeline(&self.pipelines[&key]);
+                    bound = Some(key);
+                }
+                let offset = ((first_model + i) as u64 * self.model_stride) as wgpu::DynamicOffset;
+                render_pass.set_bind_group(2, &self.bind_group_2, &[offset]);
+                render_pass.set_vertex_buffer(0, draw.mesh.vertex_buffer.slice(..));
+                if let Some((instance_buffer, _)) = draw.instances {
+                    render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+                }
+                render_pass.set_index_buffer(draw.mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                for (index_range, material_index) in &draw.mesh.submeshes {
+                    if in_pass(*material_index) {
+                        render_pass.set_bind_group(1, &draw.mesh.materials[*material_index].bind_group, &[]);
+                        render_pass.draw_indexed(index_range.clone(), 0, 0..instance_count);
+                    }
+                }
+            }
+        }
+    }
+}
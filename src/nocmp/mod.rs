@@ -1,10 +1,28 @@
 pub(crate) mod texture;
+pub(crate) mod texture_array;
+pub(crate) mod msaa;
+pub(crate) mod post_process;
+pub(crate) mod tonemapping;
+pub(crate) mod transition;
+pub(crate) mod color_grading;
+pub mod beat_clock;
+pub(crate) mod bcn;
 pub(crate) mod shadertoy_buffer;
-pub(crate) mod spline_test;
-pub(crate) mod obj_mesh_test;
+pub(crate) mod keyboard_texture;
+pub(crate) mod animated_mesh;
+pub(crate) mod skinned_mesh;
+pub(crate) mod mesh_renderer;
+pub(crate) mod instanced_mesh;
+pub(crate) mod text3d;
 pub mod spline_curves;
 pub mod camera;
 pub mod bindgrouperoo;
 pub mod obj_parser;
+pub mod tangents;
+pub mod bounds;
+pub mod lights;
+pub mod gltf_loader;
+pub mod mesh_cache;
+pub mod material;
 mod app_state;
 mod gui;
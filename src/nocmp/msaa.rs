@@ -0,0 +1,86 @@
+/*
+Multisampled targets for the passes that draw geometry (the meshes in render_scene).
+Those passes render into the multisampled color and depth targets and resolve into the real target
+(post chain or transition targets), fullscreen shadertoy passes keep drawing straight into the real target.
+With a sample count of 1 there is no color target and the passes draw directly, like before.
+ */
+use crate::nocmp::texture::Texture;
+
+pub const SUPPORTED_SAMPLE_COUNTS: [u32;4] = [1, 2, 4, 8];
+
+//The highest of 1/2/4/8 that is not above requested and works for every format as a resolvable render target.
+//Counts other than 1 and 4 need TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES on the device.
+pub fn clamp_sample_count(adapter: &wgpu::Adapter, device: &wgpu::Device, formats: &[wgpu::TextureFormat], requested: u32) -> u32 {
+    let supported = |format: &wgpu::TextureFormat, count: u32| {
+        let features = if device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+            adapter.get_texture_format_features(*format)
+        } else {
+            format.guaranteed_format_features(device.features())
+        };
+        let resolvable = format.is_depth_stencil_format()
+            || features.flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE);
+        features.flags.sample_count_supported(count) && (count == 1 || resolvable)
+    };
+    SUPPORTED_SAMPLE_COUNTS.iter()
+        .copied()
+        .filter(|count| *count <= requested.max(1))
+        .filter(|count| formats.iter().all(|format| supported(format, *count)))
+        .max()
+        .unwrap_or(1)
+}
+
+pub struct MsaaTargets {
+    sample_count: u32,
+    format: wgpu::TextureFormat,
+    color: Option<wgpu::TextureView>,
+    depth: Texture,
+}
+
+impl MsaaTargets {
+    //config gives the size and color format, sample_count should already be clamped with clamp_sample_count
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32) -> Self {
+        Self {
+            sample_count,
+            format: config.format,
+            color: Self::create_color(device, config.width, config.height, config.format, sample_count),
+            depth: Texture::create_multisampled_depth_texture(device, config, sample_count, "msaa depth texture"),
+        }
+    }
+
+    fn create_color(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, sample_count: u32) -> Option<wgpu::TextureView> {
+        if sample_count <= 1 {
+            return None;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("msaa color target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+
+    pub fn resize(self: &mut Self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        *self = Self::new(device, &wgpu::SurfaceConfiguration { format: self.format, ..config.clone() }, self.sample_count);
+    }
+
+    //The view and resolve target for a color attachment whose result should end up in target
+    pub fn color_attachment<'a>(self: &'a Self, target: &'a wgpu::TextureView) -> (&'a wgpu::TextureView, Option<&'a wgpu::TextureView>) {
+        match &self.color {
+            Some(color) => (color, Some(target)),
+            None => (target, None),
+        }
+    }
+
+    pub fn depth_view(self: &Self) -> &wgpu::TextureView {
+        &self.depth.view
+    }
+}
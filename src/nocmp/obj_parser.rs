@@ -2,6 +2,12 @@ use nalgebra::Vector3;
 use nalgebra::Vector2;
 use std::collections::HashMap;
 use std::error::Error;
+use std::ops::Range;
+use std::path::Path;
+use crate::nocmp::material;
+use crate::nocmp::material::Material;
+use crate::nocmp::tangents;
+use crate::nocmp::bounds::{Aabb, BoundingSphere};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
@@ -31,6 +37,26 @@ pub struct ObjLoaderRealtimeVertex {
     position: [f32;3],
     normal: [f32;3],
     uv: [f32;2],
+    //MikkTSpace tangent, w is the sign of the bitangent, bitangent = cross(normal, tangent.xyz) * w
+    tangent: [f32;4],
+}
+
+impl ObjLoaderRealtimeVertex {
+    //component count and byte offset of position, normal, uv and tangent, all f32
+    pub const ATTRIBUTES: [[u32;2];4] = [
+        [3, std::mem::offset_of!(ObjLoaderRealtimeVertex, position) as u32],
+        [3, std::mem::offset_of!(ObjLoaderRealtimeVertex, normal) as u32],
+        [2, std::mem::offset_of!(ObjLoaderRealtimeVertex, uv) as u32],
+        [4, std::mem::offset_of!(ObjLoaderRealtimeVertex, tangent) as u32],
+    ];
+
+    pub fn to_vertex(self: &Self) -> Vertex {
+        Vertex {
+            position: BVec3(Vector3::from(self.position)),
+            normal: BVec3(Vector3::from(self.normal)),
+            tex_coord: BVec2(Vector2::from(self.uv)),
+        }
+    }
 }
 
 #[repr(C)]
@@ -43,9 +69,9 @@ pub struct Face{
 /*
 MultiIndexing Faces contains multiple lists with index information
 so, to find the normal for a face a face, we go like
-n1  = normals[face.normal_indices[0]]
-n2  = normals[face.normal_indices[1]]
-n3  = normals[face.normal_indices[2]]
+n1  = normals[corners[face.corners.start].normal]
+n2  = normals[corners[face.corners.start+1].normal]
+n3  = normals[corners[face.corners.start+2].normal]
 
 but realtime 3D really likes it if our face has one index like this :
 vertex1 = vertices[indices[0]]
@@ -58,18 +84,42 @@ and so on..
  */
 #[derive(Debug)]
 pub struct MultiIndexingFace {
-    pub vertices: Vec<usize>, // indices of the vertices
-    pub normal_indices: Vec<usize>,
-    pub tex_coord_indices: Vec<usize>,
+    //range in the corners the face was read with. Tex coords and normals are on every corner or none
+    pub corners: Range<u32>,
     pub smoothing_group: Option<u32>,
+    //index of the name from the usemtl before the face
+    pub material: Option<u32>,
+}
+
+//One corner of a face, indices into the positions, texture coords and normals of the file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Corner {
+    pub position: u32,
+    pub tex_coord: Option<u32>,
+    pub normal: Option<u32>,
+}
+
+//A run of faces that use the same material, drawn with one draw call
+#[derive(Clone, Debug)]
+pub struct SubMesh {
+    pub material: Option<String>,
+    //range in Mesh::faces
+    pub index_range: Range<u32>,
 }
 
 #[derive(Debug)]
 pub struct Mesh {
+    //real_verts and faces in the old types, only filled by the parser (meshes from mesh_cache leave them empty)
     pub vertices: Vec<Vertex>,
     pub real_verts : Vec<ObjLoaderRealtimeVertex>,
     pub faces: Vec<u32>,
     pub feces: Vec<Face>,
+    pub submeshes: Vec<SubMesh>,
+    //everything from the mtllib of the file, shared by all objects in it
+    pub materials: HashMap<String, Material>,
+    //around real_verts, in the coordinates of the file, see compute_bounds
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
 }
 
 impl Mesh {
@@ -78,19 +128,471 @@ impl Mesh {
             vertices: Vec::new(),
             faces: Vec::new(),
             feces: Vec::new(),
-            real_verts: Vec::new()
+            real_verts: Vec::new(),
+            submeshes: Vec::new(),
+            materials: HashMap::new(),
+            bounds: Aabb::EMPTY,
+            bounding_sphere: BoundingSphere::EMPTY,
         }
     }
+
+    //Has to be called again when real_verts change
+    pub fn compute_bounds(self: &mut Self) {
+        let positions = self.real_verts.iter().map(|vertex| vertex.position);
+        self.bounds = Aabb::from_points(positions.clone());
+        self.bounding_sphere = BoundingSphere::from_points(positions);
+    }
+
+    //Puts the triangles of other after the ones already here,
+    //for objects and groups whose faces are split up by other objects in the file
+    fn append(self: &mut Self, other: Mesh) {
+        let vertex_offset = self.real_verts.len() as u32;
+        let index_offset = self.faces.len() as u32;
+        self.real_verts.extend(other.real_verts);
+        self.vertices.extend(other.vertices);
+        self.faces.extend(other.faces.iter().map(|index| index + vertex_offset));
+        self.feces.extend(other.feces.iter().map(|face| Face { face_indices: face.face_indices.map(|index| index + vertex_offset) }));
+        self.submeshes.extend(other.submeshes.into_iter().map(|submesh| SubMesh {
+            index_range: submesh.index_range.start + index_offset..submesh.index_range.end + index_offset,
+            ..submesh
+        }));
+        self.compute_bounds();
+    }
 }
 
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 use bytemuck::{Pod, Zeroable};
 
+//What went wrong while reading an obj file, line numbers start at 1
+#[derive(Debug)]
+pub enum ObjParseError {
+    Io(std::io::Error),
+    //a keyword without enough values after it, like "v 1.0 2.0"
+    MissingValue { line: usize, keyword: String },
+    InvalidNumber { line: usize, value: String },
+    //obj indices start at 1, 0 is never valid
+    ZeroIndex { line: usize },
+    //the index (as written in the file) points outside the data read so far
+    IndexOutOfRange { line: usize, index: i64, count: usize },
+    //a face needs at least 3 corners
+    TooFewVertices { line: usize, count: usize },
+}
+
+impl std::fmt::Display for ObjParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjParseError::Io(error) => write!(f, "{error}"),
+            ObjParseError::MissingValue { line, keyword } => write!(f, "line {line} : '{keyword}' is missing values"),
+            ObjParseError::InvalidNumber { line, value } => write!(f, "line {line} : '{value}' is not a number"),
+            ObjParseError::ZeroIndex { line } => write!(f, "line {line} : index 0 is not valid, obj indices start at 1"),
+            ObjParseError::IndexOutOfRange { line, index, count } => write!(f, "line {line} : index {index} is out of range, there are {count} elements"),
+            ObjParseError::TooFewVertices { line, count } => write!(f, "line {line} : a face needs 3 or more vertices, found {count}"),
+        }
+    }
+}
+
+impl Error for ObjParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjParseError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ObjParseError {
+    fn from(error: std::io::Error) -> Self {
+        ObjParseError::Io(error)
+    }
+}
+
+impl ObjParseError {
+    //errors from a chunk have line numbers local to the chunk
+    fn with_line_offset(self: Self, offset: usize) -> Self {
+        match self {
+            ObjParseError::Io(error) => ObjParseError::Io(error),
+            ObjParseError::MissingValue { line, keyword } => ObjParseError::MissingValue { line: line + offset, keyword },
+            ObjParseError::InvalidNumber { line, value } => ObjParseError::InvalidNumber { line: line + offset, value },
+            ObjParseError::ZeroIndex { line } => ObjParseError::ZeroIndex { line: line + offset },
+            ObjParseError::IndexOutOfRange { line, index, count } => ObjParseError::IndexOutOfRange { line: line + offset, index, count },
+            ObjParseError::TooFewVertices { line, count } => ObjParseError::TooFewVertices { line: line + offset, count },
+        }
+    }
+}
+
+fn parse_f32(value: Option<&str>, keyword: &str, line: usize) -> Result<f32, ObjParseError> {
+    let value = value.ok_or_else(|| ObjParseError::MissingValue { line, keyword: keyword.to_string() })?;
+    value.parse().map_err(|_| ObjParseError::InvalidNumber { line, value: value.to_string() })
+}
+
+fn parse_index(value: &str, line: usize) -> Result<i64, ObjParseError> {
+    value.parse().map_err(|_| ObjParseError::InvalidNumber { line, value: value.to_string() })
+}
+
+//A name that comes back later in the file adds its faces to the mesh it already has
+fn insert_mesh(meshes: &mut HashMap<String,Mesh>, name: String, mesh: Mesh) {
+    match meshes.get_mut(&name) {
+        Some(existing) => existing.append(mesh),
+        None => {
+            meshes.insert(name, mesh);
+        }
+    }
+}
+
+//Turns an obj index into a 0 based one, negative indices count back from the last element read so far
+fn resolve_index(index: i64, count: usize, line: usize) -> Result<u32, ObjParseError> {
+    let resolved = match index {
+        0 => return Err(ObjParseError::ZeroIndex { line }),
+        index if index > 0 => index - 1,
+        index => count as i64 + index,
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(ObjParseError::IndexOutOfRange { line, index, count });
+    }
+    Ok(resolved as u32)
+}
+
+//FxHash style hasher for the integer keys of the vertex deduplication, SipHash was most of the time spent there
+#[derive(Default)]
+struct IndexHasher(u64);
+
+impl IndexHasher {
+    fn add(self: &mut Self, value: u64) {
+        self.0 = (self.0.rotate_left(5) ^ value).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+impl std::hash::Hasher for IndexHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.add(*byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.add(value);
+    }
+
+    fn write_u128(&mut self, value: u128) {
+        self.add(value as u64);
+        self.add((value >> 64) as u64);
+    }
+}
+
+type IndexHashMap<K, V> = HashMap<K, V, std::hash::BuildHasherDefault<IndexHasher>>;
+
+//Bump when the same obj parses to different meshes (dedup, triangulation, normals, tangents..),
+//mesh_cache stores it and throws away caches made by another version
+pub const OUTPUT_VERSION: u32 = 2;
+
+//Files at least this big are parsed on several threads
+const PARALLEL_PARSE_MIN_BYTES: usize = 4 * 1024 * 1024;
+
+//A line that is more than data, in file order. Names borrow from the file
+enum Statement<'a> {
+    //the data counts are what the chunk had read before the face, for resolving negative indices
+    Face { line: usize, corners: Range<usize>, positions: usize, tex_coords: usize, normals: usize },
+    Object(&'a str),
+    Group(&'a str),
+    Smoothing(Option<u32>),
+    MaterialLibrary(&'a str),
+    UseMaterial(Option<&'a str>),
+}
+
+//What one thread got out of its part of the file. Line numbers and data counts are local to the chunk,
+//indices are left as written until every chunk is done
+struct ParsedChunk<'a> {
+    positions: Vec<BVec3>,
+    normals: Vec<BVec3>,
+    tex_coords: Vec<BVec2>,
+    //v, vt and vn of every face corner
+    corners: Vec<(i64, Option<i64>, Option<i64>)>,
+    statements: Vec<Statement<'a>>,
+    line_count: usize,
+    //the first line that could not be read, nothing after it is in the chunk
+    error: Option<ObjParseError>,
+}
+
+//The rest of the line after the keyword, for names that can contain spaces
+fn line_rest<'a>(line: &'a str, keyword: &str) -> &'a str {
+    line.trim_start_matches(|c: char| c.is_ascii_whitespace())[keyword.len()..].trim_matches(|c: char| c.is_ascii_whitespace())
+}
+
+//Splits source at line breaks into about count equally big chunks
+fn split_into_chunks(source: &str, count: usize) -> Vec<&str> {
+    let bytes = source.as_bytes();
+    let mut chunks = Vec::with_capacity(count);
+    let mut start = 0;
+    for i in 1..count {
+        let target = (source.len() * i / count).max(start);
+        let end = match bytes[target..].iter().position(|byte| *byte == b'\n') {
+            Some(newline) => target + newline + 1,
+            None => source.len(),
+        };
+        if end > start {
+            chunks.push(&source[start..end]);
+            start = end;
+        }
+    }
+    if start < source.len() || chunks.is_empty() {
+        chunks.push(&source[start..]);
+    }
+    chunks
+}
+
+impl<'a> ParsedChunk<'a> {
+    fn parse(chunk: &'a str) -> Self {
+        //a quick pass over the line starts to know how much to allocate
+        let (mut position_count, mut normal_count, mut tex_coord_count, mut face_count) = (0, 0, 0, 0);
+        for line in chunk.as_bytes().split(|byte| *byte == b'\n') {
+            match line {
+                [b'v', b' ' | b'\t', ..] => position_count += 1,
+                [b'v', b'n', ..] => normal_count += 1,
+                [b'v', b't', ..] => tex_coord_count += 1,
+                [b'f', b' ' | b'\t', ..] => face_count += 1,
+                _ => {}
+            }
+        }
+        let mut parsed = ParsedChunk {
+            positions: Vec::with_capacity(position_count),
+            normals: Vec::with_capacity(normal_count),
+            tex_coords: Vec::with_capacity(tex_coord_count),
+            corners: Vec::with_capacity(face_count * 4),
+            statements: Vec::with_capacity(face_count),
+            line_count: 0,
+            error: None,
+        };
+        for (line_index, line) in chunk.lines().enumerate() {
+            if let Err(error) = parsed.parse_line(line, line_index + 1) {
+                parsed.error = Some(error);
+                break;
+            }
+            parsed.line_count = line_index + 1;
+        }
+        parsed
+    }
+
+    fn parse_line(self: &mut Self, line: &'a str, line_number: usize) -> Result<(), ObjParseError> {
+        let mut tokens = line.split_ascii_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => return Ok(()),
+        };
+        match keyword {
+            "v" | "vn" => {
+                // Vertex position or normal
+                let x = parse_f32(tokens.next(), keyword, line_number)?;
+                let y = parse_f32(tokens.next(), keyword, line_number)?;
+                let z = parse_f32(tokens.next(), keyword, line_number)?;
+                let value = BVec3(Vector3::new(x, y, z));
+                if keyword == "v" { self.positions.push(value) } else { self.normals.push(value) }
+            }
+            "vt" => {
+                // Vertex texture coordinate, v is optional
+                let u = parse_f32(tokens.next(), keyword, line_number)?;
+                let v = match tokens.next() {
+                    Some(value) => parse_f32(Some(value), keyword, line_number)?,
+                    None => 0.0,
+                };
+                self.tex_coords.push(BVec2(Vector2::new(u, v)));
+            }
+            "f" => {
+                // Face, any number of corners, each v, v/vt, v//vn or v/vt/vn
+                let count = tokens.clone().count();
+                if count < 3 {
+                    return Err(ObjParseError::TooFewVertices { line: line_number, count });
+                }
+                let first = self.corners.len();
+                for token in tokens {
+                    let mut fields = token.split('/');
+                    let position = parse_index(fields.next().unwrap_or(""), line_number)?;
+                    let tex_coord = match fields.next() {
+                        Some(value) if !value.is_empty() => Some(parse_index(value, line_number)?),
+                        _ => None,
+                    };
+                    let normal = match fields.next() {
+                        Some(value) if !value.is_empty() => Some(parse_index(value, line_number)?),
+                        _ => None,
+                    };
+                    self.corners.push((position, tex_coord, normal));
+                }
+                self.statements.push(Statement::Face {
+                    line: line_number,
+                    corners: first..self.corners.len(),
+                    positions: self.positions.len(),
+                    tex_coords: self.tex_coords.len(),
+                    normals: self.normals.len(),
+                });
+            }
+            "o" => self.statements.push(Statement::Object(line_rest(line, keyword))),
+            "g" => self.statements.push(Statement::Group(line_rest(line, keyword))),
+            "s" => {
+                // Smoothing group, 0 and off both turn smoothing off
+                let value = tokens.next().ok_or_else(|| ObjParseError::MissingValue { line: line_number, keyword: String::from("s") })?;
+                self.statements.push(Statement::Smoothing(match value {
+                    "off" | "0" => None,
+                    value => Some(value.parse().map_err(|_| ObjParseError::InvalidNumber { line: line_number, value: value.to_string() })?),
+                }));
+            }
+            "mtllib" => self.statements.push(Statement::MaterialLibrary(line_rest(line, keyword))),
+            "usemtl" => self.statements.push(Statement::UseMaterial(tokens.next())),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+//position, uv, normal and who owns a generated normal packed into one integer, so hashing it is cheap
+fn corner_key(corner: &Corner, owner: NormalOwner) -> u128 {
+    let (normal, owner_value) = match owner {
+        NormalOwner::File => (corner.normal.unwrap_or(u32::MAX), 0),
+        NormalOwner::Face(face) => (u32::MAX, face as u32),
+        NormalOwner::SmoothingGroup(group) => (u32::MAX - 1, group),
+    };
+    corner.position as u128
+        | (corner.tex_coord.unwrap_or(u32::MAX) as u128) << 32
+        | (normal as u128) << 64
+        | (owner_value as u128) << 96
+}
+
+//Newell's method, works for concave and slightly non planar polygons. Not normalized
+fn polygon_normal(points: &[Vector3<f32>]) -> Vector3<f32> {
+    let mut normal = Vector3::zeros();
+    for i in 0..points.len() {
+        let current = points[i];
+        let next = points[(i + 1) % points.len()];
+        normal.x += (current.y - next.y) * (current.z + next.z);
+        normal.y += (current.z - next.z) * (current.x + next.x);
+        normal.z += (current.x - next.x) * (current.y + next.y);
+    }
+    normal
+}
+
+//Ear clipping, returns corners of the polygon as triangles with the winding of the polygon.
+//Triangles and convex polygons come out as a fan from the first corner, and if clipping gets stuck
+//on a degenerate polygon the rest is fanned.
+pub fn triangulate(points: &[Vector3<f32>]) -> Vec<[usize;3]> {
+    let mut triangles = Vec::with_capacity(points.len().saturating_sub(2));
+    triangulate_into(points, &mut triangles);
+    triangles
+}
+
+//triangulate, appending to triangles so one Vec can be reused for every face
+pub fn triangulate_into(points: &[Vector3<f32>], triangles: &mut Vec<[usize;3]>) {
+    let count = points.len();
+    if count < 3 {
+        return;
+    }
+    if count == 3 {
+        triangles.push([0, 1, 2]);
+        return;
+    }
+
+    //project onto the plane the polygon is most facing
+    let normal = polygon_normal(points);
+    let (axis_u, axis_v) = if normal.x.abs() >= normal.y.abs() && normal.x.abs() >= normal.z.abs() {
+        (1, 2)
+    } else if normal.y.abs() >= normal.z.abs() {
+        (2, 0)
+    } else {
+        (0, 1)
+    };
+    let dominant = if axis_u == 1 { normal.x } else if axis_u == 2 { normal.y } else { normal.z };
+    let flat: Vec<Vector2<f32>> = points.iter().map(|point| Vector2::new(point[axis_u], point[axis_v])).collect();
+    //positive when a, b, c turn the same way as the polygon
+    let orientation = |a: usize, b: usize, c: usize| -> f32 {
+        let ab = flat[b] - flat[a];
+        let ac = flat[c] - flat[a];
+        (ab.x * ac.y - ab.y * ac.x) * dominant.signum()
+    };
+    let inside = |p: usize, a: usize, b: usize, c: usize| -> bool {
+        orientation(a, b, p) >= 0.0 && orientation(b, c, p) >= 0.0 && orientation(c, a, p) >= 0.0
+    };
+
+    //the first ear of a convex quad, what the clipping below would find too
+    if count == 4 && orientation(3, 0, 1) > 0.0 && !inside(2, 3, 0, 1) {
+        triangles.extend_from_slice(&[[3, 0, 1], [1, 2, 3]]);
+        return;
+    }
+
+    let mut remaining: Vec<usize> = (0..count).collect();
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+            orientation(a, b, c) > 0.0
+                && remaining.iter().all(|&p| p == a || p == b || p == c || !inside(p, a, b, c))
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]]);
+                remaining.remove(i);
+            }
+            None => break,
+        }
+    }
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+}
+
+//Where vertex normals come from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalSource {
+    //vn from the file, faces without them get generated normals
+    File,
+    //ignore vn and generate every normal from the smoothing groups
+    Generated,
+}
+
+//Who a generated normal belongs to, corners are only shared with others of the same owner
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum NormalOwner {
+    File,
+    Face(usize),
+    SmoothingGroup(u32),
+}
+
+//The angle at corner i of a polygon, so a vertex is not pulled towards faces that just have many corners there
+fn corner_angle(points: &[Vector3<f32>], i: usize) -> f32 {
+    let count = points.len();
+    let to_previous = points[(i + count - 1) % count] - points[i];
+    let to_next = points[(i + 1) % count] - points[i];
+    let lengths = to_previous.norm() * to_next.norm();
+    if lengths <= 0.0 {
+        return 0.0;
+    }
+    (to_previous.dot(&to_next) / lengths).clamp(-1.0, 1.0).acos()
+}
+
 impl Mesh {
-    pub fn parse_from_file(path: &str) -> Result< HashMap<String,Mesh>, Box<dyn std::error::Error>> {
+    //Objects (o) and groups (g) become separate meshes. A group inside a named object is keyed "object/group",
+    //faces under a name that was used before go into the same mesh.
+    pub fn parse_from_file(path: &str) -> Result< HashMap<String,Mesh>, ObjParseError> {
+        Self::parse_from_file_with_normals(path, NormalSource::File)
+    }
+
+    pub fn parse_from_file_with_normals(path: &str, normal_source: NormalSource) -> Result< HashMap<String,Mesh>, ObjParseError> {
+        let source = std::fs::read_to_string(path)?;
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+        let threads = if source.len() >= PARALLEL_PARSE_MIN_BYTES {
+            std::thread::available_parallelism().map_or(1, |threads| threads.get())
+        } else {
+            1
+        };
+        Self::parse_from_str(&source, directory, normal_source, threads)
+    }
 
-        //A good start, but it does not actually do anything with the smoothing group
+    //The contents of an obj file, mtllib paths are relative to directory.
+    //With more than one thread the file is split into that many chunks that are tokenized and have their numbers
+    //parsed at the same time, then the faces are put together in file order on the calling thread.
+    pub fn parse_from_str(source: &str, directory: &Path, normal_source: NormalSource, threads: usize) -> Result< HashMap<String,Mesh>, ObjParseError> {
+
+        //Faces without normals get flat normals with s off, and smooth normals shared with
+        //the other faces of their smoothing group otherwise.
         //And a Face here contains indices into each data array, i.e,
         //Into Position, into Normals, into texture coords
         //But our realtime mesh will only have ONE index
@@ -100,162 +602,339 @@ impl Mesh {
         //Also - now I am using a nalgebra, but I guess - just embrace all the libs until I
         //find time or energy to start writing own stuff for fun...
 
-
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-
+        let chunks = split_into_chunks(source, threads.max(1));
+        let mut parsed: Vec<ParsedChunk> = if chunks.len() == 1 {
+            vec![ParsedChunk::parse(chunks[0])]
+        } else {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = chunks.iter().map(|chunk| scope.spawn(move || ParsedChunk::parse(chunk))).collect();
+                handles.into_iter().map(|handle| handle.join().expect("obj parser thread panicked")).collect()
+            })
+        };
+
+        //every chunk's data goes after the data of the chunks before it
+        let mut vertices = Vec::with_capacity(parsed.iter().map(|chunk| chunk.positions.len()).sum());
+        let mut normals = Vec::with_capacity(parsed.iter().map(|chunk| chunk.normals.len()).sum());
+        let mut tex_coords = Vec::with_capacity(parsed.iter().map(|chunk| chunk.tex_coords.len()).sum());
+        let mut offsets = Vec::with_capacity(parsed.len());
+        for chunk in &mut parsed {
+            offsets.push((vertices.len(), tex_coords.len(), normals.len()));
+            vertices.append(&mut chunk.positions);
+            tex_coords.append(&mut chunk.tex_coords);
+            normals.append(&mut chunk.normals);
+        }
 
         let mut final_data: HashMap<String,Mesh> = HashMap::new();
-        let mut vertices = Vec::new();
-        let mut normals = Vec::new();
-        let mut tex_coords = Vec::new();
-        let mut faces = Vec::new();
+        let mut faces: Vec<MultiIndexingFace> = Vec::new();
+        let mut corners: Vec<Corner> = Vec::new();
         let mut current_smoothing_group = None;
+        let mut current_material: Option<u32> = None;
+        let mut material_names: Vec<String> = Vec::new();
+        let mut materials: HashMap<String, Material> = HashMap::new();
         let mut object_name : String = String::new();
-
-        for line in reader.lines() {
-            let line = line?;
-            let parts: Vec<&str> = line.split_whitespace().collect();
-
-            if parts.is_empty() {
-                continue;
+        let mut group_name : Option<String> = None;
+        let mut line_offset = 0;
+
+        let mesh_name = |object_name: &String, group_name: &Option<String>| -> String {
+            match group_name {
+                Some(group) if !object_name.is_empty() => format!("{object_name}/{group}"),
+                Some(group) => group.clone(),
+                None => object_name.clone(),
             }
+        };
+
+        for (chunk, (position_offset, tex_coord_offset, normal_offset)) in parsed.into_iter().zip(offsets) {
+            for statement in &chunk.statements {
+                match statement {
+                    Statement::Face { line, corners: face_corners, positions, tex_coords: tex_coord_count, normals: normal_count } => {
+                        let line = line + line_offset;
+                        let first = corners.len();
+                        for (position, tex_coord, normal) in &chunk.corners[face_corners.clone()] {
+                            corners.push(Corner {
+                                position: resolve_index(*position, position_offset + positions, line)?,
+                                tex_coord: tex_coord.map(|index| resolve_index(index, tex_coord_offset + tex_coord_count, line)).transpose()?,
+                                normal: normal.map(|index| resolve_index(index, normal_offset + normal_count, line)).transpose()?,
+                            });
+                        }
 
-            match parts[0] {
+                        //attributes only count if every corner has them
+                        let face_corners = &mut corners[first..];
+                        if face_corners.iter().any(|corner| corner.tex_coord.is_none()) {
+                            face_corners.iter_mut().for_each(|corner| corner.tex_coord = None);
+                        }
+                        if normal_source == NormalSource::Generated || face_corners.iter().any(|corner| corner.normal.is_none()) {
+                            face_corners.iter_mut().for_each(|corner| corner.normal = None);
+                        }
 
-                "o" => {
-                    // object start
-                    if !vertices.is_empty(){
-                        let mesh = Self::create_realtime_mesh_from_loaded_data(&faces,&vertices,&normals,&tex_coords);
-                        final_data.insert(object_name,mesh.unwrap());
-                        faces.clear();
-                        current_smoothing_group = None;
+                        faces.push(MultiIndexingFace {
+                            corners: first as u32..corners.len() as u32,
+                            smoothing_group: current_smoothing_group,
+                            material: current_material,
+                        });
                     }
-                    object_name = String::from(parts[1]).clone();
-                }
-                "v" => {
-                    // Vertex position
-                    let x: f32 = parts[1].parse()?;
-                    let y: f32 = parts[2].parse()?;
-                    let z: f32 = parts[3].parse()?;
-                    vertices.push(BVec3(Vector3::new(x, y, z)));
-                }
-                "vn" => {
-                    // Vertex normal
-                    let x: f32 = parts[1].parse()?;
-                    let y: f32 = parts[2].parse()?;
-                    let z: f32 = parts[3].parse()?;
-                    normals.push(BVec3(Vector3::new(x, y, z)));
-                }
-                "vt" => {
-                    // Vertex texture coordinate
-                    let u: f32 = parts[1].parse()?;
-                    let v: f32 = parts[2].parse()?;
-                    tex_coords.push(BVec2(Vector2::new(u,v)));
-                }
-                "f" => {
-                    // Face
-                    let mut vertex_indices = Vec::new();
-                    let mut normal_indices = Vec::new();
-                    let mut tex_coord_indices = Vec::new();
-
-                    for part in &parts[1..] {
-                        let indices: Vec<&str> = part.split('/').collect();
-
-                        let vertex_index: usize = indices[0].parse()?;
-                        vertex_indices.push(vertex_index - 1);
-
-                        if indices.len() > 1 && !indices[1].is_empty() {
-                            let tex_coord_index: usize = indices[1].parse()?;
-                            tex_coord_indices.push(tex_coord_index - 1);
+                    Statement::Object(name) | Statement::Group(name) => {
+                        // object or group start, the faces so far belong to the previous one
+                        if !faces.is_empty(){
+                            let name = mesh_name(&object_name,&group_name);
+                            let mut mesh = Self::create_realtime_mesh_from_loaded_data(&name,&faces,&corners,&vertices,&normals,&tex_coords,&material_names);
+                            mesh.materials = materials.clone();
+                            insert_mesh(&mut final_data,name,mesh);
+                            faces.clear();
+                            corners.clear();
+                            current_smoothing_group = None;
                         }
-
-                        if indices.len() > 2 && !indices[2].is_empty() {
-                            let normal_index: usize = indices[2].parse()?;
-                            normal_indices.push(normal_index - 1);
+                        let name = name.split_ascii_whitespace().collect::<Vec<&str>>().join(" ");
+                        if let Statement::Object(_) = statement {
+                            object_name = name;
+                            group_name = None;
+                        } else {
+                            group_name = if name.is_empty() { None } else { Some(name) };
                         }
                     }
-
-                    faces.push(MultiIndexingFace {
-                        vertices: vertex_indices,
-                        normal_indices,
-                        tex_coord_indices,
-                        smoothing_group: current_smoothing_group,
-                    });
-                }
-                "s" => {
-                    // Smoothing group
-                    if parts[1] == "off" {
-                        current_smoothing_group = None;
-                    } else {
-                        current_smoothing_group = Some(parts[1].parse()?);
+                    Statement::Smoothing(group) => {
+                        current_smoothing_group = *group;
+                    }
+                    Statement::MaterialLibrary(libraries) => {
+                        //the library is relative to the obj file, a missing one just means no materials
+                        for library in libraries.split_ascii_whitespace() {
+                            let library_path = directory.join(library).to_string_lossy().to_string();
+                            materials.extend(material::load_material_library(&library_path));
+                        }
+                    }
+                    Statement::UseMaterial(name) => {
+                        current_material = name.map(|name| match material_names.iter().position(|known| known == name) {
+                            Some(index) => index as u32,
+                            None => {
+                                material_names.push(name.to_string());
+                                (material_names.len() - 1) as u32
+                            }
+                        });
                     }
                 }
-                _ => {}
             }
+            if let Some(error) = chunk.error {
+                return Err(error.with_line_offset(line_offset));
+            }
+            line_offset += chunk.line_count;
+        }
+        if !faces.is_empty() || final_data.is_empty() {
+            let name = mesh_name(&object_name,&group_name);
+            let mut mesh = Self::create_realtime_mesh_from_loaded_data(&name,&faces,&corners,&vertices,&normals,&tex_coords,&material_names);
+            mesh.materials = materials;
+            insert_mesh(&mut final_data,name,mesh);
         }
-        let mesh = Self::create_realtime_mesh_from_loaded_data(&faces,&vertices,&normals,&tex_coords);
-        final_data.insert(object_name,mesh.unwrap());
         Ok(final_data)
     }
 
-    pub fn create_realtime_mesh_from_loaded_data(faces : &Vec<MultiIndexingFace>,
-                                                 positions : &Vec<BVec3>,
-                                                 normals : &Vec<BVec3>,
-                                                 texture_coords : &Vec<BVec2>)
-                                                 -> Result<Self,Box<dyn std::error::Error>>{
-
-        let mut realtime_vertices: Vec<Vertex> = Vec::new();
-        let mut super_realtime_vertices: Vec<ObjLoaderRealtimeVertex> = Vec::new();
-        let mut hits: HashMap<String,u32> = HashMap::new();
-        let mut realtime_faces: Vec<u32> = Vec::new();
-        let mut realtime_feces: Vec<Face> = Vec::new();
-        for face in faces{
-
-            let mut i = 0;
-            let mut new_face : Face = Face { face_indices: [0,0,0] };
-            while i < face.vertices.len(){
-                let key: String = format!("{}/{}/{}",face.vertices[i],face.tex_coord_indices[i],face.normal_indices[i]);
-                match hits.get(&key){
-                    Some(index) => {
-                        realtime_faces.push(*index);
-                        new_face.face_indices[i] = *index as u32;
-                    },
-                    None => {
-
-                        //Collect all Vertex data
-                        let vertex : Vertex = Vertex{
-                            position: positions[face.vertices[i]],
-                            normal: normals[face.normal_indices[i]],
-                            tex_coord: texture_coords[face.tex_coord_indices[i]],
-                        };
-
-                        let realtime_vertex : ObjLoaderRealtimeVertex = ObjLoaderRealtimeVertex {
-                            position : [vertex.position.0.x,vertex.position.0.y,vertex.position.0.z],
-                            normal : [vertex.normal.0.x,vertex.normal.0.y,vertex.normal.0.z],
-                            uv : [vertex.tex_coord.0.x,vertex.tex_coord.0.y]
-                        };
-
-                        let new_index = realtime_vertices.len() as u32;
-                        realtime_vertices.push(vertex);
-                        super_realtime_vertices.push(realtime_vertex);
-                        realtime_faces.push(new_index);
-                        new_face.face_indices[i] = new_index as u32;
-                        hits.insert(key,new_index);
-                    }
+    //Indices in corners have to be valid, parse_from_file checks them while reading.
+    //Polygons are triangulated and corners without a uv get (0,0).
+    //Faces without normals get the face normal when they have no smoothing group, otherwise every corner gets
+    //the angle and area weighted average of the faces in its smoothing group that share the position.
+    //Vertices are split where smoothing groups meet. name is only used to report problems with the mesh.
+    pub fn create_realtime_mesh_from_loaded_data(name : &str,
+                                                 faces : &[MultiIndexingFace],
+                                                 corners : &[Corner],
+                                                 positions : &[BVec3],
+                                                 normals : &[BVec3],
+                                                 texture_coords : &[BVec2],
+                                                 material_names : &[String])
+                                                 -> Self {
+
+        let corner_positions: Vec<Vector3<f32>> = corners.iter().map(|corner| positions[corner.position as usize].0).collect();
+        let face_range = |face: &MultiIndexingFace| face.corners.start as usize..face.corners.end as usize;
+        let has_normals = |face: &MultiIndexingFace| corners[face.corners.start as usize].normal.is_some();
+
+        //Newell normals are as long as twice the polygon area, summing them unnormalized weighs by area.
+        //Keyed by smoothing group in the high bits and position in the low
+        let mut smooth_normals: IndexHashMap<u64,Vector3<f32>> = IndexHashMap::default();
+        for face in faces {
+            if let (Some(group), false) = (face.smoothing_group, has_normals(face)) {
+                let face_positions = &corner_positions[face_range(face)];
+                let normal = polygon_normal(face_positions);
+                for (i, corner) in corners[face_range(face)].iter().enumerate() {
+                    *smooth_normals.entry((group as u64) << 32 | corner.position as u64).or_insert(Vector3::zeros()) += normal * corner_angle(face_positions, i);
                 }
-                i+=1;
             }
-            realtime_feces.push(new_face);
         }
 
-        Ok(Mesh {
-            vertices : realtime_vertices,
+        let triangle_count = corners.len().saturating_sub(faces.len() * 2);
+        let mut super_realtime_vertices: Vec<ObjLoaderRealtimeVertex> = Vec::with_capacity(corners.len());
+        //see corner_key
+        let mut hits: IndexHashMap<u128,u32> = IndexHashMap::with_capacity_and_hasher(corners.len(), Default::default());
+        let mut realtime_faces: Vec<u32> = Vec::with_capacity(triangle_count * 3);
+        let mut submeshes: Vec<SubMesh> = Vec::new();
+        let mut corner_indices: Vec<u32> = Vec::new();
+        let mut triangles: Vec<[usize;3]> = Vec::new();
+        for (face_index,face) in faces.iter().enumerate(){
+
+            //a new submesh every time the material changes
+            let first_index = realtime_faces.len() as u32;
+            let material = face.material.map(|index| &material_names[index as usize]);
+            match submeshes.last_mut() {
+                Some(submesh) if submesh.material.as_ref() == material => {}
+                _ => submeshes.push(SubMesh { material: material.cloned(), index_range: first_index..first_index }),
+            }
+
+            let face_positions = &corner_positions[face_range(face)];
+            let face_normal = if has_normals(face) {
+                Vector3::zeros()
+            } else {
+                let normal = polygon_normal(face_positions);
+                if normal.norm() > 0.0 { normal.normalize() } else { Vector3::new(0.0, 1.0, 0.0) }
+            };
+
+            corner_indices.clear();
+            for corner in &corners[face_range(face)] {
+                let owner = match (corner.normal, face.smoothing_group) {
+                    (Some(_), _) => NormalOwner::File,
+                    (None, Some(group)) => NormalOwner::SmoothingGroup(group),
+                    (None, None) => NormalOwner::Face(face_index),
+                };
+                let index = *hits.entry(corner_key(corner, owner)).or_insert_with(|| {
+                    let normal = match owner {
+                        NormalOwner::File => normals[corner.normal.unwrap() as usize].0,
+                        NormalOwner::Face(_) => face_normal,
+                        NormalOwner::SmoothingGroup(group) => {
+                            let normal = smooth_normals[&((group as u64) << 32 | corner.position as u64)];
+                            if normal.norm() > 0.0 { normal.normalize() } else { face_normal }
+                        }
+                    };
+                    let position = positions[corner.position as usize].0;
+                    let uv = corner.tex_coord.map_or(Vector2::new(0.0, 0.0), |index| texture_coords[index as usize].0);
+                    super_realtime_vertices.push(ObjLoaderRealtimeVertex {
+                        position : [position.x,position.y,position.z],
+                        normal : [normal.x,normal.y,normal.z],
+                        uv : [uv.x,uv.y],
+                        tangent : [1.0,0.0,0.0,1.0],
+                    });
+                    (super_realtime_vertices.len() - 1) as u32
+                });
+                corner_indices.push(index);
+            }
+
+            triangles.clear();
+            triangulate_into(face_positions, &mut triangles);
+            for triangle in &triangles {
+                realtime_faces.extend(triangle.map(|corner| corner_indices[corner]));
+            }
+            submeshes.last_mut().unwrap().index_range.end = realtime_faces.len() as u32;
+        }
+
+        //vertices and feces are filled in from real_verts and faces by generate_tangents
+        let mut mesh = Mesh {
+            vertices : Vec::new(),
             faces  : realtime_faces ,
-            feces : realtime_feces,
+            feces : Vec::new(),
             real_verts: super_realtime_vertices,
+            submeshes,
+            materials: HashMap::new(),
+            bounds: Aabb::EMPTY,
+            bounding_sphere: BoundingSphere::EMPTY,
+
+        };
+        if !mesh.generate_tangents() {
+            println!("obj mesh '{name}' : could not generate tangents, normal maps will not line up");
+        }
+        mesh.compute_bounds();
+        mesh
+
+    }
+
+    //MikkTSpace tangents, vertices shared by corners with different tangents are split (see tangents.rs).
+    //Returns false if MikkTSpace gave up, the tangents are then all (1,0,0,1)
+    pub fn generate_tangents(self: &mut Self) -> bool {
+        let generated = tangents::generate_tangents(&mut self.real_verts, &mut self.faces);
+        self.vertices = self.real_verts.iter().map(|vertex| vertex.to_vertex()).collect();
+        self.feces = self.faces.chunks_exact(3).map(|face| Face { face_indices: [face[0], face[1], face[2]] }).collect();
+        generated
+    }
+}
+
+impl tangents::TangentVertex for ObjLoaderRealtimeVertex {
+    fn position(self: &Self) -> [f32;3] {
+        self.position
+    }
+
+    fn normal(self: &Self) -> [f32;3] {
+        self.normal
+    }
 
-        })
+    fn uv(self: &Self) -> [f32;2] {
+        self.uv
+    }
+
+    fn set_tangent(self: &mut Self, tangent: [f32;4]) {
+        self.tangent = tangent;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<HashMap<String,Mesh>, ObjParseError> {
+        Mesh::parse_from_str(source, Path::new(""), NormalSource::File, 1)
+    }
+
+    fn triangle_area(mesh: &Mesh, triangle: &[u32]) -> f32 {
+        let corner = |i: usize| Vector3::from(mesh.real_verts[triangle[i] as usize].position);
+        (corner(1) - corner(0)).cross(&(corner(2) - corner(0))).z * 0.5
+    }
+
+    #[test]
+    fn quad_is_two_triangles() {
+        let meshes = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n").unwrap();
+        let mesh = &meshes[""];
+        assert_eq!(mesh.real_verts.len(), 4);
+        assert_eq!(mesh.faces.len(), 6);
+        let area: f32 = mesh.faces.chunks_exact(3).map(|triangle| triangle_area(mesh, triangle)).sum();
+        assert!((area - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn concave_ngon_stays_inside() {
+        //an L of three unit squares, the corner at (1,1) is the reflex one
+        let meshes = parse("v 0 0 0\nv 2 0 0\nv 2 1 0\nv 1 1 0\nv 1 2 0\nv 0 2 0\nf 1 2 3 4 5 6\n").unwrap();
+        let mesh = &meshes[""];
+        assert_eq!(mesh.faces.len(), 4 * 3);
+        //a triangle across the notch would be wound the other way or make the area too big
+        for triangle in mesh.faces.chunks_exact(3) {
+            assert!(triangle_area(mesh, triangle) > 0.0);
+        }
+        let area: f32 = mesh.faces.chunks_exact(3).map(|triangle| triangle_area(mesh, triangle)).sum();
+        assert!((area - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn negative_indices_count_back() {
+        let meshes = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -1 -2 -3\n").unwrap();
+        let mesh = &meshes[""];
+        let positions: Vec<[f32;3]> = mesh.faces.iter().map(|index| mesh.real_verts[*index as usize].position).collect();
+        assert_eq!(positions, vec![[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn face_with_two_corners_is_an_error() {
+        match parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2\n") {
+            Err(ObjParseError::TooFewVertices { line: 4, count: 2 }) => {}
+            result => panic!("expected TooFewVertices on line 4, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn zero_index_is_an_error() {
+        match parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n") {
+            Err(ObjParseError::ZeroIndex { line: 4 }) => {}
+            result => panic!("expected ZeroIndex on line 4, got {result:?}"),
+        }
+    }
 
+    #[test]
+    fn repeated_name_merges() {
+        let meshes = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\no a\nf 1 2 3\no b\nf 1 2 3\no a\nf 3 2 1\n").unwrap();
+        assert_eq!(meshes.len(), 2);
+        let mesh = &meshes["a"];
+        assert_eq!(mesh.faces.len(), 6);
+        assert!(mesh.faces.iter().all(|index| (*index as usize) < mesh.real_verts.len()));
     }
 }
@@ -0,0 +1,394 @@
+/*
+Post processing chain.
+The scene renders into an hdr target (scene_view), then every enabled effect runs in the order of `effects`,
+ping-ponging between two targets, and the result is tonemapped to the output view (see tonemapping.rs).
+
+Every effect has 8 float params, uploaded as params0 and params1 in post_process.wgsl, so they can be driven
+straight from the timeline or sync tracks. What each slot means is listed on PostEffectKind.
+ */
+use std::collections::HashMap;
+use anyhow::*;
+use wgpu::util::DeviceExt;
+use crate::nocmp::texture;
+use crate::nocmp::texture::Texture;
+use crate::nocmp::tonemapping::ToneMapper;
+
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash)]
+pub enum PostEffectKind {
+    //threshold, intensity, blur radius in texels
+    Bloom,
+    //amount (uv offset at the screen edge)
+    ChromaticAberration,
+    //intensity, radius, smoothness
+    Vignette,
+    //amount, grain size in pixels
+    FilmGrain,
+    //curvature, scanline intensity, scanline count (0 is half the screen height), rgb mask intensity
+    Crt,
+    //center x, center y, strength, sample count
+    RadialBlur,
+    //decay, zoom, rotation, y offset
+    FeedbackTrails,
+}
+
+impl PostEffectKind {
+    fn entry_point(self: &Self) -> &'static str {
+        match self {
+            PostEffectKind::Bloom => "fs_bloom_composite",
+            PostEffectKind::ChromaticAberration => "fs_chromatic_aberration",
+            PostEffectKind::Vignette => "fs_vignette",
+            PostEffectKind::FilmGrain => "fs_film_grain",
+            PostEffectKind::Crt => "fs_crt",
+            PostEffectKind::RadialBlur => "fs_radial_blur",
+            PostEffectKind::FeedbackTrails => "fs_feedback",
+        }
+    }
+
+    pub fn default_params(self: &Self) -> [f32;8] {
+        match self {
+            PostEffectKind::Bloom => [1.0, 0.6, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            PostEffectKind::ChromaticAberration => [0.004, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            PostEffectKind::Vignette => [0.5, 0.4, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0],
+            PostEffectKind::FilmGrain => [0.04, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            PostEffectKind::Crt => [0.3, 0.3, 0.0, 0.2, 0.0, 0.0, 0.0, 0.0],
+            PostEffectKind::RadialBlur => [0.5, 0.5, 0.1, 16.0, 0.0, 0.0, 0.0, 0.0],
+            PostEffectKind::FeedbackTrails => [0.9, 1.01, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug,Copy,Clone,bytemuck::Pod,bytemuck::Zeroable)]
+struct PostUniforms {
+    params0: [f32;4],
+    params1: [f32;4],
+    resolution: [f32;2],
+    time: f32,
+    pad: f32,
+}
+
+pub struct PostEffect {
+    pub kind: PostEffectKind,
+    pub enabled: bool,
+    pub params: [f32;8],
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+}
+
+impl PostEffect {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, kind: PostEffectKind, enabled: bool) -> Self {
+        let (uniform_buffer, uniform_bind_group) = create_uniforms(device, layout);
+        PostEffect {
+            kind,
+            enabled,
+            params: kind.default_params(),
+            uniform_buffer,
+            uniform_bind_group,
+        }
+    }
+
+    fn push_uniforms(self: &Self, queue: &wgpu::Queue, resolution: [f32;2], time: f32) {
+        let uniforms = PostUniforms {
+            params0: [self.params[0], self.params[1], self.params[2], self.params[3]],
+            params1: [self.params[4], self.params[5], self.params[6], self.params[7]],
+            resolution,
+            time,
+            pad: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    }
+}
+
+fn create_uniforms(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> (wgpu::Buffer, wgpu::BindGroup) {
+    let uniform_buffer = device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("post effect uniforms"),
+            contents: bytemuck::cast_slice(&[PostUniforms { params0: [0.0;4], params1: [0.0;4], resolution: [0.0;2], time: 0.0, pad: 0.0 }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        }
+    );
+    let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }
+        ],
+        label: Some("post effect uniform bind group"),
+    });
+    (uniform_buffer, uniform_bind_group)
+}
+
+fn create_target(device: &wgpu::Device, width: u32, height: u32, label: &str) -> Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: HDR_FORMAT,
+        //copy usages for the feedback history
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+    Texture { texture, view, sampler, view_dimension: wgpu::TextureViewDimension::D2, sampler_binding_type: wgpu::SamplerBindingType::Filtering }
+}
+
+//Everything that depends on the screen size, recreated on resize
+struct PostTargets {
+    scene: Texture,
+    ping: [Texture;2],
+    history: Texture,
+    //half resolution
+    bloom: [Texture;2],
+    //source (scene, ping 0, ping 1) with the history as second input
+    with_history: [wgpu::BindGroup;3],
+    //source (scene, ping 0, ping 1) with the blurred bloom as second input
+    with_bloom: [wgpu::BindGroup;3],
+    bloom_blur: [wgpu::BindGroup;2],
+}
+
+impl PostTargets {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, width: u32, height: u32) -> Self {
+        let scene = create_target(device, width, height, "post scene target");
+        let ping = [
+            create_target(device, width, height, "post ping target"),
+            create_target(device, width, height, "post pong target"),
+        ];
+        let history = create_target(device, width, height, "post history target");
+        let bloom = [
+            create_target(device, width / 2, height / 2, "post bloom target"),
+            create_target(device, width / 2, height / 2, "post bloom blur target"),
+        ];
+
+        let pair = |source: &Texture, aux: &Texture| -> wgpu::BindGroup {
+            let mut entries = Vec::new();
+            entries.extend(texture::texture_bind_group_entries(source, 0));
+            entries.extend(texture::texture_bind_group_entries(aux, 2));
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout,
+                entries: entries.as_slice(),
+                label: Some("post source bind group"),
+            })
+        };
+        let with_history = [pair(&scene, &history), pair(&ping[0], &history), pair(&ping[1], &history)];
+        let with_bloom = [pair(&scene, &bloom[0]), pair(&ping[0], &bloom[0]), pair(&ping[1], &bloom[0])];
+        let bloom_blur = [pair(&bloom[0], &bloom[0]), pair(&bloom[1], &bloom[1])];
+
+        PostTargets { scene, ping, history, bloom, with_history, with_bloom, bloom_blur }
+    }
+}
+
+pub struct PostChain {
+    pub effects: Vec<PostEffect>,
+    targets: PostTargets,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    pipelines: HashMap<&'static str, wgpu::RenderPipeline>,
+    //operator, exposure and auto exposure of the final pass
+    pub tonemapper: ToneMapper,
+}
+
+impl PostChain {
+    //All effects are created in the order of the list, bloom, chromatic aberration, vignette and grain start enabled.
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, output_format: wgpu::TextureFormat) -> Result<Self> {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shadertoys/post_process.wgsl"));
+
+        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("post uniform bind group layout"),
+        });
+
+        let layout_target = create_target(device, 1, 1, "post layout target");
+        let mut texture_layout_entries = Vec::new();
+        texture_layout_entries.extend(texture::texture_layout_entries(device, &layout_target, 0, wgpu::ShaderStages::FRAGMENT));
+        texture_layout_entries.extend(texture::texture_layout_entries(device, &layout_target, 2, wgpu::ShaderStages::FRAGMENT));
+        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: texture_layout_entries.as_slice(),
+            label: Some("post texture bind group layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post pipeline layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let mut pipelines = HashMap::new();
+        for entry_point in ["fs_bloom_bright", "fs_blur_h", "fs_blur_v", "fs_bloom_composite", "fs_chromatic_aberration",
+            "fs_vignette", "fs_film_grain", "fs_crt", "fs_radial_blur", "fs_feedback"] {
+            pipelines.insert(entry_point, Self::create_pipeline(device, &pipeline_layout, &shader, entry_point, HDR_FORMAT));
+        }
+        let mut tonemapper = ToneMapper::new(device, &texture_bind_group_layout, output_format)?;
+
+        let effects = vec![
+            PostEffect::new(device, &uniform_bind_group_layout, PostEffectKind::Bloom, true),
+            PostEffect::new(device, &uniform_bind_group_layout, PostEffectKind::ChromaticAberration, true),
+            PostEffect::new(device, &uniform_bind_group_layout, PostEffectKind::Vignette, true),
+            PostEffect::new(device, &uniform_bind_group_layout, PostEffectKind::FilmGrain, true),
+            PostEffect::new(device, &uniform_bind_group_layout, PostEffectKind::Crt, false),
+            PostEffect::new(device, &uniform_bind_group_layout, PostEffectKind::RadialBlur, false),
+            PostEffect::new(device, &uniform_bind_group_layout, PostEffectKind::FeedbackTrails, false),
+        ];
+
+        let targets = PostTargets::new(device, &texture_bind_group_layout, width, height);
+        tonemapper.set_sources(device, &[&targets.scene, &targets.ping[0], &targets.ping[1]]);
+
+        Ok(Self {
+            effects,
+            targets,
+            texture_bind_group_layout,
+            pipelines,
+            tonemapper,
+        })
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        entry_point: &str,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point,
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    pub fn resize(self: &mut Self, device: &wgpu::Device, width: u32, height: u32) {
+        self.targets = PostTargets::new(device, &self.texture_bind_group_layout, width, height);
+        self.tonemapper.set_sources(device, &[&self.targets.scene, &self.targets.ping[0], &self.targets.ping[1]]);
+    }
+
+    //The scene is rendered here instead of to the surface
+    pub fn scene_view(self: &Self) -> &wgpu::TextureView {
+        &self.targets.scene.view
+    }
+
+    pub fn effect_mut(self: &mut Self, kind: PostEffectKind) -> Option<&mut PostEffect> {
+        self.effects.iter_mut().find(|effect| effect.kind == kind)
+    }
+
+    fn pass(
+        self: &Self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        uniform_bind_group: &wgpu::BindGroup,
+        texture_bind_group: &wgpu::BindGroup,
+        target: &wgpu::TextureView,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("post pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, texture_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    //Runs the enabled effects on the scene target and writes the tonemapped result to output_view
+    pub fn render(
+        self: &Self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        time: f32,
+    ) {
+        let targets = &self.targets;
+        let resolution = [targets.scene.texture.width() as f32, targets.scene.texture.height() as f32];
+        //0 is the scene, 1 and 2 are the ping pong targets
+        let mut source = 0;
+        for effect in self.effects.iter().filter(|effect| effect.enabled) {
+            effect.push_uniforms(queue, resolution, time);
+            let destination = if source == 1 { 2 } else { 1 };
+            let destination_target = &targets.ping[destination - 1];
+            match effect.kind {
+                PostEffectKind::Bloom => {
+                    self.pass(encoder, &self.pipelines["fs_bloom_bright"], &effect.uniform_bind_group, &targets.with_history[source], &targets.bloom[0].view);
+                    self.pass(encoder, &self.pipelines["fs_blur_h"], &effect.uniform_bind_group, &targets.bloom_blur[0], &targets.bloom[1].view);
+                    self.pass(encoder, &self.pipelines["fs_blur_v"], &effect.uniform_bind_group, &targets.bloom_blur[1], &targets.bloom[0].view);
+                    self.pass(encoder, &self.pipelines["fs_bloom_composite"], &effect.uniform_bind_group, &targets.with_bloom[source], &destination_target.view);
+                }
+                PostEffectKind::FeedbackTrails => {
+                    self.pass(encoder, &self.pipelines["fs_feedback"], &effect.uniform_bind_group, &targets.with_history[source], &destination_target.view);
+                    encoder.copy_texture_to_texture(
+                        destination_target.texture.as_image_copy(),
+                        targets.history.texture.as_image_copy(),
+                        targets.history.texture.size(),
+                    );
+                }
+                kind => {
+                    self.pass(encoder, &self.pipelines[kind.entry_point()], &effect.uniform_bind_group, &targets.with_history[source], &destination_target.view);
+                }
+            }
+            source = destination;
+        }
+
+        let source_size = (targets.scene.texture.width(), targets.scene.texture.height());
+        self.tonemapper.render(queue, encoder, source, &targets.with_history[source], source_size, output_view, time);
+    }
+}
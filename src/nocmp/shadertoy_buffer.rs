@@ -148,10 +148,73 @@ impl ShaderToyUniforms {
     }
 }
 
+//How big the render target of a buffer is, either a fixed size in pixels
+//or a scale of the surface (1.0 full res, 0.5 half res, 0.25 quarter res)
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum RenderTargetSize {
+    Fixed(u32,u32),
+    ScreenScale(f32),
+}
+
+impl RenderTargetSize {
+    pub fn resolve(self: &Self, screen_width: u32, screen_height: u32) -> (u32,u32) {
+        match *self {
+            RenderTargetSize::Fixed(width,height) => (width.max(1),height.max(1)),
+            RenderTargetSize::ScreenScale(scale) => (
+                ((screen_width as f32 * scale).round() as u32).max(1),
+                ((screen_height as f32 * scale).round() as u32).max(1),
+            ),
+        }
+    }
+}
+
+//Describes the render target a ShaderToylikeBuffer renders into.
+//Feedback effects (like buffer_a/buffer_b ping-ponging) typically want Rgba16Float or Rgba32Float,
+//format None means we use the surface format.
+#[derive(Copy,Clone,Debug)]
+pub struct RenderTargetConfig {
+    pub format: Option<wgpu::TextureFormat>,
+    pub size: RenderTargetSize,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub address_mode: wgpu::AddressMode,
+}
+
+impl Default for RenderTargetConfig {
+    //matches what create used to hardcode before the target was configurable
+    fn default() -> Self {
+        RenderTargetConfig {
+            format: None,
+            size: RenderTargetSize::Fixed(4096_u32*2,4096_u32*2),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            address_mode: wgpu::AddressMode::ClampToEdge,
+        }
+    }
+}
+
+impl RenderTargetConfig {
+    pub fn screen_sized(format: wgpu::TextureFormat, scale: f32) -> Self {
+        RenderTargetConfig {
+            format: Some(format),
+            size: RenderTargetSize::ScreenScale(scale),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        }
+    }
+}
+
 pub struct ShaderToylikeBuffer{
 
     render_pipeline: wgpu::RenderPipeline,
+    //only created when the target format differs from the surface format,
+    //since a pipeline can only render to the format it was created with.
+    screen_render_pipeline: Option<wgpu::RenderPipeline>,
+    target_config: RenderTargetConfig,
+    target_format: wgpu::TextureFormat,
     target_rtt : nocmp::texture::Texture,
+    target_rtt_bindgroup_layout : wgpu::BindGroupLayout,
     target_rtt_bindgroup : wgpu::BindGroup,
     //can probably be shared
     vertex_buffer: wgpu::Buffer,
@@ -162,23 +225,26 @@ pub struct ShaderToylikeBuffer{
 
 impl ShaderToylikeBuffer{
 
-    pub fn create(
+    pub fn create_with_target(
         device: &wgpu::Device,
         toylike_uniforms: &ShaderToyUniforms,
         texture_bind_group_layout: &wgpu::BindGroupLayout,
         surface_config: &wgpu::SurfaceConfiguration,
         shader_descriptor: wgpu::ShaderModuleDescriptor,
+        target_config: RenderTargetConfig,
     ) ->Result<Self>{
 
         //let shader = device.create_shader_module(wgpu::include_wgsl!("../shadertoys/shader_buffer_a.wgsl"));
         let shader = device.create_shader_module(shader_descriptor);
 
-        let target_rtt = nocmp::texture::Texture::create_rtt_texture(4096_u32*2,4096_u32*2,&device,surface_config.format,Some("target rtt texture")).unwrap();
-        let (bind_group_layout,target_rtt_bindgroup) = nocmp::texture::setup_texture_stage(
-            &device,
-            &[&target_rtt],
-            Some("target_rtt")
-        ).unwrap();
+        let target_format = target_config.format.unwrap_or(surface_config.format);
+        let (target_rtt,target_rtt_bindgroup_layout,target_rtt_bindgroup) = Self::create_target(
+            device,
+            &target_config,
+            target_format,
+            surface_config.width,
+            surface_config.height
+        )?;
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -191,11 +257,83 @@ impl ShaderToylikeBuffer{
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let render_pipeline = Self::create_pipeline(device,&render_pipeline_layout,&shader,target_format);
+        let screen_render_pipeline = if target_format != surface_config.format {
+            Some(Self::create_pipeline(device,&render_pipeline_layout,&shader,surface_config.format))
+        } else {
+            None
+        };
+
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(VERTICES),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: bytemuck::cast_slice(INDICES),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+
+        let num_indices= INDICES.len() as u32;
+
+        Ok(Self{
+            render_pipeline,
+            screen_render_pipeline,
+            target_config,
+            target_format,
+            vertex_buffer,
+            index_buffer,
+            num_indices,
+            target_rtt,
+            target_rtt_bindgroup_layout,
+            target_rtt_bindgroup,
+        })
+    }
+
+    fn create_target(
+        device: &wgpu::Device,
+        target_config: &RenderTargetConfig,
+        target_format: wgpu::TextureFormat,
+        screen_width: u32,
+        screen_height: u32,
+    ) -> Result<(nocmp::texture::Texture,wgpu::BindGroupLayout,wgpu::BindGroup)> {
+        let (width,height) = target_config.size.resolve(screen_width,screen_height);
+        let target_rtt = nocmp::texture::Texture::create_rtt_texture_with_sampler(
+            width,
+            height,
+            &device,
+            target_format,
+            target_config.mag_filter,
+            target_config.min_filter,
+            target_config.address_mode,
+            Some("target rtt texture")
+        )?;
+        let (bind_group_layout,target_rtt_bindgroup) = nocmp::texture::setup_texture_stage(
+            &device,
+            &[&target_rtt],
+            Some("target_rtt")
+        )?;
+        Ok((target_rtt,bind_group_layout,target_rtt_bindgroup))
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        render_pipeline_layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
+            layout: Some(render_pipeline_layout),
             vertex: wgpu::VertexState {
-                module: &shader,
+                module: shader,
                 entry_point: "vs_main",
                 compilation_options: Default::default(),
                 buffers:&[
@@ -203,11 +341,11 @@ impl ShaderToylikeBuffer{
                 ],
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: "fs_main",
                 compilation_options: Default::default(),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_config.format,
+                    format,
                     blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                     write_mask:wgpu::ColorWrites::ALL,
                 })],
@@ -234,40 +372,50 @@ impl ShaderToylikeBuffer{
             },
             multiview: None,
             cache: None,
-        });
-
-
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
+        })
+    }
 
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(INDICES),
-                usage: wgpu::BufferUsages::INDEX,
-            }
-        );
+    //Reallocates the render target if it follows the screen size.
+    //The old target content is lost, which is fine for everything but the feedback buffers,
+    //and those start over from a cleared target anyway.
+    pub fn resize(self: &mut Self, device: &wgpu::Device, screen_width: u32, screen_height: u32) -> Result<()> {
+        if let RenderTargetSize::Fixed(_,_) = self.target_config.size {
+            return Ok(());
+        }
+        let (width,height) = self.target_config.size.resolve(screen_width,screen_height);
+        if width == self.target_rtt.texture.width() && height == self.target_rtt.texture.height() {
+            return Ok(());
+        }
+        let (target_rtt,target_rtt_bindgroup_layout,target_rtt_bindgroup) = Self::create_target(
+            device,
+            &self.target_config,
+            self.target_format,
+            screen_width,
+            screen_height
+        )?;
+        self.target_rtt = target_rtt;
+        self.target_rtt_bindgroup_layout = target_rtt_bindgroup_layout;
+        self.target_rtt_bindgroup = target_rtt_bindgroup;
+        Ok(())
+    }
 
+    pub fn get_target_rtt_bindgroup(self: &Self) -> &wgpu::BindGroup{
+        &self.target_rtt_bindgroup
+    }
 
-        let num_indices= INDICES.len() as u32;
+    //For stages that read this target together with other channels, see setup_texture_stage
+    pub fn get_target_rtt(self: &Self) -> &nocmp::texture::Texture{
+        &self.target_rtt
+    }
 
-        Ok(Self{
-            render_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
-            target_rtt,
-            target_rtt_bindgroup,
-        })
+    //A buffer reading this buffers target should be created with this layout,
+    //it differs from the usual one when the target format is not filterable (Rgba32Float)
+    pub fn get_target_rtt_bindgroup_layout(self: &Self) -> &wgpu::BindGroupLayout{
+        &self.target_rtt_bindgroup_layout
     }
 
-    pub fn get_target_rtt_bindgroup(self: &Self) -> &wgpu::BindGroup{
-        &self.target_rtt_bindgroup
+    fn screen_pipeline(self: &Self) -> &wgpu::RenderPipeline {
+        self.screen_render_pipeline.as_ref().unwrap_or(&self.render_pipeline)
     }
 
     pub fn render_to_own_buffer(
@@ -331,7 +479,7 @@ impl ShaderToylikeBuffer{
         });
 
 
-        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_pipeline(self.screen_pipeline());
         //render_pass.set_bind_group(0,&self.uniform_bind_group,&[]);
         render_pass.set_bind_group(0,&toylike_uniforms.uniform_bind_group,&[]);
         render_pass.set_bind_group(1,textures_group,&[]);
@@ -368,7 +516,7 @@ impl ShaderToylikeBuffer{
         });
 
 
-        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_pipeline(self.screen_pipeline());
         //render_pass.set_bind_group(0,&self.uniform_bind_group,&[]);
         render_pass.set_bind_group(0,&toylike_uniforms.uniform_bind_group,&[]);
         render_pass.set_bind_group(1,textures_group,&[]);
@@ -0,0 +1,210 @@
+/*
+Draws everything in a GltfScene with vs_skinned from obj_test.wgsl, gpu skinned by the pose set with update_pose.
+The pipeline comes from the MeshRenderer (VertexLayout::Skinned), the mesh binds its own group 2.
+
+Every node with a mesh gets its own joint matrix buffer (group 2 binding 3, next to the model matrix at binding 0),
+the skinned ones hold joint world matrix * inverse bind matrix for each joint of the skin, the others just the world
+matrix of the node in joint 0. The model matrix places the whole scene.
+Materials use the same bind group as obj meshes (material.rs), with the base color and normal images from the file.
+ */
+
+use anyhow::*;
+use wgpu::util::DeviceExt;
+use wgpu::StoreOp;
+use crate::nocmp::gltf_loader::{GltfScene, SkinnedVertex};
+use crate::nocmp::material::{GpuMaterial, Material, MaterialImages};
+use crate::nocmp::mesh_renderer::{MeshRenderer, ModelUniform, PipelineKey, VertexLayout};
+
+//has to match the joint_matrices array in obj_test.wgsl
+pub const MAX_JOINTS: usize = 128;
+
+struct GpuPrimitive {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    material_index: usize,
+}
+
+struct NodeInstance {
+    node: usize,
+    joint_buffer: wgpu::Buffer,
+    bind_group_2: wgpu::BindGroup,
+    primitives: Vec<GpuPrimitive>,
+}
+
+pub struct SkinnedMesh {
+    pipeline: PipelineKey,
+    instances: Vec<NodeInstance>,
+    //one per gltf material, and the default material last for primitives without one
+    pub materials: Vec<GpuMaterial>,
+    model_uniform_buffer: wgpu::Buffer,
+    pub model_matrix: cgmath::Matrix4<f32>,
+}
+
+impl SkinnedMesh {
+
+    pub fn create(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut MeshRenderer,
+        shader_descriptor: wgpu::ShaderModuleDescriptor,
+        scene: &GltfScene,
+    ) -> Result<Self> {
+        if let Some(skin) = scene.skins.iter().find(|skin| skin.joints.len() > MAX_JOINTS) {
+            bail!("skin {} has {} joints, at most {} are supported", skin.name, skin.joints.len(), MAX_JOINTS);
+        }
+
+        let model_matrix: cgmath::Matrix4<f32> = cgmath::Matrix4::from_scale(1.0);
+        let model_uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("skinned mesh model uniform buffer"),
+                contents: bytemuck::cast_slice(&[ModelUniform { model_matrix: model_matrix.into() }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let mut materials = Vec::new();
+        for material in &scene.materials {
+            let image = |index: Option<usize>| index.and_then(|index| scene.images.get(index)).and_then(|image| image.as_ref());
+            let images = MaterialImages {
+                diffuse: image(material.base_color_image),
+                bump: image(material.normal_image),
+                ..Default::default()
+            };
+            materials.push(GpuMaterial::with_images(device, queue, &material.material, images, renderer.material_layout(), &[])?);
+        }
+        let default_material = materials.len();
+        materials.push(GpuMaterial::new(device, queue, &Material::default(), renderer.material_layout(), &[])?);
+
+        let pipeline = PipelineKey::new(renderer.shader(device, shader_descriptor), VertexLayout::Skinned);
+        renderer.prepare_for_materials(device, pipeline, &materials);
+
+        let rest_pose = scene.pose(None, 0.0);
+        let mut instances = Vec::new();
+        for (node_index, node) in scene.nodes.iter().enumerate() {
+            let mesh = match node.mesh {
+                Some(mesh) => &scene.meshes[mesh],
+                None => continue,
+            };
+            let mut joints = [[[0.0f32;4];4]; MAX_JOINTS];
+            for (joint, matrix) in joints.iter_mut().zip(scene.joint_matrices(node_index, &rest_pose)) {
+                *joint = matrix.into();
+            }
+            let joint_buffer = device.create_buffer_init(
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some("joint matrix buffer"),
+                    contents: bytemuck::cast_slice(&joints),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                }
+            );
+            let bind_group_2 = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&node.name),
+                layout: renderer.bind_group_layout_2(VertexLayout::Skinned),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: model_uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: joint_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+            let primitives = mesh.primitives.iter()
+                .filter(|primitive| !primitive.indices.is_empty())
+                .map(|primitive| GpuPrimitive {
+                    vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&mesh.name),
+                        contents: bytemuck::cast_slice::<SkinnedVertex, u8>(primitive.vertices.as_slice()),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    }),
+                    index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&mesh.name),
+                        contents: bytemuck::cast_slice(primitive.indices.as_slice()),
+                        usage: wgpu::BufferUsages::INDEX,
+                    }),
+                    index_count: primitive.indices.len() as u32,
+                    material_index: primitive.material.unwrap_or(default_material),
+                })
+                .collect();
+            instances.push(NodeInstance {
+                node: node_index,
+                joint_buffer,
+                bind_group_2,
+                primitives,
+            });
+        }
+
+        Ok(Self {
+            pipeline,
+            instances,
+            materials,
+            model_uniform_buffer,
+            model_matrix,
+        })
+    }
+
+    //Poses the scene with animation at time seconds (see GltfScene::pose), None is the rest pose, and pushes model_matrix.
+    //scene has to be the one the mesh was created from
+    pub fn update_pose(self: &Self, queue: &wgpu::Queue, scene: &GltfScene, animation: Option<usize>, time: f32) {
+        let model_uniforms = ModelUniform { model_matrix: self.model_matrix.into() };
+        queue.write_buffer(&self.model_uniform_buffer, 0, bytemuck::cast_slice(&[model_uniforms]));
+        let world = scene.pose(animation, time);
+        for instance in &self.instances {
+            let joints: Vec<[[f32;4];4]> = scene.joint_matrices(instance.node, &world).into_iter().map(|matrix| matrix.into()).collect();
+            queue.write_buffer(&instance.joint_buffer, 0, bytemuck::cast_slice(joints.as_slice()));
+        }
+    }
+
+    //draws on top of what is there, translucent materials after the opaque ones.
+    //renderer has to be the one the mesh was created with
+    pub fn render_to_screen_no_clear(
+        self: &Self,
+        renderer: &MeshRenderer,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        depth_view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("skinned mesh pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_bind_group(0, renderer.bind_group_0(), &[]);
+        for translucent in [false, true] {
+            let in_pass = |primitive: &&GpuPrimitive| self.materials[primitive.material_index].translucent() == translucent;
+            if !self.instances.iter().any(|instance| instance.primitives.iter().any(|primitive| in_pass(&primitive))) {
+                continue;
+            }
+            render_pass.set_pipeline(renderer.pipeline(if translucent { self.pipeline.translucent() } else { self.pipeline }));
+            for instance in &self.instances {
+                render_pass.set_bind_group(2, &instance.bind_group_2, &[]);
+                for primitive in instance.primitives.iter().filter(in_pass) {
+                    render_pass.set_bind_group(1, &self.materials[primitive.material_index].bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, primitive.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(primitive.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.draw_indexed(0..primitive.index_count, 0, 0..1);
+                }
+            }
+        }
+    }
+}
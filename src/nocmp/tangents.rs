@@ -0,0 +1,90 @@
+/*
+MikkTSpace tangents for indexed triangle lists, the same ones Blender bakes normal maps against.
+Used for obj meshes and for gltf meshes that come without tangents.
+ */
+use std::collections::HashMap;
+
+pub trait TangentVertex: Copy {
+    fn position(self: &Self) -> [f32;3];
+    fn normal(self: &Self) -> [f32;3];
+    fn uv(self: &Self) -> [f32;2];
+    //w is the sign of the bitangent, bitangent = cross(normal, tangent.xyz) * w
+    fn set_tangent(self: &mut Self, tangent: [f32;4]);
+}
+
+//Tangents are generated per triangle corner, a vertex shared by corners that get different tangents
+//(uv seams, mirrored uvs) is split, so new vertices can end up at the end of vertices.
+//Returns false if MikkTSpace gave up, every vertex then has (1,0,0,1).
+pub fn generate_tangents<V: TangentVertex>(vertices: &mut Vec<V>, indices: &mut [u32]) -> bool {
+    let mut geometry = TangentGeometry {
+        vertices: vertices.as_slice(),
+        indices,
+        corner_tangents: vec![[1.0,0.0,0.0,1.0]; indices.len()],
+    };
+    let generated = bevy_mikktspace::generate_tangents(&mut geometry);
+    let corner_tangents = geometry.corner_tangents;
+
+    let mut assigned: Vec<Option<[f32;4]>> = vec![None; vertices.len()];
+    //the copies made so far of a vertex, by original index and tangent
+    let mut splits: HashMap<(u32,[u32;4]),u32> = HashMap::new();
+    for (corner, tangent) in corner_tangents.into_iter().enumerate() {
+        let index = indices[corner];
+        indices[corner] = match assigned[index as usize] {
+            None => {
+                assigned[index as usize] = Some(tangent);
+                vertices[index as usize].set_tangent(tangent);
+                index
+            }
+            Some(existing) if existing == tangent => index,
+            Some(_) => {
+                *splits.entry((index, tangent.map(f32::to_bits))).or_insert_with(|| {
+                    let mut vertex = vertices[index as usize];
+                    vertex.set_tangent(tangent);
+                    vertices.push(vertex);
+                    assigned.push(Some(tangent));
+                    (vertices.len() - 1) as u32
+                })
+            }
+        };
+    }
+    generated
+}
+
+//Lets bevy_mikktspace walk the triangles, collecting one tangent per corner
+struct TangentGeometry<'a, V: TangentVertex> {
+    vertices: &'a [V],
+    indices: &'a [u32],
+    corner_tangents: Vec<[f32;4]>,
+}
+
+impl<'a, V: TangentVertex> TangentGeometry<'a, V> {
+    fn vertex(self: &Self, face: usize, vert: usize) -> &V {
+        &self.vertices[self.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl<'a, V: TangentVertex> bevy_mikktspace::Geometry for TangentGeometry<'a, V> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).uv()
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corner_tangents[face * 3 + vert] = tangent;
+    }
+}
@@ -0,0 +1,400 @@
+/*
+3D text made from glyph meshes, like the ones in art/alphabet/alphabet_.obj.
+
+Font3d maps characters to the named objects of an obj file (an object called "A", "letter_A" or "A.001" is the glyph for A)
+and measures their bounds once. The advance of a glyph is its width plus tracking, and pairs are kerned by comparing
+the right edge profile of the first glyph with the left edge profile of the second, band by band over the font height,
+so "AV" tucks in while "HH" keeps its spacing.
+Font3d::layout places a string with alignment, line breaks (\n) and optional word wrapping to a max width.
+Text space has x to the right, y up, the first baseline at y = 0 and one font unit per obj unit.
+
+Text3d draws a layout with one InstancedMesh per glyph, every letter is an instance, all through one MeshRenderer pipeline. LetterAnimation hooks move
+the letters per character (wave, drop in on the beat, follow a spline, or anything else as a Custom function).
+ */
+
+use anyhow::*;
+use std::collections::HashMap;
+use cgmath::{Matrix4, Vector3};
+use crate::nocmp::instanced_mesh::{InstanceData, InstancedMesh};
+use crate::nocmp::mesh_renderer::{MeshDraw, MeshRenderer, PipelineKey, VertexLayout};
+use crate::nocmp::obj_parser::Mesh;
+use crate::nocmp::spline_curves::{self, CurvePoint};
+use crate::nocmp::tangents::TangentVertex;
+use crate::nocmp::bounds::{CullStats, Frustum};
+
+//horizontal slices of the font height the edge profiles are measured in
+const PROFILE_BANDS: usize = 12;
+
+pub struct Glyph {
+    //key of the glyph in the meshes the font was made from
+    pub mesh_name: String,
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+    //distance from the left/right edge of the glyph to its ink in each band, None where the band is empty
+    left_profile: [Option<f32>; PROFILE_BANDS],
+    right_profile: [Option<f32>; PROFILE_BANDS],
+}
+
+impl Glyph {
+    pub fn width(self: &Self) -> f32 {
+        self.max.x - self.min.x
+    }
+}
+
+pub struct Font3d {
+    pub glyphs: HashMap<char, Glyph>,
+    //the vertical extent of all glyphs, what the profile bands and line height come from
+    pub ascent: f32,
+    pub descent: f32,
+    //extra space after every glyph
+    pub tracking: f32,
+    pub space_advance: f32,
+    pub line_height: f32,
+    //0 turns measured kerning off, 1 closes the gap between two glyphs completely
+    pub kerning_strength: f32,
+    //hand tuned kerning, added on top of the measured kerning
+    pub kerning_pairs: HashMap<(char, char), f32>,
+}
+
+//The character an object name is the glyph of : "A", "letter_A", "glyph_A.001" and "A/Cube" are all A
+pub fn glyph_char(mesh_name: &str) -> Option<char> {
+    let object = mesh_name.split('/').next().unwrap_or(mesh_name);
+    //blender duplicates are called "A.001", but keep "." itself
+    let object = match object.rsplit_once('.') {
+        Some((name, suffix)) if !name.is_empty() && suffix.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => object,
+    };
+    let name = object.rsplit('_').next().unwrap_or(object);
+    let name = if name.is_empty() { object } else { name };
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+impl Font3d {
+
+    //A glyph for every mesh glyph_char recognizes
+    pub fn from_meshes(meshes: &HashMap<String, Mesh>) -> Result<Self> {
+        Self::from_meshes_with_names(meshes, glyph_char)
+    }
+
+    pub fn from_meshes_with_names(meshes: &HashMap<String, Mesh>, char_of: impl Fn(&str) -> Option<char>) -> Result<Self> {
+        let mut named: Vec<(char, &String, &Mesh)> = meshes.iter()
+            .filter(|(_, mesh)| !mesh.real_verts.is_empty())
+            .filter_map(|(name, mesh)| char_of(name).map(|c| (c, name, mesh)))
+            .collect();
+        if named.is_empty() {
+            bail!("none of the {} meshes is named like a glyph", meshes.len());
+        }
+        //the same order every time, so which of two meshes for one character wins doesn't depend on the hashmap
+        named.sort_by(|a, b| a.1.cmp(b.1));
+
+        let mut bounds = HashMap::new();
+        for (c, name, mesh) in &named {
+            let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+            let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+            for vertex in &mesh.real_verts {
+                let [x, y, z] = vertex.position();
+                min = Vector3::new(min.x.min(x), min.y.min(y), min.z.min(z));
+                max = Vector3::new(max.x.max(x), max.y.max(y), max.z.max(z));
+            }
+            bounds.entry(*c).or_insert((*name, *mesh, min, max));
+        }
+        let ascent = bounds.values().map(|(_, _, _, max)| max.y).fold(f32::MIN, f32::max);
+        let descent = bounds.values().map(|(_, _, min, _)| min.y).fold(f32::MAX, f32::min);
+        let height = (ascent - descent).max(f32::EPSILON);
+
+        let mut glyphs = HashMap::new();
+        for (c, (name, mesh, min, max)) in bounds {
+            //the x span of every triangle goes into all the bands it covers
+            let mut ink: [Option<(f32, f32)>; PROFILE_BANDS] = [None; PROFILE_BANDS];
+            for triangle in mesh.faces.chunks_exact(3) {
+                let points = [triangle[0], triangle[1], triangle[2]].map(|index| mesh.real_verts[index as usize].position());
+                let band = |y: f32| ((((y - descent) / height) * PROFILE_BANDS as f32) as usize).min(PROFILE_BANDS - 1);
+                let low = band(points.iter().map(|p| p[1]).fold(f32::MAX, f32::min));
+                let high = band(points.iter().map(|p| p[1]).fold(f32::MIN, f32::max));
+                let left = points.iter().map(|p| p[0]).fold(f32::MAX, f32::min);
+                let right = points.iter().map(|p| p[0]).fold(f32::MIN, f32::max);
+                for span in &mut ink[low..=high] {
+                    *span = Some(match span {
+                        Some((l, r)) => (l.min(left), r.max(right)),
+                        None => (left, right),
+                    });
+                }
+            }
+            glyphs.insert(c, Glyph {
+                mesh_name: name.clone(),
+                min,
+                max,
+                left_profile: ink.map(|span| span.map(|(left, _)| left - min.x)),
+                right_profile: ink.map(|span| span.map(|(_, right)| max.x - right)),
+            });
+        }
+
+        //a space as wide as half an average glyph
+        let average_width = glyphs.values().map(|glyph| glyph.width()).sum::<f32>() / glyphs.len() as f32;
+        Ok(Self {
+            glyphs,
+            ascent,
+            descent,
+            tracking: height * 0.08,
+            space_advance: average_width * 0.5,
+            line_height: height * 1.25,
+            kerning_strength: 0.5,
+            kerning_pairs: HashMap::new(),
+        })
+    }
+
+    //Falls back to the other case, fonts often only have capitals
+    pub fn glyph(self: &Self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&c)
+            .or_else(|| c.to_uppercase().next().and_then(|upper| self.glyphs.get(&upper)))
+            .or_else(|| c.to_lowercase().next().and_then(|lower| self.glyphs.get(&lower)))
+    }
+
+    pub fn advance(self: &Self, c: char) -> f32 {
+        match self.glyph(c) {
+            Some(glyph) => glyph.width() + self.tracking,
+            None => self.space_advance,
+        }
+    }
+
+    //Added to the advance of left when right follows it, negative pulls right closer
+    pub fn kerning(self: &Self, left: char, right: char) -> f32 {
+        let manual = self.kerning_pairs.get(&(left, right)).copied().unwrap_or(0.0);
+        let (a, b) = match (self.glyph(left), self.glyph(right)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return manual,
+        };
+        //the narrowest gap between the two where both have ink at the same height
+        let gap = a.right_profile.iter().zip(b.left_profile.iter())
+            .filter_map(|(a_gap, b_gap)| Some((*a_gap)? + (*b_gap)?))
+            .fold(f32::MAX, f32::min);
+        if gap == f32::MAX {
+            return manual;
+        }
+        -gap * self.kerning_strength + manual
+    }
+
+    pub fn measure(self: &Self, text: &str) -> f32 {
+        let chars: Vec<char> = text.chars().collect();
+        let mut width = 0.0;
+        for (i, c) in chars.iter().enumerate() {
+            width += match chars.get(i + 1) {
+                Some(next) => self.advance(*c) + self.kerning(*c, *next),
+                //no tracking after the last glyph
+                None => self.glyph(*c).map_or(self.space_advance, |glyph| glyph.width()),
+            };
+        }
+        width
+    }
+
+    //Lines break at \n, and between words when a line would get wider than max_width
+    pub fn layout(self: &Self, text: &str, align: TextAlign, max_width: Option<f32>) -> TextLayout {
+        let mut lines: Vec<String> = Vec::new();
+        for paragraph in text.split('\n') {
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                let candidate = if line.is_empty() { word.to_string() } else { format!("{line} {word}") };
+                match max_width {
+                    Some(max_width) if !line.is_empty() && self.measure(&candidate) > max_width => {
+                        lines.push(std::mem::replace(&mut line, word.to_string()));
+                    }
+                    _ => line = candidate,
+                }
+            }
+            lines.push(line);
+        }
+
+        let mut glyphs = Vec::new();
+        let mut width: f32 = 0.0;
+        let mut index = 0;
+        for (line_index, line) in lines.iter().enumerate() {
+            let line_width = self.measure(line);
+            width = width.max(line_width);
+            let mut x = match align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => -line_width * 0.5,
+                TextAlign::Right => -line_width,
+            };
+            let y = -(line_index as f32) * self.line_height;
+            let chars: Vec<char> = line.chars().collect();
+            for (i, c) in chars.iter().enumerate() {
+                if let Some(glyph) = self.glyph(*c) {
+                    //glyphs sit wherever they were modelled, move the left edge to the pen
+                    let offset = Vector3::new(x - glyph.min.x, y, 0.0);
+                    glyphs.push(PlacedGlyph {
+                        mesh_name: glyph.mesh_name.clone(),
+                        offset,
+                        center: offset + (glyph.min + glyph.max) * 0.5,
+                        index,
+                        line: line_index,
+                    });
+                }
+                x += self.advance(*c) + chars.get(i + 1).map_or(0.0, |next| self.kerning(*c, *next));
+                index += 1;
+            }
+        }
+        TextLayout {
+            glyphs,
+            width,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    //the alignment after this one, wrapping around, to step through them at runtime
+    pub fn next(self: &Self) -> TextAlign {
+        match self {
+            TextAlign::Left => TextAlign::Center,
+            TextAlign::Center => TextAlign::Right,
+            TextAlign::Right => TextAlign::Left,
+        }
+    }
+}
+
+pub struct PlacedGlyph {
+    pub mesh_name: String,
+    //translation from where the glyph was modelled to where it goes in text space
+    pub offset: Vector3<f32>,
+    //middle of the glyph bounds in text space, for rotating and scaling around
+    pub center: Vector3<f32>,
+    //position in the string, spaces and missing glyphs count too
+    pub index: usize,
+    pub line: usize,
+}
+
+#[derive(Default)]
+pub struct TextLayout {
+    pub glyphs: Vec<PlacedGlyph>,
+    //of the widest line
+    pub width: f32,
+}
+
+//Per letter animation, applied in text space after layout. Several can be stacked, see Text3d::update
+pub enum LetterAnimation {
+    //a sine going through the letters, one wave every beats_per_wave beats
+    Wave { amplitude: f32, beats_per_wave: f32, letters_per_wave: f32 },
+    //letter n falls in from height at start_beat + n * beats_per_letter, taking beats_per_drop.
+    //Letters that haven't dropped yet are hidden
+    DropIn { start_beat: f32, beats_per_letter: f32, beats_per_drop: f32, height: f32 },
+    //the letters ride along a bezier spline (see spline_curves::do_bezzy_super_spline_t_01),
+    //going around once every beats_per_loop, spacing apart in curve t per text unit
+    FollowSpline { points: Vec<CurvePoint>, beats_per_loop: f32, spacing: f32 },
+    //anything else, gets the letter and the beat
+    Custom(fn(&PlacedGlyph, f32) -> Matrix4<f32>),
+}
+
+impl LetterAnimation {
+    //The transform of the placed glyph at beat
+    pub fn transform(self: &Self, glyph: &PlacedGlyph, beat: f32) -> Matrix4<f32> {
+        match self {
+            LetterAnimation::Wave { amplitude, beats_per_wave, letters_per_wave } => {
+                let phase = beat / beats_per_wave - glyph.index as f32 / letters_per_wave;
+                Matrix4::from_translation(Vector3::new(0.0, amplitude * (phase * std::f32::consts::TAU).sin(), 0.0))
+            }
+            LetterAnimation::DropIn { start_beat, beats_per_letter, beats_per_drop, height } => {
+                let t = (beat - start_beat - glyph.index as f32 * beats_per_letter) / beats_per_drop.max(f32::EPSILON);
+                if t < 0.0 {
+                    return Matrix4::from_scale(0.0);
+                }
+                //falls faster and faster, like it's dropped
+                let t = t.min(1.0);
+                Matrix4::from_translation(Vector3::new(0.0, height * (1.0 - t * t), 0.0))
+            }
+            LetterAnimation::FollowSpline { points, beats_per_loop, spacing } => {
+                if points.len() < 4 {
+                    return Matrix4::from_scale(1.0);
+                }
+                let t = (beat / beats_per_loop + glyph.center.x * spacing).rem_euclid(1.0).min(0.9999);
+                let point = spline_curves::do_bezzy_super_spline_t_01(points, t);
+                //the spline decides where the glyph is, the layout only where it is on the line
+                Matrix4::from_translation(Vector3::new(point.x - glyph.center.x, point.y, point.z))
+            }
+            LetterAnimation::Custom(transform) => transform(glyph, beat),
+        }
+    }
+}
+
+pub struct Text3d {
+    pub font: Font3d,
+    glyph_meshes: HashMap<String, InstancedMesh>,
+    //every glyph draws with the same pipeline
+    pipeline: PipelineKey,
+    pub model_matrix: Matrix4<f32>,
+}
+
+impl Text3d {
+
+    //meshes are the glyph meshes the font was made from, one InstancedMesh is made for each glyph
+    pub fn create(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut MeshRenderer,
+        shader_descriptor: wgpu::ShaderModuleDescriptor,
+        meshes: &HashMap<String, Mesh>,
+        font: Font3d,
+    ) -> Result<Self> {
+        let pipeline = PipelineKey::new(renderer.shader(device, shader_descriptor), VertexLayout::Instanced);
+        renderer.prepare(device, pipeline);
+        let mut glyph_meshes = HashMap::new();
+        for glyph in font.glyphs.values() {
+            let mesh = meshes.get(&glyph.mesh_name)
+                .ok_or_else(|| anyhow!("no mesh {} for the font", glyph.mesh_name))?;
+            glyph_meshes.insert(glyph.mesh_name.clone(), InstancedMesh::create(device, queue, renderer, mesh, 8)?);
+        }
+        Ok(Self {
+            font,
+            glyph_meshes,
+            pipeline,
+            model_matrix: Matrix4::from_scale(1.0),
+        })
+    }
+
+    //Fills the instances with layout, animations are applied in order (the first one closest to the letter).
+    //An empty layout hides the text
+    pub fn update(
+        self: &mut Self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &TextLayout,
+        animations: &[LetterAnimation],
+        color: [f32;4],
+        beat: f32,
+    ) {
+        let mut instances: HashMap<&str, Vec<InstanceData>> = HashMap::new();
+        for glyph in &layout.glyphs {
+            let animated = animations.iter()
+                .fold(Matrix4::from_translation(glyph.offset), |matrix, animation| animation.transform(glyph, beat) * matrix);
+            instances.entry(glyph.mesh_name.as_str()).or_default().push(
+                InstanceData::new(self.model_matrix * animated)
+                    .with_color(color)
+                    .with_params([glyph.index as f32, glyph.line as f32, beat, 0.0])
+            );
+        }
+        for (name, mesh) in &mut self.glyph_meshes {
+            mesh.set_instances(device, queue, instances.get(name.as_str()).map_or(&[], |instances| instances.as_slice()));
+        }
+    }
+
+    //every letter is tested on its own, see InstancedMesh::cull
+    pub fn cull(self: &mut Self, queue: &wgpu::Queue, frustum: &Frustum, stats: &mut CullStats) {
+        for mesh in self.glyph_meshes.values_mut() {
+            mesh.cull(queue, frustum, stats);
+        }
+    }
+
+    //one draw per glyph that has letters showing, for MeshRenderer::render
+    pub fn draws(self: &Self) -> Vec<MeshDraw<'_>> {
+        self.glyph_meshes.values().filter_map(|mesh| mesh.draw(self.pipeline)).collect()
+    }
+}
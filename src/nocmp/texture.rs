@@ -3,11 +3,130 @@ use std::io::Read;
 use image::GenericImageView;
 use anyhow::*;
 use wgpu::Device;
+use crate::nocmp::bcn;
 
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    //how the view should be declared in a bind group layout (D2, Cube, D3..)
+    pub view_dimension: wgpu::TextureViewDimension,
+    //how the sampler should be declared in a bind group layout, comparison samplers need their own type
+    pub sampler_binding_type: wgpu::SamplerBindingType,
+}
+
+//How the mip chain of a loaded texture gets filled.
+//Gpu renders each level from the previous with a linear blit, Cpu downsamples with the image crate before upload.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum MipmapGeneration {
+    None,
+    Cpu,
+    Gpu,
+}
+
+#[derive(Copy,Clone,Debug)]
+pub struct SamplerConfig {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub address_mode_w: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    //1 is off, wgpu needs all filters to be linear when this is above 1, so create_sampler forces that
+    pub anisotropy_clamp: u16,
+    pub compare: Option<wgpu::CompareFunction>,
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+}
+
+impl Default for SamplerConfig {
+    //the sampler textures have always been loaded with
+    fn default() -> Self {
+        SamplerConfig {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            anisotropy_clamp: 1,
+            compare: None,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
+        }
+    }
+}
+
+impl SamplerConfig {
+    pub fn trilinear(address_mode: wgpu::AddressMode, anisotropy_clamp: u16) -> Self {
+        SamplerConfig {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy_clamp,
+            ..Default::default()
+        }
+    }
+
+    pub fn create_sampler(self: &Self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Sampler {
+        let anisotropy_clamp = self.anisotropy_clamp.max(1);
+        let (mag_filter,min_filter,mipmap_filter) = if anisotropy_clamp > 1 {
+            (wgpu::FilterMode::Linear,wgpu::FilterMode::Linear,wgpu::FilterMode::Linear)
+        } else {
+            (self.mag_filter,self.min_filter,self.mipmap_filter)
+        };
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: self.address_mode_w,
+            mag_filter,
+            min_filter,
+            mipmap_filter,
+            lod_min_clamp: self.lod_min_clamp,
+            lod_max_clamp: self.lod_max_clamp,
+            compare: self.compare,
+            anisotropy_clamp,
+            border_color: None,
+        })
+    }
+}
+
+#[derive(Copy,Clone,Debug)]
+pub struct TextureConfig {
+    pub mipmaps: MipmapGeneration,
+    pub sampler: SamplerConfig,
+    //8 bit images hold srgb encoded color, set this to get an Rgba8UnormSrgb texture that samples as linear.
+    //Leave it off for data (masks, normal maps, lookup textures)
+    pub srgb: bool,
+}
+
+impl Default for TextureConfig {
+    fn default() -> Self {
+        TextureConfig {
+            mipmaps: MipmapGeneration::None,
+            sampler: SamplerConfig::default(),
+            srgb: false,
+        }
+    }
+}
+
+impl TextureConfig {
+    //full mip chain and trilinear anisotropic filtering, for textures that get minified a lot
+    pub fn mipmapped(address_mode: wgpu::AddressMode, anisotropy_clamp: u16) -> Self {
+        TextureConfig {
+            mipmaps: MipmapGeneration::Gpu,
+            sampler: SamplerConfig::trilinear(address_mode,anisotropy_clamp),
+            srgb: false,
+        }
+    }
+}
+
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
 }
 
 impl Texture {
@@ -17,8 +136,41 @@ impl Texture {
         bytes: &[u8],
         label: &str,
     ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_bytes_with_config(device, queue, bytes, label, &TextureConfig::default())
+    }
+
+    //Picks the loader from the magic bytes, KTX2 and DDS containers go straight to the gpu,
+    //everything else (png, jpeg, hdr, exr) goes through the image crate.
+    pub fn from_bytes_with_config(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        config: &TextureConfig,
+    ) -> Result<Self> {
+        Self::from_bytes_with_hint(device, queue, bytes, label, config, None)
+    }
+
+    //format_hint is only used for hdr and exr, which the image crate can not always tell from the bytes
+    fn from_bytes_with_hint(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        config: &TextureConfig,
+        format_hint: Option<image::ImageFormat>,
+    ) -> Result<Self> {
+        if bytes.starts_with(&KTX2_MAGIC) {
+            return Self::from_ktx2_bytes(device, queue, bytes, label, config);
+        }
+        if bytes.starts_with(b"DDS ") {
+            return Self::from_dds_bytes(device, queue, bytes, label, config);
+        }
+        let img = match format_hint {
+            Some(format @ (image::ImageFormat::Hdr | image::ImageFormat::OpenExr)) => image::load_from_memory_with_format(bytes, format)?,
+            _ => image::load_from_memory(bytes)?,
+        };
+        Self::from_image_with_config(device, queue, &img, Some(label), config)
     }
 
     pub fn from_path(
@@ -27,13 +179,23 @@ impl Texture {
         path: &str,
         label: &str,
     ) -> Result<Self> {
+        Self::from_path_with_config(device, queue, path, label, &TextureConfig::default())
+    }
+
+    pub fn from_path_with_config(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &str,
+        label: &str,
+        config: &TextureConfig,
+    ) -> Result<Self> {
 
         println!("Opening Texture : {}",path);
         let mut f = File::open(path).unwrap();
         let mut buffer = Vec::new();
         f.read_to_end(&mut buffer).expect("TODO: panic message");
-        let img = image::load_from_memory(buffer.as_slice())?;
-        Self::from_image(device, queue, &img, Some(label))
+        //the container magic decides first, the extension only helps with hdr and exr
+        Self::from_bytes_with_hint(device, queue, buffer.as_slice(), label, config, image::ImageFormat::from_path(path).ok())
         /*match f.read_to_end(&mut buffer) {
             Ok(_)=> {
 
@@ -52,59 +214,252 @@ impl Texture {
         img: &image::DynamicImage,
         label: Option<&str>,
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
-        let texture_size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
+        Self::from_image_with_config(device, queue, img, label, &TextureConfig::default())
+    }
+
+    //High dynamic range images (hdr, exr) become Rgba16Float textures, the rest Rgba8Unorm (Rgba8UnormSrgb with config.srgb).
+    pub fn from_image_with_config(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        config: &TextureConfig,
+    ) -> Result<Self> {
+        let (width, height) = img.dimensions();
+        let mip_level_count = match config.mipmaps {
+            MipmapGeneration::None => 1,
+            _ => mip_level_count(width,height),
+        };
+
+        let is_hdr = matches!(img, image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_));
+        let (format, mut levels) = if is_hdr {
+            let rgba = img.to_rgba32f();
+            let mut levels = vec![rgba32f_to_rgba16f_bytes(&rgba)];
+            if config.mipmaps == MipmapGeneration::Cpu {
+                let mut level_image = rgba;
+                for mip_level in 1..mip_level_count {
+                    level_image = image::imageops::resize(&level_image,(width >> mip_level).max(1),(height >> mip_level).max(1),image::imageops::FilterType::Triangle);
+                    levels.push(rgba32f_to_rgba16f_bytes(&level_image));
+                }
+            }
+            (wgpu::TextureFormat::Rgba16Float, levels)
+        } else {
+            let rgba = img.to_rgba8();
+            let mut levels = Vec::new();
+            if config.mipmaps == MipmapGeneration::Cpu {
+                let mut level_image = rgba.clone();
+                for mip_level in 1..mip_level_count {
+                    //each level is made from the previous one, triangle filtering a 2x2 footprint is a box filter
+                    level_image = image::imageops::resize(&level_image,(width >> mip_level).max(1),(height >> mip_level).max(1),image::imageops::FilterType::Triangle);
+                    levels.push(level_image.clone().into_raw());
+                }
+            }
+            levels.insert(0, rgba.into_raw());
+            let format = if config.srgb { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm };
+            (format, levels)
         };
+        if config.mipmaps == MipmapGeneration::None {
+            levels.truncate(1);
+        }
+
+        Ok(Self::create_with_levels(device, queue, label, format, width, height, mip_level_count, &levels, config))
+    }
+
+    //KTX2 files with BCn, rgba8 or float data. Only the first layer/face is used, and supercompressed files are not supported.
+    pub fn from_ktx2_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        config: &TextureConfig,
+    ) -> Result<Self> {
+        let reader = ktx2::Reader::new(bytes).map_err(|e| anyhow!("{} is not a valid ktx2 file : {}",label,e))?;
+        let header = reader.header();
+        if header.supercompression_scheme.is_some() {
+            return Err(anyhow!("{} uses ktx2 supercompression {:?}, which is not supported",label,header.supercompression_scheme));
+        }
+        let format = header.format.and_then(ktx2_format_to_wgpu)
+            .ok_or(anyhow!("{} has unsupported ktx2 format {:?}",label,header.format))?;
+        let levels: Vec<&[u8]> = reader.levels().collect();
+        Self::from_container_levels(device, queue, label, format, header.pixel_width, header.pixel_height.max(1), &levels, config)
+    }
+
+    //DDS files with BCn (DXT1/3/5, ATI1/2 or DX10 headers), rgba8 or float data. Only the first array layer is used.
+    pub fn from_dds_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        config: &TextureConfig,
+    ) -> Result<Self> {
+        let dds = ddsfile::Dds::read(bytes).map_err(|e| anyhow!("{} is not a valid dds file : {}",label,e))?;
+        let format = dds_format_to_wgpu(&dds).ok_or(anyhow!("{} has an unsupported dds format",label))?;
+        let (width, height) = (dds.get_width(), dds.get_height());
+        let data = dds.get_data(0).map_err(|e| anyhow!("{} : {}",label,e))?;
+
+        //the mips of a layer are stored back to back, smallest last
+        let mut levels = Vec::new();
+        let mut offset = 0;
+        for mip_level in 0..dds.get_num_mipmap_levels() {
+            let size = level_byte_size(format, (width >> mip_level).max(1), (height >> mip_level).max(1));
+            if offset + size > data.len() {
+                break;
+            }
+            levels.push(&data[offset..offset + size]);
+            offset += size;
+        }
+        Self::from_container_levels(device, queue, label, format, width, height, &levels, config)
+    }
+
+    fn from_container_levels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        levels: &[&[u8]],
+        config: &TextureConfig,
+    ) -> Result<Self> {
+        if levels.is_empty() {
+            return Err(anyhow!("{} has no mip levels",label));
+        }
+        //a level can hold several layers or faces, we only keep the first one
+        let levels: Vec<Vec<u8>> = levels.iter().enumerate().map(|(mip_level, level)| {
+            let size = level_byte_size(format, (width >> mip_level).max(1), (height >> mip_level).max(1));
+            level[..size.min(level.len())].to_vec()
+        }).collect();
+
+        if !format.is_compressed() {
+            let mip_level_count = if config.mipmaps == MipmapGeneration::Gpu && levels.len() == 1 {
+                mip_level_count(width,height)
+            } else {
+                levels.len() as u32
+            };
+            return Ok(Self::create_with_levels(device, queue, Some(label), format, width, height, mip_level_count, &levels, config));
+        }
+
+        let supported = device.features().contains(format.required_features());
+        if supported && width % 4 == 0 && height % 4 == 0 {
+            //we can't render into block compressed textures, so the mips are the ones stored in the file
+            return Ok(Self::create_with_levels(device, queue, Some(label), format, width, height, levels.len() as u32, &levels, config));
+        }
+
+        println!("{} : {:?} not supported by the adapter, decoding on the cpu",label,format);
+        let fallback_format = if format.is_srgb() { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm };
+        let mut decoded = Vec::new();
+        for (mip_level, level) in levels.iter().enumerate() {
+            decoded.push(bcn::decompress_to_rgba8(format, (width >> mip_level).max(1), (height >> mip_level).max(1), level)?);
+        }
+        let mip_level_count = if config.mipmaps == MipmapGeneration::Gpu && decoded.len() == 1 {
+            mip_level_count(width,height)
+        } else {
+            decoded.len() as u32
+        };
+        Ok(Self::create_with_levels(device, queue, Some(label), fallback_format, width, height, mip_level_count, &decoded, config))
+    }
+
+    //Creates a 2D texture and uploads the given levels, starting at mip 0.
+    //Levels not provided are filled by the gpu blit if the config asks for it.
+    fn create_with_levels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        mip_level_count: u32,
+        levels: &[Vec<u8>],
+        config: &TextureConfig,
+    ) -> Self {
+        let generate_on_gpu = config.mipmaps == MipmapGeneration::Gpu
+            && (levels.len() as u32) < mip_level_count
+            && !format.is_compressed();
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if generate_on_gpu {
+            //the blit renders into each mip level
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+        let mip_level_count = if generate_on_gpu { mip_level_count } else { levels.len() as u32 };
 
         let texture = device.create_texture(
             &wgpu::TextureDescriptor {
                 label,
-                size: texture_size,
-                mip_level_count: 1,
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8Unorm,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                format,
+                usage,
                 view_formats: &[],
             }
         );
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            texture_size,
-        );
+        let (block_width, block_height) = format.block_dimensions();
+        let block_size = format.block_copy_size(None).unwrap_or(4);
+        for (mip_level, level) in levels.iter().enumerate() {
+            let level_width = (width >> mip_level).max(1);
+            let level_height = (height >> mip_level).max(1);
+            let blocks_wide = (level_width + block_width - 1) / block_width;
+            let blocks_high = (level_height + block_height - 1) / block_height;
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: mip_level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                level,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(blocks_wide * block_size),
+                    rows_per_image: Some(blocks_high),
+                },
+                //compressed copies have to cover whole blocks, even for the tiny mips
+                wgpu::Extent3d {
+                    width: blocks_wide * block_width,
+                    height: blocks_high * block_height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        if generate_on_gpu {
+            generate_mipmaps_on_gpu(device,queue,&texture,format,mip_level_count);
+        }
 
+        //float32 textures can't be filtered without FLOAT32_FILTERABLE, same fallback as the render targets
+        let mut sampler_config = config.sampler;
+        if !Self::is_filterable(device,format) {
+            sampler_config.mag_filter = wgpu::FilterMode::Nearest;
+            sampler_config.min_filter = wgpu::FilterMode::Nearest;
+            sampler_config.mipmap_filter = wgpu::FilterMode::Nearest;
+            sampler_config.anisotropy_clamp = 1;
+        }
         let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+        let sampler = sampler_config.create_sampler(device,label);
+        let sampler_binding_type = Self::sampler_binding_type_for(device,format,config.sampler.compare);
 
-        Ok(Self { texture, sampler, view: texture_view})
+        Self { texture, sampler, view: texture_view, view_dimension: wgpu::TextureViewDimension::D2, sampler_binding_type}
     }
 
     pub fn create_rtt_texture(width: u32, height: u32, device: &wgpu::Device,format : wgpu::TextureFormat, label: Option<&str>)
     -> Result<Self>
+    {
+        Self::create_rtt_texture_with_sampler(width,height,device,format,
+                                              wgpu::FilterMode::Linear,wgpu::FilterMode::Nearest,wgpu::AddressMode::ClampToEdge,label)
+    }
+
+    //Same as create_rtt_texture, but lets the caller pick how the render target is sampled
+    //when it is read back as a texture (i.e by the next shadertoy like buffer)
+    pub fn create_rtt_texture_with_sampler(width: u32, height: u32, device: &wgpu::Device,format : wgpu::TextureFormat,
+                                           mag_filter: wgpu::FilterMode, min_filter: wgpu::FilterMode,
+                                           address_mode: wgpu::AddressMode, label: Option<&str>)
+    -> Result<Self>
     {
         let texture_descriptor_rtt = wgpu::TextureDescriptor {
             label,
@@ -123,22 +478,321 @@ impl Texture {
 
         let texture_rtt = device.create_texture(&texture_descriptor_rtt);
 
+        //formats like Rgba32Float can not be filtered unless the device has FLOAT32_FILTERABLE,
+        //so we fall back to nearest filtering for those.
+        let filterable = Self::is_filterable(device,format);
+        let mag_filter = if filterable { mag_filter } else { wgpu::FilterMode::Nearest };
+        let min_filter = if filterable { min_filter } else { wgpu::FilterMode::Nearest };
         let sampler_rtt = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            mag_filter,
+            min_filter,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let texture_view = texture_rtt.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler_binding_type = Self::sampler_binding_type_for(device,format,None);
+        Ok(Self { texture:texture_rtt, sampler:sampler_rtt, view: texture_view, view_dimension: wgpu::TextureViewDimension::D2, sampler_binding_type})
+    }
+
+    //Faces are expected in the wgpu/D3D order : +X, -X, +Y, -Y, +Z, -Z
+    pub fn create_cubemap_from_paths(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        paths: &[&str;6],
+        label: &str,
+    ) -> Result<Self> {
+        let mut faces = Vec::new();
+        for path in paths {
+            println!("Opening cubemap face : {}",path);
+            let mut f = File::open(path)?;
+            let mut buffer = Vec::new();
+            f.read_to_end(&mut buffer)?;
+            faces.push(image::load_from_memory(buffer.as_slice())?);
+        }
+        Self::create_cubemap_from_images(device,queue,&faces,Some(label))
+    }
+
+    pub fn create_cubemap_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage],
+        label: Option<&str>,
+    ) -> Result<Self> {
+        if faces.len() != 6 {
+            return Err(anyhow!("a cubemap needs 6 faces, got {}",faces.len()));
+        }
+        let (size,_) = faces[0].dimensions();
+        let mut data: Vec<u8> = Vec::with_capacity((size*size*4*6) as usize);
+        for face in faces {
+            if face.dimensions() != (size,size) {
+                return Err(anyhow!("cubemap faces must be square and the same size, expected {}x{} got {:?}",size,size,face.dimensions()));
+            }
+            data.extend_from_slice(&face.to_rgba8());
+        }
+        Ok(Self::create_cubemap_from_rgba8(device,queue,size,&data,label))
+    }
+
+    //Projects an equirectangular (latitude/longitude) panorama onto the six faces of a cubemap.
+    //This is done on the cpu at load time, face_size is the width/height of each face.
+    pub fn create_cubemap_from_equirectangular(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        panorama: &image::DynamicImage,
+        face_size: u32,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let rgba = panorama.to_rgba8();
+        let (width,height) = rgba.dimensions();
+        let mut data: Vec<u8> = Vec::with_capacity((face_size*face_size*4*6) as usize);
+        for face in 0..6 {
+            for y in 0..face_size {
+                for x in 0..face_size {
+                    let direction = cube_face_direction(face,x,y,face_size);
+                    let (u,v) = equirectangular_uv(direction);
+                    //bilinear sample, wrapping horizontally and clamping vertically
+                    let fx = u * width as f32 - 0.5;
+                    let fy = (v * height as f32 - 0.5).clamp(0.0,(height-1) as f32);
+                    let x0 = fx.floor();
+                    let y0 = fy.floor();
+                    let tx = fx - x0;
+                    let ty = fy - y0;
+                    let x0 = (x0 as i64).rem_euclid(width as i64) as u32;
+                    let x1 = (x0 + 1) % width;
+                    let y0 = y0 as u32;
+                    let y1 = (y0 + 1).min(height-1);
+                    for c in 0..4 {
+                        let top = rgba.get_pixel(x0,y0)[c] as f32 * (1.0-tx) + rgba.get_pixel(x1,y0)[c] as f32 * tx;
+                        let bottom = rgba.get_pixel(x0,y1)[c] as f32 * (1.0-tx) + rgba.get_pixel(x1,y1)[c] as f32 * tx;
+                        data.push((top * (1.0-ty) + bottom * ty).round() as u8);
+                    }
+                }
+            }
+        }
+        Ok(Self::create_cubemap_from_rgba8(device,queue,face_size,&data,label))
+    }
+
+    fn create_cubemap_from_rgba8(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: u32,
+        data: &[u8],
+        label: Option<&str>,
+    ) -> Self {
+        let texture_size = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label,
+                size: texture_size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            }
+        );
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size),
+                rows_per_image: Some(size),
+            },
+            texture_size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
-        let texture_view = texture_rtt.create_view(&wgpu::TextureViewDescriptor::default());
-        Ok(Self { texture:texture_rtt, sampler:sampler_rtt, view: texture_view})
+        Self { texture, view, sampler, view_dimension: wgpu::TextureViewDimension::Cube, sampler_binding_type: wgpu::SamplerBindingType::Filtering }
+    }
+
+    //Creates a 3D (volume) texture from tightly packed texel data, slice after slice.
+    pub fn create_volume(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        depth: u32,
+        format: wgpu::TextureFormat,
+        data: &[u8],
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let bytes_per_texel = format.block_copy_size(None)
+            .ok_or(anyhow!("volume textures need an uncompressed color format, got {:?}",format))?;
+        let expected = (width * height * depth * bytes_per_texel) as usize;
+        if data.len() != expected {
+            return Err(anyhow!("volume data is {} bytes, expected {} for {}x{}x{} {:?}",data.len(),expected,width,height,depth,format));
+        }
+        let texture_size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: depth,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label,
+                size: texture_size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            }
+        );
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_texel * width),
+                rows_per_image: Some(height),
+            },
+            texture_size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        //noise volumes are usually tiling, so we repeat
+        let filter = if Self::is_filterable(device,format) { wgpu::FilterMode::Linear } else { wgpu::FilterMode::Nearest };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let sampler_binding_type = Self::sampler_binding_type_for(device,format,None);
+        Ok(Self { texture, view, sampler, view_dimension: wgpu::TextureViewDimension::D3, sampler_binding_type })
+    }
+
+    //Reads a headerless volume file, i.e the .raw files most volume tools export
+    pub fn create_volume_from_raw_file(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &str,
+        width: u32,
+        height: u32,
+        depth: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Result<Self> {
+        println!("Opening volume texture : {}",path);
+        let mut f = File::open(path)?;
+        let mut buffer = Vec::new();
+        f.read_to_end(&mut buffer)?;
+        Self::create_volume(device,queue,width,height,depth,format,buffer.as_slice(),Some(label))
+    }
+
+    //A tiling rgba noise volume, like the 3D noise channels on shadertoy.
+    //Each channel is value noise at a different frequency, so r is the smoothest and a the most detailed.
+    pub fn create_noise_volume(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: u32,
+        seed: u64,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let lattice: Vec<f32> = (0..size*size*size).map(|_| rng.gen::<f32>()).collect();
+        let lattice_value = |x: u32, y: u32, z: u32| -> f32 {
+            lattice[((z % size) * size * size + (y % size) * size + (x % size)) as usize]
+        };
+
+        let mut data: Vec<u8> = Vec::with_capacity((size*size*size*4) as usize);
+        for z in 0..size {
+            for y in 0..size {
+                for x in 0..size {
+                    for channel in 0..4 {
+                        //cell count per axis, has to divide size for the volume to keep tiling
+                        let cells = (size >> (3 - channel)).max(1);
+                        let cell_size = size as f32 / cells as f32;
+                        let fx = x as f32 / cell_size;
+                        let fy = y as f32 / cell_size;
+                        let fz = z as f32 / cell_size;
+                        let (ix,iy,iz) = (fx.floor() as u32,fy.floor() as u32,fz.floor() as u32);
+                        let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+                        let (tx,ty,tz) = (smooth(fx.fract()),smooth(fy.fract()),smooth(fz.fract()));
+                        //the lattice is sampled with a stride so each cell corner lands on the same lattice point when wrapping
+                        let stride = size / cells;
+                        let corner = |cx: u32, cy: u32, cz: u32| -> f32 {
+                            lattice_value(((ix + cx) % cells) * stride + channel,((iy + cy) % cells) * stride,((iz + cz) % cells) * stride)
+                        };
+                        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+                        let value = lerp(
+                            lerp(lerp(corner(0,0,0),corner(1,0,0),tx),lerp(corner(0,1,0),corner(1,1,0),tx),ty),
+                            lerp(lerp(corner(0,0,1),corner(1,0,1),tx),lerp(corner(0,1,1),corner(1,1,1),tx),ty),
+                            tz
+                        );
+                        data.push((value * 255.0).round() as u8);
+                    }
+                }
+            }
+        }
+        Self::create_volume(device,queue,size,size,size,wgpu::TextureFormat::Rgba8Unorm,&data,label)
+    }
+
+    pub fn is_filterable(device: &wgpu::Device,format : wgpu::TextureFormat) -> bool {
+        match format.sample_type(None,Some(device.features())) {
+            Some(wgpu::TextureSampleType::Float { filterable }) => filterable,
+            _ => false,
+        }
+    }
+
+    pub fn sampler_binding_type_for(device: &wgpu::Device,format : wgpu::TextureFormat, compare: Option<wgpu::CompareFunction>)
+        -> wgpu::SamplerBindingType {
+        if compare.is_some() {
+            wgpu::SamplerBindingType::Comparison
+        } else if Self::is_filterable(device,format) {
+            wgpu::SamplerBindingType::Filtering
+        } else {
+            wgpu::SamplerBindingType::NonFiltering
+        }
     }
 
     pub const DEPTH_FORMAT : wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
     pub fn create_depth_texture(device: &wgpu::Device,config: &wgpu::SurfaceConfiguration, label: &str)
         -> Self
+    {
+        Self::create_multisampled_depth_texture(device,config,1,label)
+    }
+
+    //sample_count has to match the color target the depth is used with
+    pub fn create_multisampled_depth_texture(device: &wgpu::Device,config: &wgpu::SurfaceConfiguration, sample_count: u32, label: &str)
+        -> Self
     {
         let size = wgpu::Extent3d{
             width: config.width,
@@ -150,7 +804,7 @@ impl Texture {
          label: Some(label)   ,
             size,
             mip_level_count : 1,
-            sample_count : 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT
@@ -176,16 +830,267 @@ impl Texture {
             }
         );
 
-        Self{texture,view,sampler}
+        Self{texture,view,sampler,view_dimension: wgpu::TextureViewDimension::D2,sampler_binding_type: wgpu::SamplerBindingType::Comparison}
     }
 
 }
 
+const KTX2_MAGIC: [u8;12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+fn rgba32f_to_rgba16f_bytes(image: &image::Rgba32FImage) -> Vec<u8> {
+    let halfs: Vec<half::f16> = image.as_raw().iter().map(|v| half::f16::from_f32(*v)).collect();
+    bytemuck::cast_slice(&halfs).to_vec()
+}
+
+//Bytes of one tightly packed mip level, in whole blocks for compressed formats
+fn level_byte_size(format: wgpu::TextureFormat, width: u32, height: u32) -> usize {
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(4);
+    (((width + block_width - 1) / block_width) * ((height + block_height - 1) / block_height) * block_size) as usize
+}
+
+fn ktx2_format_to_wgpu(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+    let format = match format {
+        ktx2::Format::R8G8B8A8_UNORM => F::Rgba8Unorm,
+        ktx2::Format::R8G8B8A8_SRGB => F::Rgba8UnormSrgb,
+        ktx2::Format::R16G16B16A16_SFLOAT => F::Rgba16Float,
+        ktx2::Format::R32G32B32A32_SFLOAT => F::Rgba32Float,
+        ktx2::Format::BC1_RGB_UNORM_BLOCK | ktx2::Format::BC1_RGBA_UNORM_BLOCK => F::Bc1RgbaUnorm,
+        ktx2::Format::BC1_RGB_SRGB_BLOCK | ktx2::Format::BC1_RGBA_SRGB_BLOCK => F::Bc1RgbaUnormSrgb,
+        ktx2::Format::BC2_UNORM_BLOCK => F::Bc2RgbaUnorm,
+        ktx2::Format::BC2_SRGB_BLOCK => F::Bc2RgbaUnormSrgb,
+        ktx2::Format::BC3_UNORM_BLOCK => F::Bc3RgbaUnorm,
+        ktx2::Format::BC3_SRGB_BLOCK => F::Bc3RgbaUnormSrgb,
+        ktx2::Format::BC4_UNORM_BLOCK => F::Bc4RUnorm,
+        ktx2::Format::BC4_SNORM_BLOCK => F::Bc4RSnorm,
+        ktx2::Format::BC5_UNORM_BLOCK => F::Bc5RgUnorm,
+        ktx2::Format::BC5_SNORM_BLOCK => F::Bc5RgSnorm,
+        ktx2::Format::BC6H_UFLOAT_BLOCK => F::Bc6hRgbUfloat,
+        ktx2::Format::BC6H_SFLOAT_BLOCK => F::Bc6hRgbFloat,
+        ktx2::Format::BC7_UNORM_BLOCK => F::Bc7RgbaUnorm,
+        ktx2::Format::BC7_SRGB_BLOCK => F::Bc7RgbaUnormSrgb,
+        _ => return None,
+    };
+    Some(format)
+}
+
+fn dds_format_to_wgpu(dds: &ddsfile::Dds) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+    use ddsfile::{D3DFormat, DxgiFormat, FourCC};
+    if let Some(format) = dds.get_dxgi_format() {
+        return match format {
+            DxgiFormat::R8G8B8A8_UNorm => Some(F::Rgba8Unorm),
+            DxgiFormat::R8G8B8A8_UNorm_sRGB => Some(F::Rgba8UnormSrgb),
+            DxgiFormat::R16G16B16A16_Float => Some(F::Rgba16Float),
+            DxgiFormat::R32G32B32A32_Float => Some(F::Rgba32Float),
+            DxgiFormat::BC1_Typeless | DxgiFormat::BC1_UNorm => Some(F::Bc1RgbaUnorm),
+            DxgiFormat::BC1_UNorm_sRGB => Some(F::Bc1RgbaUnormSrgb),
+            DxgiFormat::BC2_Typeless | DxgiFormat::BC2_UNorm => Some(F::Bc2RgbaUnorm),
+            DxgiFormat::BC2_UNorm_sRGB => Some(F::Bc2RgbaUnormSrgb),
+            DxgiFormat::BC3_Typeless | DxgiFormat::BC3_UNorm => Some(F::Bc3RgbaUnorm),
+            DxgiFormat::BC3_UNorm_sRGB => Some(F::Bc3RgbaUnormSrgb),
+            DxgiFormat::BC4_Typeless | DxgiFormat::BC4_UNorm => Some(F::Bc4RUnorm),
+            DxgiFormat::BC4_SNorm => Some(F::Bc4RSnorm),
+            DxgiFormat::BC5_Typeless | DxgiFormat::BC5_UNorm => Some(F::Bc5RgUnorm),
+            DxgiFormat::BC5_SNorm => Some(F::Bc5RgSnorm),
+            DxgiFormat::BC6H_Typeless | DxgiFormat::BC6H_UF16 => Some(F::Bc6hRgbUfloat),
+            DxgiFormat::BC6H_SF16 => Some(F::Bc6hRgbFloat),
+            DxgiFormat::BC7_Typeless | DxgiFormat::BC7_UNorm => Some(F::Bc7RgbaUnorm),
+            DxgiFormat::BC7_UNorm_sRGB => Some(F::Bc7RgbaUnormSrgb),
+            _ => None,
+        };
+    }
+    match dds.get_d3d_format() {
+        Some(D3DFormat::DXT1) => return Some(F::Bc1RgbaUnorm),
+        Some(D3DFormat::DXT2) | Some(D3DFormat::DXT3) => return Some(F::Bc2RgbaUnorm),
+        Some(D3DFormat::DXT4) | Some(D3DFormat::DXT5) => return Some(F::Bc3RgbaUnorm),
+        Some(D3DFormat::A16B16G16R16F) => return Some(F::Rgba16Float),
+        Some(D3DFormat::A32B32G32R32F) => return Some(F::Rgba32Float),
+        _ => {}
+    }
+    //older exporters write the BC4/BC5 four character codes without a DX10 header
+    match dds.header.spf.fourcc.as_ref().map(|fourcc| fourcc.0) {
+        Some(FourCC::ATI1) | Some(FourCC::BC4_UNORM) => Some(F::Bc4RUnorm),
+        Some(FourCC::BC4_SNORM) => Some(F::Bc4RSnorm),
+        Some(FourCC::ATI2) => Some(F::Bc5RgUnorm),
+        Some(FourCC::BC5_SNORM) => Some(F::Bc5RgSnorm),
+        _ => None,
+    }
+}
+
+//Fills mip levels 1.. by rendering each level from the one above with a linear filtered blit.
+//The texture needs RENDER_ATTACHMENT usage and a filterable, renderable format.
+fn generate_mipmaps_on_gpu(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    mip_level_count: u32,
+) {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("mipmap blit shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../shadertoys/blit.wgsl").into()),
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("mipmap blit pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    });
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("mipmap blit sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    let views: Vec<wgpu::TextureView> = (0..mip_level_count).map(|mip_level| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("mipmap level view"),
+            base_mip_level: mip_level,
+            mip_level_count: Some(1),
+            ..Default::default()
+        })
+    }).collect();
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("mipmap encoder"),
+    });
+    for target_level in 1..mip_level_count as usize {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&views[target_level - 1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: None,
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("mipmap pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &views[target_level],
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+}
+
+//Direction through pixel x,y of a cube face, faces in the +X, -X, +Y, -Y, +Z, -Z order
+fn cube_face_direction(face: u32, x: u32, y: u32, face_size: u32) -> [f32;3] {
+    let s = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+    let t = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+    let direction = match face {
+        0 => [1.0, -t, -s],
+        1 => [-1.0, -t, s],
+        2 => [s, 1.0, t],
+        3 => [s, -1.0, -t],
+        4 => [s, -t, 1.0],
+        _ => [-s, -t, -1.0],
+    };
+    let length = (direction[0]*direction[0] + direction[1]*direction[1] + direction[2]*direction[2]).sqrt();
+    [direction[0]/length, direction[1]/length, direction[2]/length]
+}
+
+fn equirectangular_uv(direction: [f32;3]) -> (f32,f32) {
+    let u = 0.5 + direction[0].atan2(-direction[2]) / (2.0 * std::f32::consts::PI);
+    let v = direction[1].clamp(-1.0,1.0).acos() / std::f32::consts::PI;
+    (u,v)
+}
+
+//Layout entries for a texture and its sampler at binding and binding+1,
+//generated from the texture so cubemaps, volumes and unfilterable formats get the right binding types.
+pub fn texture_layout_entries(device: &Device, texture: &Texture, binding: u32, visibility: wgpu::ShaderStages)
+    -> [wgpu::BindGroupLayoutEntry;2] {
+    //the layout has to follow the texture format, float32 render targets are not filterable
+    let format = texture.texture.format();
+    let sample_type = if format.is_depth_stencil_format() {
+        wgpu::TextureSampleType::Depth
+    } else {
+        wgpu::TextureSampleType::Float {
+            filterable: Texture::is_filterable(device,format)
+        }
+    };
+    [
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: texture.view_dimension,
+                sample_type,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: binding + 1,
+            visibility,
+            ty: wgpu::BindingType::Sampler(texture.sampler_binding_type),
+            count: None,
+        }
+    ]
+}
+
+pub fn texture_bind_group_entries(texture: &Texture, binding: u32) -> [wgpu::BindGroupEntry<'_>;2] {
+    [
+        wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(&texture.view),
+        },
+        wgpu::BindGroupEntry {
+            binding: binding + 1,
+            resource: wgpu::BindingResource::Sampler(&texture.sampler),
+        }
+    ]
+}
+
 /*
 so, bind group and bind group layout seems to describe
 "multi-texturing" in old school terms.
 I am guessing this must match with the shader as well,i.e if
 you say 3 textures, and your shader reads 4, mayhem!?!?
+Each texture takes two bindings, texture n is at binding n*2 and its sampler at n*2+1.
  */
 pub fn setup_texture_stage(device: &Device, textures: &[&Texture], label: Option<&str> )
                            -> Result<(wgpu::BindGroupLayout,wgpu::BindGroup)> {
@@ -193,39 +1098,9 @@ pub fn setup_texture_stage(device: &Device, textures: &[&Texture], label: Option
     let mut bind_group_entries: Vec<wgpu::BindGroupEntry> = Vec::new();
     let mut bind_group_layout_entries: Vec<wgpu::BindGroupLayoutEntry> = Vec::new();
     for i in 0..textures.len() {
-
-       let layout_entry_1 = wgpu::BindGroupLayoutEntry {
-        binding: i as u32,
-           visibility: wgpu::ShaderStages::FRAGMENT,
-           ty: wgpu::BindingType::Texture {
-               multisampled: false,
-               view_dimension: wgpu::TextureViewDimension::D2,
-               sample_type: wgpu::TextureSampleType::Float {
-                   filterable: true
-               },
-           },
-           count: None,
-       };
-
-        let layout_entry_2 = wgpu::BindGroupLayoutEntry {
-            binding: (i+1) as u32,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-            count: None,
-        };
-
-        let entry_1  = wgpu::BindGroupEntry {
-            binding: i as u32,
-            resource: wgpu::BindingResource::TextureView(&textures[i].view),
-        };
-        let entry_2  = wgpu::BindGroupEntry {
-            binding: (i+1) as u32,
-            resource: wgpu::BindingResource::Sampler(&textures[i].sampler),
-        };
-        bind_group_entries.push(entry_1);
-        bind_group_entries.push(entry_2);
-        bind_group_layout_entries.push(layout_entry_1);
-        bind_group_layout_entries.push(layout_entry_2);
+        let binding = (i*2) as u32;
+        bind_group_layout_entries.extend(texture_layout_entries(device,textures[i],binding,wgpu::ShaderStages::FRAGMENT));
+        bind_group_entries.extend(texture_bind_group_entries(textures[i],binding));
     }
 
 
@@ -245,5 +1120,3 @@ pub fn setup_texture_stage(device: &Device, textures: &[&Texture], label: Option
     Ok((bind_group_layout,bind_group))
 
 }
-
-
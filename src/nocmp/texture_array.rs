@@ -0,0 +1,207 @@
+/*
+Flipbooks as a single binding.
+TextureArray packs same sized frames into the layers of a D2Array texture,
+and binds as texture at 0, sampler at 1 and a uniform with the frame index at 2,
+so a whole sequence is one bind group and the shader picks the frame, see fs_quad_array.wgsl.
+
+The frame index lives in a uniform buffer, and writes to it land before the command buffer is submitted,
+so only one frame per array can be shown per submit.
+
+Only same sized frames are supported, all the flipbooks are exported at screen size.
+Packing arbitrary sized sprites into an atlas with a rect table was left out, nothing in the demo needs it.
+
+Frames are artwork, so they are stored as srgb and sample as linear color for the hdr scene.
+ */
+use anyhow::*;
+use image::GenericImageView;
+use wgpu::util::DeviceExt;
+use crate::nocmp::texture;
+use crate::nocmp::texture::{SamplerConfig, Texture};
+
+#[repr(C)]
+#[derive(Debug,Copy,Clone,bytemuck::Pod,bytemuck::Zeroable)]
+struct FrameUniforms {
+    frame: u32,
+    frame_count: u32,
+    pad: [u32;2],
+}
+
+fn frame_layout_entries() -> [wgpu::BindGroupLayoutEntry;3] {
+    [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ]
+}
+
+fn create_frame_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, texture: &Texture, buffer: &wgpu::Buffer, label: Option<&str>) -> wgpu::BindGroup {
+    let [view_entry, sampler_entry] = texture::texture_bind_group_entries(texture,0);
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            view_entry,
+            sampler_entry,
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buffer.as_entire_binding(),
+            },
+        ],
+        label,
+    })
+}
+
+fn load_frame(path: &str) -> Result<image::DynamicImage> {
+    println!("Opening Texture : {}",path);
+    image::open(path).map_err(|e| anyhow!("could not load frame {} : {}",path,e))
+}
+
+//The texture is only reached through bind_group, which keeps it alive
+pub struct TextureArray {
+    pub frame_count: u32,
+    uniforms: FrameUniforms,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl TextureArray {
+    //One layout for every texture array, so a single pipeline can draw them all.
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &frame_layout_entries(),
+            label: Some("texture array bind group layout"),
+        })
+    }
+
+    pub fn from_paths(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        paths: &[String],
+        label: &str,
+    ) -> Result<Self> {
+        let mut frames = Vec::new();
+        for path in paths {
+            frames.push(load_frame(path)?);
+        }
+        Self::from_images(device, queue, layout, &frames, label)
+    }
+
+    //Every frame ends up the size of the first one, frames that differ are resized (and we say so).
+    pub fn from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        frames: &[image::DynamicImage],
+        label: &str,
+    ) -> Result<Self> {
+        if frames.is_empty() {
+            return Err(anyhow!("texture array {} needs at least one frame",label));
+        }
+        let frame_count = frames.len() as u32;
+        if frame_count > device.limits().max_texture_array_layers {
+            return Err(anyhow!("texture array {} has {} frames, the device allows {}",label,frame_count,device.limits().max_texture_array_layers));
+        }
+        let (width, height) = frames[0].dimensions();
+        let layer_size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: frame_count,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            }
+        );
+
+        for (layer, frame) in frames.iter().enumerate() {
+            let rgba = if frame.dimensions() != (width, height) {
+                println!("{} : frame {} is {:?}, resizing to {}x{}",label,layer,frame.dimensions(),width,height);
+                frame.resize_exact(width, height, image::imageops::FilterType::Triangle).to_rgba8()
+            } else {
+                frame.to_rgba8()
+            };
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &rgba,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * width),
+                    rows_per_image: Some(height),
+                },
+                layer_size,
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = SamplerConfig::default().create_sampler(device, Some(label));
+        let texture = Texture { texture, view, sampler, view_dimension: wgpu::TextureViewDimension::D2Array, sampler_binding_type: wgpu::SamplerBindingType::Filtering };
+
+        let uniforms = FrameUniforms { frame: 0, frame_count, pad: [0;2] };
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("texture array frame uniform"),
+                contents: bytemuck::cast_slice(&[uniforms]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let bind_group = create_frame_bind_group(device, layout, &texture, &uniform_buffer, Some(label));
+
+        Ok(Self { frame_count, uniforms, uniform_buffer, bind_group })
+    }
+
+    //Frames past the end are clamped to the last one
+    pub fn set_frame(self: &mut Self, queue: &wgpu::Queue, frame: u32) {
+        let frame = frame.min(self.frame_count - 1);
+        if frame != self.uniforms.frame {
+            self.uniforms.frame = frame;
+            queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniforms]));
+        }
+    }
+
+    pub fn bind_group(self: &Self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
@@ -0,0 +1,451 @@
+/*
+Tonemapping and exposure, the last pass of the post chain.
+The hdr result of the post effects is scaled by the exposure and mapped to 0..1 with one of the operators,
+the output stays linear, color grading does the srgb transfer after it.
+
+The exposure is in stops. With auto exposure on (the default), two compute passes build a histogram of log2 luminance
+of the hdr image and ease an adapted luminance towards its average, the image is then scaled so that average lands on key.
+Auto exposure needs compute shaders, where they are missing (webgl) the settings are ignored and only the manual exposure is used.
+ */
+use anyhow::*;
+use wgpu::util::DeviceExt;
+use crate::nocmp::texture::Texture;
+
+const HISTOGRAM_BINS: u64 = 256;
+//has to match the workgroup size of cs_histogram in auto_exposure.wgsl
+const HISTOGRAM_TILE: u32 = 16;
+
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum TonemapOperator {
+    //just clamps to 0..1
+    Clamp,
+    Reinhard,
+    Aces,
+    AgX,
+    //Hable's Uncharted 2 curve
+    Filmic,
+}
+
+impl TonemapOperator {
+    //has to match the switch in tonemapping.wgsl
+    fn index(self: &Self) -> u32 {
+        match self {
+            TonemapOperator::Clamp => 0,
+            TonemapOperator::Reinhard => 1,
+            TonemapOperator::Aces => 2,
+            TonemapOperator::AgX => 3,
+            TonemapOperator::Filmic => 4,
+        }
+    }
+
+    //the operator after this one, wrapping around, to step through them at runtime
+    pub fn next(self: &Self) -> TonemapOperator {
+        match self {
+            TonemapOperator::Clamp => TonemapOperator::Reinhard,
+            TonemapOperator::Reinhard => TonemapOperator::Aces,
+            TonemapOperator::Aces => TonemapOperator::AgX,
+            TonemapOperator::AgX => TonemapOperator::Filmic,
+            TonemapOperator::Filmic => TonemapOperator::Clamp,
+        }
+    }
+}
+
+#[derive(Copy,Clone,Debug)]
+pub struct AutoExposureSettings {
+    //log2 luminance range of the histogram, anything outside is put in the first or last bin
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    //how fast the exposure follows the scene, per second, towards brighter and towards darker
+    pub speed_up: f32,
+    pub speed_down: f32,
+    //the average luminance is mapped to key, 0.18 is middle grey
+    pub key: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        AutoExposureSettings {
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+            speed_up: 3.0,
+            speed_down: 1.0,
+            key: 0.18,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug,Copy,Clone,bytemuck::Pod,bytemuck::Zeroable)]
+struct TonemapUniforms {
+    exposure_scale: f32,
+    operator_index: u32,
+    auto_exposure: u32,
+    key: f32,
+}
+
+#[repr(C)]
+#[derive(Debug,Copy,Clone,bytemuck::Pod,bytemuck::Zeroable)]
+struct AutoExposureUniforms {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    speed_up: f32,
+    speed_down: f32,
+    time: f32,
+    pad: [f32;3],
+}
+
+//The compute side of auto exposure, only created when the device has compute shaders
+struct AutoExposurePasses {
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    source_bind_group_layout: wgpu::BindGroupLayout,
+    //one per post chain source (scene, ping 0, ping 1), set with set_sources
+    source_bind_groups: Vec<wgpu::BindGroup>,
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+}
+
+pub struct ToneMapper {
+    pub operator: TonemapOperator,
+    //in stops, added on top of auto exposure when that is on
+    pub exposure: f32,
+    pub auto_exposure: Option<AutoExposureSettings>,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    auto_exposure_passes: Option<AutoExposurePasses>,
+}
+
+impl ToneMapper {
+    //source_layout is the layout of the post chain's source bind groups, source at binding 0 and 1
+    pub fn new(device: &wgpu::Device, source_layout: &wgpu::BindGroupLayout, output_format: wgpu::TextureFormat) -> Result<Self> {
+        //storage usage is invalid without storage textures (webgl2), so it is only asked for with the compute passes
+        let has_compute = device.limits().max_compute_invocations_per_workgroup >= HISTOGRAM_BINS as u32
+            && device.limits().max_storage_buffers_per_shader_stage >= 2
+            && device.limits().max_storage_textures_per_shader_stage >= 1;
+        //the adapted luminance, written by cs_average and read by fs_tonemap
+        let exposure_usage = if has_compute {
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING
+        } else {
+            wgpu::TextureUsages::TEXTURE_BINDING
+        };
+        let exposure_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("exposure texture"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: exposure_usage,
+            view_formats: &[],
+        });
+        let exposure_view = exposure_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("tonemap uniforms"),
+                contents: bytemuck::cast_slice(&[TonemapUniforms { exposure_scale: 1.0, operator_index: 0, auto_exposure: 0, key: 0.18 }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("tonemap uniform bind group layout"),
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&exposure_view),
+                },
+            ],
+            label: Some("tonemap uniform bind group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("tonemap pipeline layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, source_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shadertoys/tonemapping.wgsl"));
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("tonemap pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_tonemap",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let auto_exposure_passes = if has_compute {
+            Some(Self::create_auto_exposure_passes(device, &exposure_view))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            operator: TonemapOperator::Aces,
+            exposure: 0.0,
+            auto_exposure: Some(AutoExposureSettings::default()),
+            uniform_buffer,
+            uniform_bind_group,
+            pipeline,
+            auto_exposure_passes,
+        })
+    }
+
+    fn create_auto_exposure_passes(device: &wgpu::Device, exposure_view: &wgpu::TextureView) -> AutoExposurePasses {
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("auto exposure uniforms"),
+                contents: bytemuck::cast_slice(&[AutoExposureUniforms { min_log_luminance: 0.0, log_luminance_range: 1.0, speed_up: 0.0, speed_down: 0.0, time: 0.0, pad: [0.0;3] }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let histogram_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("luminance histogram"),
+            size: HISTOGRAM_BINS * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        //adapted luminance and the time it was last updated, a negative time makes the first frame start adapted
+        let state_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("exposure state"),
+                contents: bytemuck::cast_slice(&[1.0f32, -1.0f32]),
+                usage: wgpu::BufferUsages::STORAGE,
+            }
+        );
+
+        let storage_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+                storage_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::R32Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("auto exposure bind group layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: histogram_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: state_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(exposure_view),
+                },
+            ],
+            label: Some("auto exposure bind group"),
+        });
+        let source_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("auto exposure source bind group layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("auto exposure pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout, &source_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shadertoys/auto_exposure.wgsl"));
+        let compute_pipeline = |entry_point: &str| device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point,
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        AutoExposurePasses {
+            uniform_buffer,
+            bind_group,
+            source_bind_group_layout,
+            source_bind_groups: Vec::new(),
+            histogram_pipeline: compute_pipeline("cs_histogram"),
+            average_pipeline: compute_pipeline("cs_average"),
+        }
+    }
+
+    //The textures render() can be asked to tonemap, has to be called again when they are recreated
+    pub fn set_sources(self: &mut Self, device: &wgpu::Device, sources: &[&Texture]) {
+        if let Some(passes) = &mut self.auto_exposure_passes {
+            passes.source_bind_groups = sources.iter().map(|source| device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &passes.source_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source.view),
+                    }
+                ],
+                label: Some("auto exposure source bind group"),
+            })).collect();
+        }
+    }
+
+    pub fn has_auto_exposure(self: &Self) -> bool {
+        self.auto_exposure_passes.is_some()
+    }
+
+    //source_index picks the texture given to set_sources for the histogram,
+    //source_bind_group is the same texture bound with the post chain's source layout
+    pub fn render(
+        self: &Self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        source_index: usize,
+        source_bind_group: &wgpu::BindGroup,
+        source_size: (u32, u32),
+        output_view: &wgpu::TextureView,
+        time: f32,
+    ) {
+        let mut auto_exposure = false;
+        if let (Some(settings), Some(passes)) = (&self.auto_exposure, &self.auto_exposure_passes) {
+            queue.write_buffer(&passes.uniform_buffer, 0, bytemuck::cast_slice(&[AutoExposureUniforms {
+                min_log_luminance: settings.min_log_luminance,
+                log_luminance_range: (settings.max_log_luminance - settings.min_log_luminance).max(0.001),
+                speed_up: settings.speed_up,
+                speed_down: settings.speed_down,
+                time,
+                pad: [0.0;3],
+            }]));
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("auto exposure pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_bind_group(0, &passes.bind_group, &[]);
+            compute_pass.set_bind_group(1, &passes.source_bind_groups[source_index], &[]);
+            compute_pass.set_pipeline(&passes.histogram_pipeline);
+            compute_pass.dispatch_workgroups(source_size.0.div_ceil(HISTOGRAM_TILE), source_size.1.div_ceil(HISTOGRAM_TILE), 1);
+            compute_pass.set_pipeline(&passes.average_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+            auto_exposure = true;
+        }
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[TonemapUniforms {
+            exposure_scale: self.exposure.exp2(),
+            operator_index: self.operator.index(),
+            auto_exposure: auto_exposure as u32,
+            key: self.auto_exposure.map(|settings| settings.key).unwrap_or(0.18),
+        }]));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("tonemap pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, source_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
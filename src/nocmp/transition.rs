@@ -0,0 +1,284 @@
+/*
+Scene transitions.
+The outgoing scene renders into from_view, the incoming one into to_view, and render() blends them
+into the output with one of the built in transitions or a custom wgsl function.
+Custom transitions define
+  fn transition(uv: vec2<f32>, progress: f32) -> vec4<f32>
+and can use from_color(uv), to_color(uv), mask_value(uv) and transition_uniforms from transition.wgsl.
+
+Transitions are placed in beats, use a BeatClock to get the current beat.
+ */
+use anyhow::*;
+use wgpu::util::DeviceExt;
+use crate::nocmp::texture;
+use crate::nocmp::texture::Texture;
+
+const TRANSITION_COMMON: &str = include_str!("../shadertoys/transition.wgsl");
+
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum TransitionKind {
+    Crossfade,
+    //softness and angle are taken from the Transition
+    Wipe,
+    //uses the mask texture given to the renderer, softness from the Transition
+    MaskDissolve,
+    //index returned by TransitionRenderer::add_custom
+    Custom(usize),
+}
+
+#[derive(Copy,Clone,Debug)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub start_beat: f32,
+    pub duration_beats: f32,
+    pub softness: f32,
+    //direction of a wipe in radians, 0 is left to right
+    pub angle: f32,
+}
+
+impl Transition {
+    pub fn new(kind: TransitionKind, start_beat: f32, duration_beats: f32) -> Self {
+        Transition {
+            kind,
+            start_beat,
+            duration_beats,
+            softness: 0.1,
+            angle: 0.0,
+        }
+    }
+
+    //0..1 while the transition runs, None before and after
+    pub fn progress(self: &Self, beat: f32) -> Option<f32> {
+        if beat < self.start_beat || beat >= self.start_beat + self.duration_beats {
+            return None;
+        }
+        Some((beat - self.start_beat) / self.duration_beats.max(f32::EPSILON))
+    }
+}
+
+#[repr(C)]
+#[derive(Debug,Copy,Clone,bytemuck::Pod,bytemuck::Zeroable)]
+struct TransitionUniforms {
+    progress: f32,
+    softness: f32,
+    angle: f32,
+    time: f32,
+}
+
+pub struct TransitionRenderer {
+    from: Texture,
+    to: Texture,
+    format: wgpu::TextureFormat,
+    pipeline_layout: wgpu::PipelineLayout,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    texture_bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    crossfade_pipeline: wgpu::RenderPipeline,
+    wipe_pipeline: wgpu::RenderPipeline,
+    mask_dissolve_pipeline: wgpu::RenderPipeline,
+    custom_pipelines: Vec<wgpu::RenderPipeline>,
+}
+
+impl TransitionRenderer {
+    //format is used both for the from/to targets and the output
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, mask: &Texture) -> Result<Self> {
+        let from = Texture::create_rtt_texture_with_sampler(width, height, device, format,
+            wgpu::FilterMode::Linear, wgpu::FilterMode::Linear, wgpu::AddressMode::ClampToEdge, Some("transition from target"))?;
+        let to = Texture::create_rtt_texture_with_sampler(width, height, device, format,
+            wgpu::FilterMode::Linear, wgpu::FilterMode::Linear, wgpu::AddressMode::ClampToEdge, Some("transition to target"))?;
+
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("transition uniforms"),
+                contents: bytemuck::cast_slice(&[TransitionUniforms { progress: 0.0, softness: 0.1, angle: 0.0, time: 0.0 }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("transition uniform bind group layout"),
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("transition uniform bind group"),
+        });
+
+        let mut layout_entries = Vec::new();
+        layout_entries.extend(texture::texture_layout_entries(device, &from, 0, wgpu::ShaderStages::FRAGMENT));
+        layout_entries.extend(texture::texture_layout_entries(device, &to, 2, wgpu::ShaderStages::FRAGMENT));
+        layout_entries.extend(texture::texture_layout_entries(device, mask, 4, wgpu::ShaderStages::FRAGMENT));
+        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: layout_entries.as_slice(),
+            label: Some("transition texture bind group layout"),
+        });
+        let texture_bind_group = Self::create_texture_bind_group(device, &texture_bind_group_layout, &from, &to, mask);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("transition pipeline layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let builtin = |name: &str| format!("fn transition(uv: vec2<f32>, progress: f32) -> vec4<f32> {{ return {}(uv, progress); }}", name);
+        let crossfade_pipeline = Self::create_pipeline(device, &pipeline_layout, format, &builtin("crossfade"), "crossfade transition");
+        let wipe_pipeline = Self::create_pipeline(device, &pipeline_layout, format, &builtin("wipe"), "wipe transition");
+        let mask_dissolve_pipeline = Self::create_pipeline(device, &pipeline_layout, format, &builtin("mask_dissolve"), "mask dissolve transition");
+
+        Ok(Self {
+            from,
+            to,
+            format,
+            pipeline_layout,
+            texture_bind_group_layout,
+            texture_bind_group,
+            uniform_buffer,
+            uniform_bind_group,
+            crossfade_pipeline,
+            wipe_pipeline,
+            mask_dissolve_pipeline,
+            custom_pipelines: Vec::new(),
+        })
+    }
+
+    fn create_texture_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, from: &Texture, to: &Texture, mask: &Texture) -> wgpu::BindGroup {
+        let mut entries = Vec::new();
+        entries.extend(texture::texture_bind_group_entries(from, 0));
+        entries.extend(texture::texture_bind_group_entries(to, 2));
+        entries.extend(texture::texture_bind_group_entries(mask, 4));
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: entries.as_slice(),
+            label: Some("transition texture bind group"),
+        })
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        transition_source: &str,
+        label: &str,
+    ) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(format!("{}\n{}\n", TRANSITION_COMMON, transition_source).into()),
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    //Compiles a custom transition, the source has to define fn transition(uv, progress) -> vec4<f32>
+    pub fn add_custom(self: &mut Self, device: &wgpu::Device, transition_source: &str, label: &str) -> TransitionKind {
+        let pipeline = Self::create_pipeline(device, &self.pipeline_layout, self.format, transition_source, label);
+        self.custom_pipelines.push(pipeline);
+        TransitionKind::Custom(self.custom_pipelines.len() - 1)
+    }
+
+    pub fn set_mask(self: &mut Self, device: &wgpu::Device, mask: &Texture) {
+        self.texture_bind_group = Self::create_texture_bind_group(device, &self.texture_bind_group_layout, &self.from, &self.to, mask);
+    }
+
+    //The mask is not owned by the renderer, so it has to be passed again when the targets are recreated
+    pub fn resize(self: &mut Self, device: &wgpu::Device, width: u32, height: u32, mask: &Texture) -> Result<()> {
+        self.from = Texture::create_rtt_texture_with_sampler(width, height, device, self.format,
+            wgpu::FilterMode::Linear, wgpu::FilterMode::Linear, wgpu::AddressMode::ClampToEdge, Some("transition from target"))?;
+        self.to = Texture::create_rtt_texture_with_sampler(width, height, device, self.format,
+            wgpu::FilterMode::Linear, wgpu::FilterMode::Linear, wgpu::AddressMode::ClampToEdge, Some("transition to target"))?;
+        self.set_mask(device, mask);
+        Ok(())
+    }
+
+    pub fn from_view(self: &Self) -> &wgpu::TextureView {
+        &self.from.view
+    }
+
+    pub fn to_view(self: &Self) -> &wgpu::TextureView {
+        &self.to.view
+    }
+
+    pub fn render(
+        self: &Self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        transition: &Transition,
+        progress: f32,
+        time: f32,
+        output_view: &wgpu::TextureView,
+    ) {
+        let pipeline = match transition.kind {
+            TransitionKind::Crossfade => &self.crossfade_pipeline,
+            TransitionKind::Wipe => &self.wipe_pipeline,
+            TransitionKind::MaskDissolve => &self.mask_dissolve_pipeline,
+            TransitionKind::Custom(index) => &self.custom_pipelines[index],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[TransitionUniforms {
+            progress,
+            softness: transition.softness,
+            angle: transition.angle,
+            time,
+        }]));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("transition pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.texture_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,100 @@
+//Auto exposure from a luminance histogram (see nocmp::tonemapping).
+//cs_histogram sorts every pixel of the hdr image into 256 bins of log2 luminance,
+//cs_average turns the histogram into an average luminance, eases the adapted luminance towards it
+//and writes it to the 1x1 exposure texture read by the tonemapping pass. It also clears the histogram for the next frame.
+
+const HISTOGRAM_BINS: u32 = 256u;
+
+struct AutoExposureUniforms {
+	min_log_luminance: f32,
+	log_luminance_range: f32,
+	//adaptation rates per second, towards brighter and towards darker
+	speed_up: f32,
+	speed_down: f32,
+	time: f32,
+	pad0: f32,
+	pad1: f32,
+	pad2: f32,
+};
+
+struct ExposureState {
+	adapted_luminance: f32,
+	//negative before the first frame, so the exposure starts adapted
+	last_time: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> settings: AutoExposureUniforms;
+@group(0) @binding(1)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+@group(0) @binding(2)
+var<storage, read_write> state: ExposureState;
+@group(0) @binding(3)
+var exposure_out: texture_storage_2d<r32float, write>;
+
+@group(1) @binding(0)
+var t_source: texture_2d<f32>;
+
+var<workgroup> local_histogram: array<atomic<u32>, 256>;
+var<workgroup> weighted_bins: array<f32, 256>;
+
+//bin 0 is for (nearly) black pixels, they are left out of the average
+fn luminance_to_bin(luminance: f32) -> u32 {
+	if (luminance < 0.0001) {
+		return 0u;
+	}
+	let t = clamp((log2(luminance) - settings.min_log_luminance) / settings.log_luminance_range, 0.0, 1.0);
+	return u32(t * 254.0 + 1.0);
+}
+
+@compute @workgroup_size(16, 16)
+fn cs_histogram(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_index) local_index: u32) {
+	atomicStore(&local_histogram[local_index], 0u);
+	workgroupBarrier();
+
+	let dimensions = textureDimensions(t_source);
+	if (global_id.x < dimensions.x && global_id.y < dimensions.y) {
+		let color = textureLoad(t_source, vec2<i32>(global_id.xy), 0).rgb;
+		let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+		atomicAdd(&local_histogram[luminance_to_bin(luminance)], 1u);
+	}
+	workgroupBarrier();
+
+	atomicAdd(&histogram[local_index], atomicLoad(&local_histogram[local_index]));
+}
+
+@compute @workgroup_size(256)
+fn cs_average(@builtin(local_invocation_index) local_index: u32) {
+	let count = atomicLoad(&histogram[local_index]);
+	weighted_bins[local_index] = f32(count) * f32(local_index);
+	atomicStore(&histogram[local_index], 0u);
+	workgroupBarrier();
+
+	for (var stride = HISTOGRAM_BINS / 2u; stride > 0u; stride = stride / 2u) {
+		if (local_index < stride) {
+			weighted_bins[local_index] += weighted_bins[local_index + stride];
+		}
+		workgroupBarrier();
+	}
+
+	if (local_index == 0u) {
+		let dimensions = textureDimensions(t_source);
+		//count is the black bin here
+		let lit_pixels = max(f32(dimensions.x * dimensions.y) - f32(count), 1.0);
+		let average_bin = max(weighted_bins[0] / lit_pixels - 1.0, 0.0);
+		let average_luminance = exp2(average_bin / 254.0 * settings.log_luminance_range + settings.min_log_luminance);
+
+		var adapted = state.adapted_luminance;
+		let delta_time = settings.time - state.last_time;
+		if (state.last_time < 0.0 || delta_time < 0.0) {
+			//first frame or the timeline jumped back, no easing
+			adapted = average_luminance;
+		} else {
+			let speed = select(settings.speed_down, settings.speed_up, average_luminance > adapted);
+			adapted = adapted + (average_luminance - adapted) * (1.0 - exp(-delta_time * speed));
+		}
+		state.adapted_luminance = adapted;
+		state.last_time = settings.time;
+		textureStore(exposure_out, vec2<i32>(0, 0), vec4<f32>(adapted, 0.0, 0.0, 1.0));
+	}
+}
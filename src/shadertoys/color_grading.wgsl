@@ -0,0 +1,85 @@
+//Color grading with up to four blended 3D luts (see nocmp::color_grading).
+//Empty lut slots are bound to an identity lut with weight 0.
+//The input is linear, the luts are applied to srgb encoded color.
+
+struct GradingUniforms {
+	weights: vec4<f32>,
+	domain_min: array<vec4<f32>,4>,
+	domain_max: array<vec4<f32>,4>,
+	//x is 1.0 when the output format is srgb, and the hardware encodes
+	output_srgb: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> grading: GradingUniforms;
+
+@group(1) @binding(0)
+var t_input: texture_2d<f32>;
+@group(1) @binding(1)
+var s_input: sampler;
+@group(1) @binding(2)
+var t_lut0: texture_3d<f32>;
+@group(1) @binding(3)
+var s_lut0: sampler;
+@group(1) @binding(4)
+var t_lut1: texture_3d<f32>;
+@group(1) @binding(5)
+var s_lut1: sampler;
+@group(1) @binding(6)
+var t_lut2: texture_3d<f32>;
+@group(1) @binding(7)
+var s_lut2: sampler;
+@group(1) @binding(8)
+var t_lut3: texture_3d<f32>;
+@group(1) @binding(9)
+var s_lut3: sampler;
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+	var out: VertexOutput;
+	//one big triangle covering the screen, (0,0) (2,0) (0,2) in uv space
+	let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+	out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	out.uv = uv;
+	return out;
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+	let low = color * 12.92;
+	let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+	return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+	let low = color / 12.92;
+	let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+	return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+fn apply_lut(t_lut: texture_3d<f32>, s_lut: sampler, color: vec3<f32>, domain_min: vec3<f32>, domain_max: vec3<f32>) -> vec3<f32> {
+	let size = f32(textureDimensions(t_lut).x);
+	let normalized = clamp((color - domain_min) / (domain_max - domain_min), vec3<f32>(0.0), vec3<f32>(1.0));
+	//the lut entries sit at the texel centers, so 0 and 1 map to the first and last texel, not the texture edges
+	let coords = normalized * ((size - 1.0) / size) + 0.5 / size;
+	return textureSampleLevel(t_lut, s_lut, coords, 0.0).rgb;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = linear_to_srgb(clamp(textureSample(t_input, s_input, in.uv).rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+	let w = grading.weights;
+	var graded = color * max(1.0 - (w.x + w.y + w.z + w.w), 0.0);
+	graded += apply_lut(t_lut0, s_lut0, color, grading.domain_min[0].xyz, grading.domain_max[0].xyz) * w.x;
+	graded += apply_lut(t_lut1, s_lut1, color, grading.domain_min[1].xyz, grading.domain_max[1].xyz) * w.y;
+	graded += apply_lut(t_lut2, s_lut2, color, grading.domain_min[2].xyz, grading.domain_max[2].xyz) * w.z;
+	graded += apply_lut(t_lut3, s_lut3, color, grading.domain_min[3].xyz, grading.domain_max[3].xyz) * w.w;
+	if (grading.output_srgb.x > 0.5) {
+		graded = srgb_to_linear(graded);
+	}
+	return vec4<f32>(graded, 1.0);
+}
@@ -0,0 +1,77 @@
+//Fullscreen quad that shows one frame of a texture array (see nocmp::texture_array)
+//Vertex Shader
+
+
+struct Uniforms {
+iMouse: vec4<f32>,
+iResolution: vec2<f32>,
+iTime:f32,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+struct FrameUniforms {
+frame: u32,
+frame_count: u32,
+};
+
+@group(1) @binding(0)
+var t_frames: texture_2d_array<f32>;
+@group(1) @binding(1)
+var s_frames: sampler;
+@group(1) @binding(2)
+var<uniform> frame_uniforms: FrameUniforms;
+
+struct VertexInput {
+	@location(0) position: vec3<f32>,
+	@location(1) color: vec3<f32>,
+};
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) color: vec3<f32>,
+	@location(1) uv: vec2<f32>,
+};
+
+
+@vertex
+fn vs_main(
+	model: VertexInput,
+)-> VertexOutput {
+	var out: VertexOutput;
+	out.color = model.color;
+	out.clip_position = vec4<f32>(model.position, 1.0);
+	out.uv = vec2<f32>(model.position.x*0.5, model.position.y*0.5*-1.0)+ vec2<f32>(0.5,0.5);
+
+	return out;
+}
+
+fn smoothedCircle(pos : vec2<f32> ,size:f32,smoothFactor:f32,uv:vec2<f32>) -> f32{
+
+    let distanceToCircleOrigo:f32 = distance(pos,uv);
+    
+    //why do we need 1.0
+    //because smoothStep returns if 1 if x is bigger than a and b in a,b,x
+    //when we define a circle , distances smaller than size should be 1, 
+    //so we need to invert it.
+    
+    //rule to remember , smoothstep a b x , x larger than ab then 1..
+    return (1.0-smoothstep(size,size+smoothFactor,distanceToCircleOrigo));
+
+}
+
+//Fragment Shader
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+
+     var uv:vec2f = in.uv;
+
+    //one layer per flipbook frame, the frame index comes from the uniform
+    var texCol :vec4f = textureSample(t_frames,s_frames,uv,frame_uniforms.frame).rgba;
+    return vec4(texCol);
+
+}
+
+
@@ -7,11 +7,33 @@ iTime:f32,
 
 struct VertUniforms{
 view_proj: mat4x4<f32>,
+view_position: vec4<f32>, //the eye
 };
 
+//has to match MAX_LIGHTS in nocmp::lights
+const MAX_LIGHTS: u32 = 16u;
 
+//see nocmp::lights::LightUniform
+struct Light {
+position: vec4<f32>, //xyz, kind in w (0 directional, 1 point, 2 spot)
+direction: vec4<f32>, //xyz, range in w
+color: vec4<f32>, //rgb, intensity in w
+cone: vec4<f32>, //cos of the inner and outer spot angle
+};
+
+struct Lights {
+lights: array<Light, MAX_LIGHTS>,
+ambient: vec4<f32>,
+count: vec4<u32>, //x is the number of lights
+};
+
+
+//from the mtl file, see nocmp::material
 struct MaterialUniforms{
-color : vec4<f32>
+color : vec4<f32>, //Kd and d
+specular : vec4<f32>, //Ks and Ns
+ambient : vec4<f32>, //Ka and Ni
+emissive : vec4<f32>, //Ke and illum
 };
 
 //modelUniforms typically change per draw-call
@@ -31,6 +53,9 @@ var<uniform> uniforms: Uniforms;
 @group(0) @binding(1)
 var<uniform> vert_uniforms: VertUniforms;
 
+@group(0) @binding(2)
+var<uniform> lights: Lights;
+
 
 @group(1) @binding(0)
 var<uniform> material_uniforms: MaterialUniforms;
@@ -38,67 +63,242 @@ var<uniform> material_uniforms: MaterialUniforms;
 var t_diffuse: texture_2d<f32>;
 @group(1) @binding(2)
 var s_diffuse: sampler;
+@group(1) @binding(3)
+var t_normal: texture_2d<f32>;
+@group(1) @binding(4)
+var s_normal: sampler;
+@group(1) @binding(5)
+var t_specular: texture_2d<f32>;
+@group(1) @binding(6)
+var s_specular: sampler;
+@group(1) @binding(7)
+var t_alpha: texture_2d<f32>;
+@group(1) @binding(8)
+var s_alpha: sampler;
 
 @group(2) @binding(0)
 var<uniform> model_uniforms: ModelUniforms;
 
+//only used by vs_animated, see nocmp::animated_mesh
+struct AnimationUniforms {
+frame_a: u32, //first vertex of the frames in vertex_animation
+frame_b: u32,
+blend: f32,
+texture_width: u32,
+frame_c: u32, //the clip being crossfaded away from
+frame_d: u32,
+blend_cd: f32,
+mix_cd: f32,
+};
+
+@group(2) @binding(1)
+var<uniform> animation_uniforms: AnimationUniforms;
+//three texels per vertex, position + uv.x, normal + uv.y, tangent
+@group(2) @binding(2)
+var vertex_animation: texture_2d<f32>;
+
+//only used by vs_skinned, see nocmp::skinned_mesh. Has to be as long as MAX_JOINTS there
+@group(2) @binding(3)
+var<uniform> joint_matrices: array<mat4x4<f32>, 128>;
+
 
 
 struct VertexInput {
 	@location(0) position: vec3<f32>,
 	@location(1) normal: vec3<f32>,
 	@location(2) uv: vec2<f32>,
+	@location(3) tangent: vec4<f32>, //w is the bitangent sign
+};
+
+struct SkinnedVertexInput {
+	@location(0) position: vec3<f32>,
+	@location(1) normal: vec3<f32>,
+	@location(2) uv: vec2<f32>,
+	@location(3) tangent: vec4<f32>,
+	@location(4) joints: vec4<u32>,
+	@location(5) weights: vec4<f32>,
+};
+
+//per instance, only used by vs_instanced, see nocmp::instanced_mesh
+struct InstanceInput {
+	@location(6) model_matrix_0: vec4<f32>,
+	@location(7) model_matrix_1: vec4<f32>,
+	@location(8) model_matrix_2: vec4<f32>,
+	@location(9) model_matrix_3: vec4<f32>,
+	@location(10) color: vec4<f32>,
+	@location(11) params: vec4<f32>,
 };
 
 struct VertexOutput {
 	@builtin(position) clip_position: vec4<f32>,
 	@location(0) normal: vec3<f32>,
 	@location(1) uv: vec2<f32>,
+	@location(2) tangent: vec4<f32>,
+	@location(3) color: vec4<f32>, //white unless instanced
+	@location(4) params: vec4<f32>, //free for the shader, from the instance
+	@location(5) world_position: vec3<f32>,
 };
 
+//tangent space normal from t_normal, a flat map (the default) gives back the vertex normal.
+//Not normalizing the interpolated tangent frame before building the bitangent keeps it matching MikkTSpace bakes
+fn mapped_normal(in: VertexOutput) -> vec3<f32> {
+	let tangent_normal = textureSample(t_normal, s_normal, in.uv).xyz * 2.0 - 1.0;
+	let bitangent = in.tangent.w * cross(in.normal, in.tangent.xyz);
+	return normalize(tangent_normal.x * in.tangent.xyz + tangent_normal.y * bitangent + tangent_normal.z * in.normal);
+}
+
 
 @vertex
 fn vs_main(
 	model: VertexInput,
 )-> VertexOutput {
+	let world_position = model_uniforms.model_matrix * vec4<f32>(model.position, 1.0);
+
 	var out: VertexOutput;
-	out.normal = model.normal;
-	out.clip_position = vert_uniforms.view_proj * model_uniforms.model_matrix * vec4<f32>(model.position, 1.0);
+	out.normal = normalize((model_uniforms.model_matrix * vec4<f32>(model.normal, 0.0)).xyz);
+	out.clip_position = vert_uniforms.view_proj * world_position;
 	out.uv = model.uv;
+	out.tangent = vec4<f32>((model_uniforms.model_matrix * vec4<f32>(model.tangent.xyz, 0.0)).xyz, model.tangent.w);
+	out.color = vec4<f32>(1.0);
+	out.world_position = world_position.xyz;
 
 	return out;
 }
 
-//Fragment Shader
+//vs_main with the model matrix of the instance, placed by the per draw model matrix
+@vertex
+fn vs_instanced(
+	model: VertexInput,
+	instance: InstanceInput,
+)-> VertexOutput {
+	let instance_matrix = mat4x4<f32>(instance.model_matrix_0, instance.model_matrix_1, instance.model_matrix_2, instance.model_matrix_3);
+	let model_matrix = model_uniforms.model_matrix * instance_matrix;
+	let world_position = model_matrix * vec4<f32>(model.position, 1.0);
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	var out: VertexOutput;
+	out.normal = normalize((model_matrix * vec4<f32>(model.normal, 0.0)).xyz);
+	out.clip_position = vert_uniforms.view_proj * world_position;
+	out.world_position = world_position.xyz;
+	out.uv = model.uv;
+	out.tangent = vec4<f32>((model_matrix * vec4<f32>(model.tangent.xyz, 0.0)).xyz, model.tangent.w);
+	out.color = instance.color;
+	out.params = instance.params;
 
-	var texSample : vec4<f32> = textureSample(t_diffuse,s_diffuse,in.uv);
-	let depth :f32 = in.clip_position.z / in.clip_position.w;
+	return out;
+}
 
-    let dutchColors: vec3<f32> = vec3<f32>(0.5) + vec3<f32>(0.5) * cos(uniforms.iTime + vec3<f32>(in.uv.x, in.uv.x, in.uv.x) + vec3<f32>(0.0, 2.0, 4.0));
-	var sun : vec3<f32> = vec3<f32>(-0.57,-0.57,0.57);
+fn animation_texel(vertex: u32, texel: u32) -> vec4<f32> {
+	let index = vertex * 3u + texel;
+	return textureLoad(vertex_animation, vec2<u32>(index % animation_uniforms.texture_width, index / animation_uniforms.texture_width), 0);
+}
 
-	let first_sun : f32 = pow(dot(in.normal,sun),2.0)*1.8;
-	let otherSun : f32 = dot(in.normal,vec3<f32>(-0.57,0.57,0.57))*0.5;
+//the three texels of a vertex blended between two frames
+fn blended_texels(vertex_index: u32, frame_a: u32, frame_b: u32, blend: f32) -> array<vec4<f32>, 3> {
+	var texels: array<vec4<f32>, 3>;
+	for (var i = 0u; i < 3u; i++) {
+		texels[i] = mix(animation_texel(frame_a + vertex_index, i), animation_texel(frame_b + vertex_index, i), blend);
+	}
+	return texels;
+}
 
-	let fog = 1.0 - pow(((depth*0.5 +0.5)) *0.1,2.1342);
+//vs_main with the vertex blended between two frames of the animation texture, and crossfaded with the clip fading out
+@vertex
+fn vs_animated(
+	@builtin(vertex_index) vertex_index: u32,
+)-> VertexOutput {
+	let ab = blended_texels(vertex_index, animation_uniforms.frame_a, animation_uniforms.frame_b, animation_uniforms.blend);
+	let cd = blended_texels(vertex_index, animation_uniforms.frame_c, animation_uniforms.frame_d, animation_uniforms.blend_cd);
+	let t0 = mix(ab[0], cd[0], animation_uniforms.mix_cd);
+	let t1 = mix(ab[1], cd[1], animation_uniforms.mix_cd);
+	let t2 = mix(ab[2], cd[2], animation_uniforms.mix_cd);
+
+	let world_position = model_uniforms.model_matrix * vec4<f32>(t0.xyz, 1.0);
+
+	var out: VertexOutput;
+	out.normal = normalize((model_uniforms.model_matrix * vec4<f32>(t1.xyz, 0.0)).xyz);
+	out.clip_position = vert_uniforms.view_proj * world_position;
+	out.uv = vec2<f32>(t0.w, t1.w);
+	//the bitangent sign is not blended
+	out.tangent = vec4<f32>((model_uniforms.model_matrix * vec4<f32>(t2.xyz, 0.0)).xyz, sign(animation_texel(animation_uniforms.frame_a + vertex_index, 2u).w));
+	out.color = vec4<f32>(1.0);
+	out.world_position = world_position.xyz;
+
+	return out;
+}
+
+//vs_main for gltf meshes, skinned by up to four joints
+@vertex
+fn vs_skinned(
+	model: SkinnedVertexInput,
+)-> VertexOutput {
+	let skin = joint_matrices[model.joints.x] * model.weights.x
+		+ joint_matrices[model.joints.y] * model.weights.y
+		+ joint_matrices[model.joints.z] * model.weights.z
+		+ joint_matrices[model.joints.w] * model.weights.w;
+
+	let model_matrix = model_uniforms.model_matrix * skin;
+	let world_position = model_matrix * vec4<f32>(model.position, 1.0);
 
-	texSample.r = first_sun * dutchColors.r + dutchColors.r * otherSun;
-	texSample.g = first_sun * dutchColors.g * uniforms.iMouse.z + dutchColors.g * otherSun;
-	texSample.b = first_sun * dutchColors.b + dutchColors.b * otherSun;
+	var out: VertexOutput;
+	out.normal = normalize((model_matrix * vec4<f32>(model.normal, 0.0)).xyz);
+	out.clip_position = vert_uniforms.view_proj * world_position;
+	out.uv = model.uv;
+	out.tangent = vec4<f32>((model_matrix * vec4<f32>(model.tangent.xyz, 0.0)).xyz, model.tangent.w);
+	out.color = vec4<f32>(1.0);
+	out.world_position = world_position.xyz;
+
+	return out;
+}
 
-	texSample.r *= fog;
-	texSample.g *= fog;
-	texSample.b *= fog;
+//Blinn-Phong for one light, diffuse and specular are the material colors at this fragment
+fn shade_light(light: Light, position: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>, diffuse: vec3<f32>, specular: vec3<f32>, shininess: f32) -> vec3<f32> {
+	let kind = u32(light.position.w);
+	var to_light = -light.direction.xyz;
+	var attenuation = 1.0;
+	if (kind != 0u) {
+		let offset = light.position.xyz - position;
+		let distance = length(offset);
+		to_light = offset / max(distance, 0.0001);
+		//inverse square, windowed so it reaches zero at the range
+		let window = clamp(1.0 - pow(distance / light.direction.w, 4.0), 0.0, 1.0);
+		attenuation = window * window / max(distance * distance, 0.0001);
+	}
+	if (kind == 2u) {
+		attenuation *= smoothstep(light.cone.y, light.cone.x, dot(-to_light, light.direction.xyz));
+	}
 
-    let fog_plus = pow(fog,1.71212)*0.15;
-	texSample.r += fog_plus;
-	texSample.g += fog_plus;
-	texSample.b += fog_plus;
-	texSample.a = 1.0;
-	return texSample;
+	let n_dot_l = dot(normal, to_light);
+	if (n_dot_l <= 0.0) {
+		return vec3<f32>(0.0);
+	}
+	let half_dir = normalize(to_light + view_dir);
+	let highlight = pow(max(dot(normal, half_dir), 0.0), shininess);
+	return light.color.rgb * light.color.w * attenuation * (diffuse * n_dot_l + specular * highlight);
 }
 
+//Fragment Shader
 
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+
+	let depth :f32 = in.clip_position.z / in.clip_position.w;
+	let normal = mapped_normal(in);
+	let view_dir = normalize(vert_uniforms.view_position.xyz - in.world_position);
+
+	//Kd, Ks and Ns from the mtl, scaled by their maps
+	let diffuse = material_uniforms.color.rgb * textureSample(t_diffuse, s_diffuse, in.uv).rgb * in.color.rgb;
+	let specular = material_uniforms.specular.rgb * textureSample(t_specular, s_specular, in.uv).rgb;
+	let shininess = max(material_uniforms.specular.w, 1.0);
+
+	var color = lights.ambient.rgb * material_uniforms.ambient.rgb * diffuse + material_uniforms.emissive.rgb;
+	for (var i = 0u; i < min(lights.count.x, MAX_LIGHTS); i++) {
+		color += shade_light(lights.lights[i], in.world_position, normal, view_dir, diffuse, specular, shininess);
+	}
+
+	let fog = 1.0 - pow(((depth*0.5 +0.5)) *0.1,2.1342);
+	let fog_plus = pow(fog,1.71212)*0.15;
+	color = color * fog + vec3<f32>(fog_plus);
+	//d from the mtl times map_d, only blended when the pipeline is (see MeshRenderer)
+	let alpha = material_uniforms.color.a * textureSample(t_alpha, s_alpha, in.uv).r;
+	return vec4<f32>(color, alpha);
+}
@@ -0,0 +1,172 @@
+//Post processing effects, one fragment entry point per pass (see nocmp::post_process).
+//Every pass reads the previous result from t_source, t_aux is the bloom or feedback history texture
+//for the passes that need a second input.
+
+struct PostUniforms {
+	params0: vec4<f32>,
+	params1: vec4<f32>,
+	resolution: vec2<f32>,
+	time: f32,
+	pad: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> post: PostUniforms;
+
+@group(1) @binding(0)
+var t_source: texture_2d<f32>;
+@group(1) @binding(1)
+var s_source: sampler;
+@group(1) @binding(2)
+var t_aux: texture_2d<f32>;
+@group(1) @binding(3)
+var s_aux: sampler;
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+	var out: VertexOutput;
+	//one big triangle covering the screen, (0,0) (2,0) (0,2) in uv space
+	let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+	out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	out.uv = uv;
+	return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+	return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn hash(p: vec2<f32>) -> f32 {
+	let p3 = fract(vec3<f32>(p.xyx) * 0.1031);
+	let d = p3 + dot(p3, p3.yzx + 33.33);
+	return fract((d.x + d.y) * d.z);
+}
+
+//params0 : x threshold, y intensity, z blur radius in texels
+@fragment
+fn fs_bloom_bright(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = textureSample(t_source, s_source, in.uv).rgb;
+	//soft knee so things don't pop in and out of the bloom
+	let brightness = luminance(color);
+	let knee = post.params0.x * 0.5;
+	let soft = clamp(brightness - post.params0.x + knee, 0.0, 2.0 * knee);
+	let contribution = max(soft * soft / (4.0 * knee + 0.0001), brightness - post.params0.x) / max(brightness, 0.0001);
+	return vec4<f32>(color * max(contribution, 0.0), 1.0);
+}
+
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+	let texel = direction * post.params0.z / vec2<f32>(textureDimensions(t_source));
+	//9 tap gaussian
+	var weights = array<f32,5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+	var color = textureSample(t_source, s_source, uv).rgb * weights[0];
+	for (var i = 1; i < 5; i++) {
+		let offset = texel * f32(i);
+		color += textureSample(t_source, s_source, uv + offset).rgb * weights[i];
+		color += textureSample(t_source, s_source, uv - offset).rgb * weights[i];
+	}
+	return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_blur_h(in: VertexOutput) -> @location(0) vec4<f32> {
+	return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_v(in: VertexOutput) -> @location(0) vec4<f32> {
+	return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+@fragment
+fn fs_bloom_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = textureSample(t_source, s_source, in.uv).rgb;
+	let bloom = textureSample(t_aux, s_aux, in.uv).rgb;
+	return vec4<f32>(color + bloom * post.params0.y, 1.0);
+}
+
+//params0 : x amount, the uv offset at the edge of the screen
+@fragment
+fn fs_chromatic_aberration(in: VertexOutput) -> @location(0) vec4<f32> {
+	let offset = (in.uv - vec2<f32>(0.5)) * post.params0.x;
+	let r = textureSample(t_source, s_source, in.uv + offset).r;
+	let g = textureSample(t_source, s_source, in.uv).g;
+	let b = textureSample(t_source, s_source, in.uv - offset).b;
+	return vec4<f32>(r, g, b, 1.0);
+}
+
+//params0 : x intensity, y radius where the darkening starts, z smoothness
+@fragment
+fn fs_vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = textureSample(t_source, s_source, in.uv).rgb;
+	let aspect = post.resolution.x / max(post.resolution.y, 1.0);
+	let distance_to_center = length((in.uv - vec2<f32>(0.5)) * vec2<f32>(aspect, 1.0));
+	let vignette = smoothstep(post.params0.y, post.params0.y + post.params0.z, distance_to_center);
+	return vec4<f32>(color * (1.0 - vignette * post.params0.x), 1.0);
+}
+
+//params0 : x amount, y grain size in pixels
+@fragment
+fn fs_film_grain(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = textureSample(t_source, s_source, in.uv).rgb;
+	let cell = floor(in.uv * post.resolution / max(post.params0.y, 1.0));
+	//new noise 24 times a second, like film
+	let noise = hash(cell + floor(post.time * 24.0) * 17.0) - 0.5;
+	//grain is most visible in the mid tones
+	let response = 1.0 - abs(luminance(color) * 2.0 - 1.0);
+	return vec4<f32>(color + noise * post.params0.x * max(response, 0.2), 1.0);
+}
+
+//params0 : x curvature, y scanline intensity, z scanline count (0 uses half the screen height), w rgb mask intensity
+@fragment
+fn fs_crt(in: VertexOutput) -> @location(0) vec4<f32> {
+	var uv = in.uv * 2.0 - 1.0;
+	uv = uv * (1.0 + post.params0.x * dot(uv.yx, uv.yx) * 0.25);
+	uv = uv * 0.5 + 0.5;
+	var color = textureSample(t_source, s_source, uv).rgb;
+	if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+		color = vec3<f32>(0.0);
+	}
+	var line_count = post.params0.z;
+	if (line_count <= 0.0) {
+		line_count = post.resolution.y * 0.5;
+	}
+	let scanline = 0.5 + 0.5 * sin(uv.y * line_count * 6.2831853);
+	color *= 1.0 - post.params0.y * (1.0 - scanline);
+	//aperture grille, every third pixel column favours one of r, g or b
+	let column = u32(in.clip_position.x) % 3u;
+	var mask = vec3<f32>(1.0 - post.params0.w);
+	mask[column] = 1.0;
+	return vec4<f32>(color * mask, 1.0);
+}
+
+//params0 : xy center in uv, z strength, w sample count (up to 32)
+@fragment
+fn fs_radial_blur(in: VertexOutput) -> @location(0) vec4<f32> {
+	let samples = clamp(i32(post.params0.w), 1, 32);
+	let direction = in.uv - post.params0.xy;
+	var color = vec3<f32>(0.0);
+	for (var i = 0; i < samples; i++) {
+		let scale = 1.0 - post.params0.z * f32(i) / f32(samples);
+		color += textureSample(t_source, s_source, post.params0.xy + direction * scale).rgb;
+	}
+	return vec4<f32>(color / f32(samples), 1.0);
+}
+
+//params0 : x decay, y zoom, z rotation in radians, w offset in y
+//the history is last frame's output of this pass, so the trails feed themselves
+@fragment
+fn fs_feedback(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = textureSample(t_source, s_source, in.uv).rgb;
+	let centered = in.uv - vec2<f32>(0.5);
+	let c = cos(post.params0.z);
+	let s = sin(post.params0.z);
+	let history_uv = vec2<f32>(c * centered.x - s * centered.y, s * centered.x + c * centered.y) / max(post.params0.y, 0.0001)
+		+ vec2<f32>(0.5, 0.5 + post.params0.w);
+	let history = textureSample(t_aux, s_aux, history_uv).rgb * post.params0.x;
+	return vec4<f32>(max(color, history), 1.0);
+}
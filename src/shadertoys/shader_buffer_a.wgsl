@@ -12,6 +12,11 @@ var<uniform> uniforms: Uniforms;
 var t_diffuse: texture_2d<f32>;
 @group(1) @binding(1)
 var s_diffuse: sampler;
+//the keyboard channel, 256x3 with the javascript keycode on x, see keyboard_texture.rs
+@group(1) @binding(2)
+var t_keyboard: texture_2d<f32>;
+
+const KEY_SPACE: i32 = 32;
 
 struct VertexInput {
 	@location(0) position: vec3<f32>,
@@ -44,6 +49,8 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 
      //reading with distortion
      var dist_factor = textureSample(t_diffuse,s_diffuse,in.uv).a;
+     //holding space shakes the feedback harder
+     dist_factor += textureLoad(t_keyboard, vec2<i32>(KEY_SPACE, 0), 0).r * 0.02;
      var uv:vec2f = in.uv;
      uv.x += sin(in.uv.x*uniforms.iTime*0.003 + in.uv.y*5.3 + uniforms.iTime*5.4)*(0.005 + dist_factor);
      uv.y += sin(in.uv.y*uniforms.iTime*0.003 + in.uv.y*8.3 + uniforms.iTime*6.4)*(0.005 + dist_factor);
@@ -0,0 +1,137 @@
+//Tonemapping, the last pass of the post chain (see nocmp::tonemapping).
+//Takes the hdr result of the post effects, applies exposure and a tonemapping operator,
+//and writes linear color in 0..1, the srgb transfer is done by the color grading pass after this.
+
+struct TonemapUniforms {
+	//exp2 of the exposure in stops
+	exposure_scale: f32,
+	//0 clamp, 1 reinhard, 2 aces, 3 agx, 4 filmic
+	operator_index: u32,
+	//1 when t_exposure holds the adapted luminance from the auto exposure histogram
+	auto_exposure: u32,
+	//the average luminance is mapped to this, 0.18 is middle grey
+	key: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> tonemap: TonemapUniforms;
+@group(0) @binding(1)
+var t_exposure: texture_2d<f32>;
+
+@group(1) @binding(0)
+var t_source: texture_2d<f32>;
+@group(1) @binding(1)
+var s_source: sampler;
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+	var out: VertexOutput;
+	//one big triangle covering the screen, (0,0) (2,0) (0,2) in uv space
+	let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+	out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	out.uv = uv;
+	return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+	return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+//on luminance so saturated colors keep their hue
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+	return color / (1.0 + luminance(color));
+}
+
+//Stephen Hill's fit of the ACES RRT and ODT, linear srgb in and out
+const ACES_INPUT = mat3x3<f32>(
+	vec3<f32>(0.59719, 0.07600, 0.02840),
+	vec3<f32>(0.35458, 0.90834, 0.13383),
+	vec3<f32>(0.04823, 0.01566, 0.83777)
+);
+const ACES_OUTPUT = mat3x3<f32>(
+	vec3<f32>(1.60475, -0.10208, -0.00327),
+	vec3<f32>(-0.53108, 1.10813, -0.07276),
+	vec3<f32>(-0.07367, -0.00605, 1.07602)
+);
+
+fn aces(color: vec3<f32>) -> vec3<f32> {
+	let v = ACES_INPUT * color;
+	let a = v * (v + 0.0245786) - 0.000090537;
+	let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+	return ACES_OUTPUT * (a / b);
+}
+
+//Minimal AgX with the default look, after Benjamin Wrensch's fit of the Blender config
+const AGX_INSET = mat3x3<f32>(
+	vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+	vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+	vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104)
+);
+const AGX_OUTSET = mat3x3<f32>(
+	vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+	vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+	vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116)
+);
+const AGX_MIN_EV = -12.47393;
+const AGX_MAX_EV = 4.026069;
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+	var v = AGX_INSET * color;
+	v = clamp(log2(max(v, vec3<f32>(1e-10))), vec3<f32>(AGX_MIN_EV), vec3<f32>(AGX_MAX_EV));
+	v = (v - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+	//6th order polynomial approximation of the contrast curve
+	let x2 = v * v;
+	let x4 = x2 * x2;
+	v = 15.5 * x4 * x2 - 40.14 * x4 * v + 31.96 * x4 - 6.868 * x2 * v + 0.4298 * x2 + 0.1191 * v - 0.00232;
+	v = AGX_OUTSET * v;
+	//the curve ends in display encoding, back to linear
+	return pow(max(v, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+//John Hable's Uncharted 2 curve
+fn hable(x: vec3<f32>) -> vec3<f32> {
+	let a = 0.15;
+	let b = 0.50;
+	let c = 0.10;
+	let d = 0.20;
+	let e = 0.02;
+	let f = 0.30;
+	return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+fn filmic(color: vec3<f32>) -> vec3<f32> {
+	let white_point = 11.2;
+	return hable(color * 2.0) / hable(vec3<f32>(white_point));
+}
+
+@fragment
+fn fs_tonemap(in: VertexOutput) -> @location(0) vec4<f32> {
+	var exposure = tonemap.exposure_scale;
+	if (tonemap.auto_exposure != 0u) {
+		let adapted_luminance = textureLoad(t_exposure, vec2<i32>(0, 0), 0).r;
+		exposure *= tonemap.key / max(adapted_luminance, 0.0001);
+	}
+	let color = max(textureSample(t_source, s_source, in.uv).rgb * exposure, vec3<f32>(0.0));
+	var mapped = color;
+	switch tonemap.operator_index {
+		case 1u: {
+			mapped = reinhard(color);
+		}
+		case 2u: {
+			mapped = aces(color);
+		}
+		case 3u: {
+			mapped = agx(color);
+		}
+		case 4u: {
+			mapped = filmic(color);
+		}
+		default: {}
+	}
+	return vec4<f32>(clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
@@ -0,0 +1,82 @@
+//Shared part of every scene transition (see nocmp::transition).
+//A transition module is this file plus a function
+//  fn transition(uv: vec2<f32>, progress: f32) -> vec4<f32>
+//that can use from_color, to_color and mask_value, progress goes from 0 (all outgoing) to 1 (all incoming).
+
+struct TransitionUniforms {
+	progress: f32,
+	softness: f32,
+	angle: f32,
+	time: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> transition_uniforms: TransitionUniforms;
+
+@group(1) @binding(0)
+var t_from: texture_2d<f32>;
+@group(1) @binding(1)
+var s_from: sampler;
+@group(1) @binding(2)
+var t_to: texture_2d<f32>;
+@group(1) @binding(3)
+var s_to: sampler;
+@group(1) @binding(4)
+var t_mask: texture_2d<f32>;
+@group(1) @binding(5)
+var s_mask: sampler;
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+	var out: VertexOutput;
+	//one big triangle covering the screen, (0,0) (2,0) (0,2) in uv space
+	let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+	out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	out.uv = uv;
+	return out;
+}
+
+fn from_color(uv: vec2<f32>) -> vec4<f32> {
+	return textureSample(t_from, s_from, uv);
+}
+
+fn to_color(uv: vec2<f32>) -> vec4<f32> {
+	return textureSample(t_to, s_to, uv);
+}
+
+fn mask_value(uv: vec2<f32>) -> f32 {
+	return dot(textureSample(t_mask, s_mask, uv).rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn crossfade(uv: vec2<f32>, progress: f32) -> vec4<f32> {
+	return mix(from_color(uv), to_color(uv), progress);
+}
+
+//the incoming scene sweeps in along angle (0 is left to right)
+fn wipe(uv: vec2<f32>, progress: f32) -> vec4<f32> {
+	let softness = max(transition_uniforms.softness, 0.0001);
+	let direction = vec2<f32>(cos(transition_uniforms.angle), sin(transition_uniforms.angle));
+	let extent = 0.5 * (abs(direction.x) + abs(direction.y));
+	let along = (dot(uv - vec2<f32>(0.5), direction) + extent) / (2.0 * extent);
+	let edge = progress * (1.0 + softness);
+	let keep_from = smoothstep(edge - softness, edge, along);
+	return mix(to_color(uv), from_color(uv), keep_from);
+}
+
+//dark parts of the mask switch first, bright parts last
+fn mask_dissolve(uv: vec2<f32>, progress: f32) -> vec4<f32> {
+	let softness = max(transition_uniforms.softness, 0.0001);
+	let edge = progress * (1.0 + softness);
+	let keep_from = smoothstep(edge - softness, edge, mask_value(uv));
+	return mix(to_color(uv), from_color(uv), keep_from);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	return transition(in.uv, clamp(transition_uniforms.progress, 0.0, 1.0));
+}
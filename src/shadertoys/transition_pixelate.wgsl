@@ -0,0 +1,12 @@
+//Custom scene transition, added with TransitionRenderer::add_custom after transition.wgsl.
+//The outgoing picture breaks up into blocks that grow until halfway, switches to the incoming one
+//and the blocks shrink back to pixels, made for the hard edged flipbook frames.
+
+const MAX_BLOCK_SIZE: f32 = 48.0;
+
+fn transition(uv: vec2<f32>, progress: f32) -> vec4<f32> {
+	let size = vec2<f32>(textureDimensions(t_from));
+	let block = max(1.0, floor((1.0 - abs(progress * 2.0 - 1.0)) * MAX_BLOCK_SIZE));
+	let block_uv = (floor(uv * size / block) + 0.5) * block / size;
+	return mix(from_color(block_uv), to_color(block_uv), smoothstep(0.45, 0.55, progress));
+}